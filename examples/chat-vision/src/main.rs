@@ -1,8 +1,20 @@
 use futures_util::StreamExt;
-use naori_ai::{Message, NaoriAI};
+use naori_ai::{AIRequestError, ChatStreamItem, Message, ModelFilter, NaoriAI};
 use std::io::{self, Write};
 use std::env;
 
+/// The streamed item, `None` for a chunk that couldn't be parsed, or the error that ended the stream
+fn stream_item(item: Result<ChatStreamItem, AIRequestError>) -> Result<Option<ChatStreamItem>, Box<dyn std::error::Error>> {
+    match item {
+        Ok(item) => Ok(Some(item)),
+        Err(e) if !e.is_fatal() => {
+            eprintln!("\nSkipping chunk: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(format!("Stream error: {}", e).into()),
+    }
+}
+
 fn get_api_key(env_var: &str, provider_name: &str) -> Result<String, Box<dyn std::error::Error>> {
     match std::env::var(env_var) {
         Ok(key) => {
@@ -34,13 +46,26 @@ fn get_user_choice(prompt: &str) -> Result<usize, Box<dyn std::error::Error>> {
     input.trim().parse().map_err(|_| "Invalid number".into())
 }
 
+// Local server by default; OLLAMA_HOST and OLLAMA_API_KEY select Ollama cloud or an authenticated server
+fn ollama_endpoint() -> String {
+    env::var("OLLAMA_HOST").unwrap_or_else(|_| "http://localhost:11434".to_string())
+}
+
+fn ollama_client(model: String) -> NaoriAI {
+    let endpoint = ollama_endpoint();
+    match env::var("OLLAMA_API_KEY") {
+        Ok(api_key) => NaoriAI::ollama_with_key(endpoint, model, api_key),
+        Err(_) => NaoriAI::ollama(endpoint, model),
+    }
+}
+
 async fn select_ollama_model() -> Result<NaoriAI, Box<dyn std::error::Error>> {
     println!("\nConnecting to Ollama...");
-    let temp_client = NaoriAI::ollama("http://localhost:11434".to_string(), "temp".to_string());
+    let temp_client = ollama_client("temp".to_string());
     
     let models = temp_client.list_local_models().await.map_err(|e| {
         println!("Failed to connect to Ollama: {}", e);
-        println!("Make sure Ollama is running on http://localhost:11434");
+        println!("Make sure Ollama is running on {} (set OLLAMA_HOST to use another server)", ollama_endpoint());
         e
     })?;
 
@@ -49,7 +74,7 @@ async fn select_ollama_model() -> Result<NaoriAI, Box<dyn std::error::Error>> {
         return Err("No models available".into());
     }
 
-    println!("\nAvailable models (all models support vision):");
+    println!("\nAvailable models:");
     for (i, model) in models.iter().enumerate() {
         println!("{}. {} ({:.1} GB)", i + 1, model.name, model.size as f64 / 1_073_741_824.0);
     }
@@ -62,15 +87,17 @@ async fn select_ollama_model() -> Result<NaoriAI, Box<dyn std::error::Error>> {
     let selected_model = &models[choice - 1];
     println!("\nSelected: {}", selected_model.name);
 
-    Ok(NaoriAI::ollama("http://localhost:11434".to_string(), selected_model.name.clone()))
+    let client = ollama_client(selected_model.name.clone());
+    if !client.supports_vision().await.unwrap_or(true) {
+        println!("Warning: {} doesn't accept images, pick a vision model such as llava or gemma3", selected_model.name);
+    }
+    Ok(client)
 }
 
 async fn select_cloud_vision_model<F>(
     provider_name: &str,
     env_var: &str,
     constructor: F,
-    vision_filter: fn(&naori_ai::core::MonoModel) -> bool,
-    fallback_filter: Option<fn(&naori_ai::core::MonoModel) -> bool>,
 ) -> Result<NaoriAI, Box<dyn std::error::Error>>
 where
     F: Fn(String, String) -> NaoriAI,
@@ -90,20 +117,21 @@ where
         return Err("No models available".into());
     }
 
-    // First try vision-specific filter
-    let vision_models: Vec<_> = models.iter().filter(|m| vision_filter(m)).cloned().collect();
+    // Capability metadata where the provider reports it, model name heuristics otherwise.
+    // OpenRouter's "custom" entry lets the user type any model id
+    let vision_models: Vec<_> = models
+        .iter()
+        .filter(|m| m.is_vision_capable() || m.id == "custom")
+        .cloned()
+        .collect();
 
     let (filtered_models, model_type) = if vision_models.is_empty() {
-        if let Some(fallback) = fallback_filter {
-            let fallback_models: Vec<_> = models.into_iter().filter(fallback).collect();
-            if fallback_models.is_empty() {
-                return Err(format!("No suitable {} models available", provider_name).into());
-            }
-            println!("No vision-specific models found, showing all suitable models:");
-            (fallback_models, "suitable")
-        } else {
-            return Err(format!("No vision-capable {} models available", provider_name).into());
+        let fallback_models: Vec<_> = models.iter().filter(ModelFilter::new().chat().predicate()).cloned().collect();
+        if fallback_models.is_empty() {
+            return Err(format!("No suitable {} models available", provider_name).into());
         }
+        println!("No vision-specific models found, showing all suitable models:");
+        (fallback_models, "suitable")
     } else {
         (vision_models, "vision")
     };
@@ -154,32 +182,9 @@ async fn select_provider() -> Result<NaoriAI, Box<dyn std::error::Error>> {
 
     match choice {
         1 => select_ollama_model().await,
-        2 => {
-            // All Anthropic models support vision
-            let anthropic_filter = |_: &naori_ai::core::MonoModel| true;
-            select_cloud_vision_model("Anthropic", "ANTHROPIC_API_KEY", NaoriAI::anthropic, anthropic_filter, None).await
-        }
-        3 => {
-            // OpenAI vision models: GPT-4 with vision or GPT-4o
-            let vision_filter = |m: &naori_ai::core::MonoModel| {
-                m.id.contains("gpt-4") && (m.id.contains("vision") || m.id.contains("gpt-4o"))
-            };
-            let fallback_filter = |m: &naori_ai::core::MonoModel| {
-                m.id.contains("gpt-4") || m.id.contains("o1")
-            };
-            select_cloud_vision_model("OpenAI", "OPENAI_API_KEY", NaoriAI::openai, vision_filter, Some(fallback_filter)).await
-        }
-        4 => {
-            // OpenRouter vision models: GPT-4 vision, Claude, Gemini
-            let vision_filter = |m: &naori_ai::core::MonoModel| {
-                let id_lower = m.id.to_lowercase();
-                (id_lower.contains("gpt-4") && (id_lower.contains("vision") || id_lower.contains("gpt-4o"))) ||
-                id_lower.contains("claude") ||
-                id_lower.contains("gemini") ||
-                m.id == "custom"
-            };
-            select_cloud_vision_model("OpenRouter", "OPENROUTER_API_KEY", NaoriAI::openrouter, vision_filter, None).await
-        }
+        2 => select_cloud_vision_model("Anthropic", "ANTHROPIC_API_KEY", NaoriAI::anthropic).await,
+        3 => select_cloud_vision_model("OpenAI", "OPENAI_API_KEY", NaoriAI::openai).await,
+        4 => select_cloud_vision_model("OpenRouter", "OPENROUTER_API_KEY", NaoriAI::openrouter).await,
         _ => {
             println!("Invalid choice. Exiting.");
             Err("Invalid provider selection".into())
@@ -214,7 +219,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             role: "user".to_string(),
             content: "What do you see in this image?".to_string(),
             images: Some(vec![encoded_image]),
-            tool_calls: None,
+            ..Default::default()
         }
     ];
 
@@ -229,7 +234,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut final_usage = None;
 
     while let Some(item) = stream.next().await {
-        let item = item.map_err(|e| format!("Stream error: {}", e))?;
+        let Some(item) = stream_item(item)? else { continue };
         
         if !item.content.is_empty() {
             print!("{}", item.content);
@@ -238,7 +243,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
         
         if let Some(tc) = item.tool_calls {
-            tool_calls = Some(tc);
+            tool_calls.get_or_insert_with(Vec::new).extend(tc);
         }
 
         if let Some(usage) = item.usage {
@@ -263,8 +268,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     messages.push(Message {
         role: "assistant".to_string(),
         content: full_response,
-        images: None,
         tool_calls: tool_calls.clone(),
+        ..Default::default()
     });
 
     // Handle tool calls if any
@@ -278,7 +283,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let mut tool_stream = client.send_chat_request(&messages).await?;
         let mut final_response = String::new();
         while let Some(item) = tool_stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
+            let Some(item) = stream_item(item)? else { continue };
             if !item.content.is_empty() {
                 print!("{}", item.content);
                 io::stdout().flush()?;
@@ -293,8 +298,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         messages.push(Message {
             role: "assistant".to_string(),
             content: final_response,
-            images: None,
-            tool_calls: None,
+            ..Default::default()
         });
     }
 
@@ -319,8 +323,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         messages.push(Message {
             role: "user".to_string(),
             content: input.to_string(),
-            images: None,
-            tool_calls: None,
+            ..Default::default()
         });
 
         print!("{}: ", client.model());
@@ -331,7 +334,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let mut tool_calls = None;
 
         while let Some(item) = stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
+            let Some(item) = stream_item(item)? else { continue };
             
             if !item.content.is_empty() {
                 print!("{}", item.content);
@@ -340,7 +343,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             
             if let Some(tc) = item.tool_calls {
-                tool_calls = Some(tc);
+                tool_calls.get_or_insert_with(Vec::new).extend(tc);
             }
             
             if item.done {
@@ -352,8 +355,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         messages.push(Message {
             role: "assistant".to_string(),
             content: full_response,
-            images: None,
             tool_calls: tool_calls.clone(),
+            ..Default::default()
         });
 
         // Handle tool calls if any
@@ -367,7 +370,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let mut tool_stream = client.send_chat_request(&messages).await?;
             let mut final_response = String::new(); 
             while let Some(item) = tool_stream.next().await {
-                let item = item.map_err(|e| format!("Stream error: {}", e))?;
+                let Some(item) = stream_item(item)? else { continue };
                 if !item.content.is_empty() {
                     print!("{}", item.content);
                     io::stdout().flush()?;
@@ -382,8 +385,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             messages.push(Message {
                 role: "assistant".to_string(),
                 content: final_response,
-                images: None,
-                tool_calls: None,
+                ..Default::default()
             });
         }
 
@@ -1,7 +1,8 @@
 use futures_util::StreamExt;
-use naori_ai::{Message, NaoriAI};
+use naori_ai::{AIRequestError, ChatStreamItem, Message, NaoriAI};
 use naori_ai_macros::tool;
 use std::io::{self, Write};
+use std::env;
 use colored::*;
 
 #[tool]
@@ -71,8 +72,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         messages.push(Message {
             role: "user".to_string(),
             content: input.to_string(),
-            images: None,
-            tool_calls: None,
+            ..Default::default()
         });
 
         print!("{}: ", client.model());
@@ -84,7 +84,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let mut final_usage = None;
 
         while let Some(item) = stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
+            let Some(item) = stream_item(item)? else { continue };
             
             if !item.content.is_empty() {
                 print!("{}", item.content);
@@ -93,7 +93,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
             
             if let Some(tc) = item.tool_calls {
-                tool_calls = Some(tc);
+                tool_calls.get_or_insert_with(Vec::new).extend(tc);
             }
 
             if let Some(usage) = item.usage {
@@ -119,8 +119,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         messages.push(Message {
             role: "assistant".to_string(),
             content: full_response,
-            images: None,
             tool_calls: tool_calls.clone(), // Include tool calls in the conversation history
+            ..Default::default()
         });
 
         // Handle tool calls
@@ -158,7 +158,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let mut final_response = String::new();
             let mut tool_usage = None;
             while let Some(item) = tool_stream.next().await {
-                let item = item.map_err(|e| format!("Stream error: {}", e))?;
+                let Some(item) = stream_item(item)? else { continue };
                 if !item.content.is_empty() {
                     print!("{}", item.content);
                     io::stdout().flush()?;
@@ -186,8 +186,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             messages.push(Message {
                 role: "assistant".to_string(),
                 content: final_response,
-                images: None,
-                tool_calls: None,
+                ..Default::default()
             });
         }
 
@@ -197,6 +196,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The streamed item, `None` for a chunk that couldn't be parsed, or the error that ended the stream
+fn stream_item(item: Result<ChatStreamItem, AIRequestError>) -> Result<Option<ChatStreamItem>, Box<dyn std::error::Error>> {
+    match item {
+        Ok(item) => Ok(Some(item)),
+        Err(e) if !e.is_fatal() => {
+            eprintln!("\nSkipping chunk: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(format!("Stream error: {}", e).into()),
+    }
+}
+
 fn get_api_key(env_var: &str, provider_name: &str) -> Result<String, Box<dyn std::error::Error>> {
     match std::env::var(env_var) {
         Ok(key) => {
@@ -228,13 +239,26 @@ fn get_user_choice(prompt: &str) -> Result<usize, Box<dyn std::error::Error>> {
     input.trim().parse().map_err(|_| "Invalid number".into())
 }
 
+// Local server by default; OLLAMA_HOST and OLLAMA_API_KEY select Ollama cloud or an authenticated server
+fn ollama_endpoint() -> String {
+    env::var("OLLAMA_HOST").unwrap_or_else(|_| "http://localhost:11434".to_string())
+}
+
+fn ollama_client(model: String) -> NaoriAI {
+    let endpoint = ollama_endpoint();
+    match env::var("OLLAMA_API_KEY") {
+        Ok(api_key) => NaoriAI::ollama_with_key(endpoint, model, api_key),
+        Err(_) => NaoriAI::ollama(endpoint, model),
+    }
+}
+
 async fn select_ollama_model() -> Result<NaoriAI, Box<dyn std::error::Error>> {
     println!("\nConnecting to Ollama...");
-    let temp_client = NaoriAI::ollama("http://localhost:11434".to_string(), "temp".to_string());
+    let temp_client = ollama_client("temp".to_string());
     
     let models = temp_client.list_local_models().await.map_err(|e| {
         println!("Failed to connect to Ollama: {}", e);
-        println!("Make sure Ollama is running on http://localhost:11434");
+        println!("Make sure Ollama is running on {} (set OLLAMA_HOST to use another server)", ollama_endpoint());
         e
     })?;
 
@@ -256,7 +280,7 @@ async fn select_ollama_model() -> Result<NaoriAI, Box<dyn std::error::Error>> {
     let selected_model = &models[choice - 1];
     println!("\nSelected: {}", selected_model.name);
 
-    Ok(NaoriAI::ollama("http://localhost:11434".to_string(), selected_model.name.clone()))
+    Ok(ollama_client(selected_model.name.clone()))
 }
 
 async fn select_cloud_model<F>(
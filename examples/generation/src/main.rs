@@ -1,5 +1,5 @@
 use futures_util::StreamExt;
-use naori_ai::NaoriAI;
+use naori_ai::{AIRequestError, ChatStreamItem, NaoriAI};
 use std::io::{self, Write};
 use std::env;
 
@@ -39,12 +39,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("{}: ", client.model());
         io::stdout().flush()?;
 
-        let mut stream = client.generate_stream(&prompt).await?;
+        let mut stream = client.generate_stream_items(&prompt).await?;
         let mut full_response = String::new();
         let mut final_usage = None;
 
         while let Some(item) = stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
+            let Some(item) = stream_item(item)? else { continue };
             
             if !item.content.is_empty() {
                 print!("{}", item.content);
@@ -83,6 +83,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// The streamed item, `None` for a chunk that couldn't be parsed, or the error that ended the stream
+fn stream_item(item: Result<ChatStreamItem, AIRequestError>) -> Result<Option<ChatStreamItem>, Box<dyn std::error::Error>> {
+    match item {
+        Ok(item) => Ok(Some(item)),
+        Err(e) if !e.is_fatal() => {
+            eprintln!("\nSkipping chunk: {}", e);
+            Ok(None)
+        }
+        Err(e) => Err(format!("Stream error: {}", e).into()),
+    }
+}
+
 fn get_api_key(env_var: &str, provider_name: &str) -> Result<String, Box<dyn std::error::Error>> {
     match std::env::var(env_var) {
         Ok(key) => {
@@ -1,11 +1,16 @@
 extern crate proc_macro;
 
+use std::collections::HashMap;
+
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
 use serde_json::json;
-use syn::{ItemFn, Pat, PatType, Type, parse_macro_input};
+use syn::{ItemFn, Pat, PatType, ReturnType, Type, parse_macro_input};
 
 fn rust_type_to_json_type(ty: &Type) -> &'static str {
+    if generic_inner_type(ty, "Vec").is_some() {
+        return "array";
+    }
     if let Type::Path(type_path) = ty {
         if type_path.path.is_ident("String") {
             return "string";
@@ -23,6 +28,52 @@ fn rust_type_to_json_type(ty: &Type) -> &'static str {
     "string"
 }
 
+/// JSON schema for a parameter type, recursing into `Vec<T>` for the array items
+fn rust_type_to_json_schema(ty: &Type) -> serde_json::Value {
+    if let Some(inner) = generic_inner_type(ty, "Vec") {
+        return json!({
+            "type": "array",
+            "items": rust_type_to_json_schema(inner)
+        });
+    }
+    json!({ "type": rust_type_to_json_type(ty) })
+}
+
+/// Type argument of a single-parameter generic like `Option<T>` or `Vec<T>`
+fn generic_inner_type<'a>(ty: &'a Type, wrapper: &str) -> Option<&'a Type> {
+    if let Type::Path(type_path) = ty
+        && let Some(segment) = type_path.path.segments.last()
+        && segment.ident == wrapper
+        && let syn::PathArguments::AngleBracketed(generics) = &segment.arguments
+        && let Some(syn::GenericArgument::Type(inner)) = generics.args.first()
+    {
+        return Some(inner);
+    }
+    None
+}
+
+/// Name and description of a `@param name description` doc line. The tag must be followed by
+/// whitespace, so words like `@params` stay part of the description
+fn parse_param_doc(line: &str) -> Option<(&str, &str)> {
+    let param_doc = line.strip_prefix("@param")?;
+    if !param_doc.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let param_doc = param_doc.trim();
+    let (name, text) = param_doc.split_once(char::is_whitespace).unwrap_or((param_doc, ""));
+    Some((name, text.trim()))
+}
+
+fn returns_result(output: &ReturnType) -> bool {
+    if let ReturnType::Type(_, ty) = output
+        && let Type::Path(type_path) = &**ty
+        && let Some(segment) = type_path.path.segments.last()
+    {
+        return segment.ident == "Result";
+    }
+    false
+}
+
 #[proc_macro_attribute]
 pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let func = parse_macro_input!(item as ItemFn);
@@ -31,16 +82,22 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let tool_func_name = format_ident!("{}_tool", func_name);
 
     let mut description = String::new();
+    // Doc lines of the form `@param name description` document a single parameter
+    let mut param_descriptions = HashMap::new();
     for attr in &func.attrs {
-        if attr.path().is_ident("doc") {
-            if let Ok(name_value) = attr.meta.require_name_value() {
-                if let syn::Expr::Lit(expr_lit) = &name_value.value {
-                    if let syn::Lit::Str(lit_str) = &expr_lit.lit {
-                        description.push_str(&lit_str.value().trim());
-                        description.push(' ');
-                    }
-                }
+        if attr.path().is_ident("doc")
+            && let Ok(name_value) = attr.meta.require_name_value()
+            && let syn::Expr::Lit(expr_lit) = &name_value.value
+            && let syn::Lit::Str(lit_str) = &expr_lit.lit
+        {
+            let line = lit_str.value();
+            let line = line.trim();
+            if let Some((name, text)) = parse_param_doc(line) {
+                param_descriptions.insert(name.to_string(), text.to_string());
+                continue;
             }
+            description.push_str(line);
+            description.push(' ');
         }
     }
     let description = description.trim().to_string();
@@ -48,27 +105,49 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     let mut params_properties = serde_json::Map::new();
     let mut required_params = Vec::new();
     let mut arg_names = Vec::new();
-    let mut arg_names_str = Vec::new();
-    let mut arg_types = Vec::new();
+    let mut arg_bindings = Vec::new();
 
     for input in &func.sig.inputs {
-        if let syn::FnArg::Typed(PatType { pat, ty, .. }) = input {
-            if let Pat::Ident(pat_ident) = &**pat {
-                let arg_name = pat_ident.ident.to_string();
-                let json_type = rust_type_to_json_type(ty);
-
-                params_properties.insert(
-                    arg_name.clone(),
-                    json!({
-                        "type": json_type,
-                        "description": ""
-                    }),
-                );
+        if let syn::FnArg::Typed(PatType { pat, ty, .. }) = input
+            && let Pat::Ident(pat_ident) = &**pat
+        {
+            let arg_name = pat_ident.ident.to_string();
+            // Option<T> parameters are not required and use the schema of T
+            let optional_inner = generic_inner_type(ty, "Option");
+            let value_type = optional_inner.unwrap_or(ty);
+            let json_type = rust_type_to_json_type(value_type);
+            let param_description = param_descriptions.get(&arg_name).cloned().unwrap_or_default();
+
+            let mut schema = rust_type_to_json_schema(value_type);
+            schema["description"] = json!(param_description);
+            params_properties.insert(arg_name.clone(), schema);
+
+            // Bad arguments from the model become a tool error instead of a panic
+            let ident = &pat_ident.ident;
+            if optional_inner.is_some() {
+                // Missing or null optional arguments become None
+                arg_bindings.push(quote! {
+                    let #ident: #ty = args
+                        .get(#arg_name)
+                        .filter(|value| !value.is_null())
+                        .map(|value| serde_json::from_value(value.clone()))
+                        .transpose()
+                        .map_err(|e| {
+                            format!("invalid argument '{}': expected {} ({})", #arg_name, #json_type, e)
+                        })?;
+                });
+            } else {
                 required_params.push(arg_name.clone());
-                arg_names.push(pat_ident.ident.clone());
-                arg_names_str.push(arg_name.clone());
-                arg_types.push(ty.clone());
+                arg_bindings.push(quote! {
+                    let #ident: #ty = match args.get(#arg_name) {
+                        Some(value) => serde_json::from_value(value.clone()).map_err(|e| {
+                            format!("invalid argument '{}': expected {} ({})", #arg_name, #json_type, e)
+                        })?,
+                        None => return Err(format!("missing required argument '{}'", #arg_name)),
+                    };
+                });
             }
+            arg_names.push(ident.clone());
         }
     }
 
@@ -79,6 +158,41 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
     })
     .to_string();
 
+    let is_async = func.sig.asyncness.is_some();
+    let call = if is_async {
+        quote! { #func_name(#(#arg_names),*).await }
+    } else {
+        quote! { #func_name(#(#arg_names),*) }
+    };
+
+    // Result-returning tools pass their error back to the model instead of a plain output
+    let call_expr = if returns_result(&func.sig.output) {
+        quote! {
+            #call
+                .map(|output| output.to_string())
+                .map_err(|e| e.to_string())
+        }
+    } else {
+        quote! { Ok(#call.to_string()) }
+    };
+
+    // Async tools are awaited by handle_tool_calls, sync ones run on the blocking thread pool
+    let function = if is_async {
+        quote! {
+            naori_ai::ToolFunction::Async(std::sync::Arc::new(|args| Box::pin(async move {
+                #(#arg_bindings)*
+                #call_expr
+            })))
+        }
+    } else {
+        quote! {
+            naori_ai::ToolFunction::Sync(std::sync::Arc::new(|args| {
+                #(#arg_bindings)*
+                #call_expr
+            }))
+        }
+    };
+
     let expanded = quote! {
         pub fn #tool_func_name() -> naori_ai::Tool {
             #func
@@ -87,13 +201,32 @@ pub fn tool(_attr: TokenStream, item: TokenStream) -> TokenStream {
                 name: #func_name_str.to_string(),
                 description: #description.to_string(),
                 parameters: serde_json::from_str(#parameters_json).unwrap(),
-                function: Box::new(|args| {
-                    #(let #arg_names: #arg_types = serde_json::from_value(args[#arg_names_str].clone()).unwrap();)*
-                    #func_name(#(#arg_names),*).to_string()
-                }),
+                function: #function,
             }
         }
     };
 
     TokenStream::from(expanded)
 }
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn vec_parameters_describe_their_items() {
+        let ty: Type = parse_quote!(Vec<i64>);
+        assert_eq!(rust_type_to_json_schema(&ty), json!({"type": "array", "items": {"type": "integer"}}));
+
+        let ty: Type = parse_quote!(Vec<Vec<String>>);
+        assert_eq!(
+            rust_type_to_json_schema(&ty),
+            json!({"type": "array", "items": {"type": "array", "items": {"type": "string"}}})
+        );
+
+        let ty: Type = parse_quote!(f64);
+        assert_eq!(rust_type_to_json_schema(&ty), json!({"type": "number"}));
+    }
+}
@@ -1,25 +1,135 @@
 use std::fmt;
+use std::time::Duration;
+
+use crate::core::UsageTotals;
 
 #[derive(Debug)]
 pub enum AIRequestError {
+    /// The API key was missing, invalid, or lacks access
+    Auth { message: String },
+    /// Too many requests; `retry_after` is taken from the response when provided
+    RateLimited { retry_after: Option<Duration> },
     Network(reqwest::Error),
-    Json(serde_json::Error),
+    /// A response or stream event could not be parsed
+    Decode(String),
+    /// Any other error status returned by the provider
+    Api { status: u16, message: String },
+    /// The operation isn't available for this provider
+    Unsupported(String),
+    ModelNotFound { model: String },
+    /// An image the provider won't accept, named by its path (or `image data` for raw bytes)
+    InvalidImage { name: String, message: String },
+    /// A structured response that isn't valid JSON or doesn't match its schema, with the raw text
+    InvalidJson { message: String, raw: String },
+    /// The usage tracker's limit was reached, so the request wasn't sent
+    BudgetExceeded { totals: UsageTotals },
     IO(std::io::Error),
     Other(String),
 }
 
+impl AIRequestError {
+    /// Whether a stream yielding this error is unusable. Decode errors only mean one chunk or event
+    /// couldn't be parsed and the stream continues after them, so robust consumers can skip them;
+    /// transport, API and provider errors end the stream
+    pub fn is_fatal(&self) -> bool {
+        !matches!(self, AIRequestError::Decode(_))
+    }
+
+    /// Whether sending the same request again may succeed: rate limits, network failures and
+    /// server side (5xx) errors
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            AIRequestError::RateLimited { .. } | AIRequestError::Network(_) => true,
+            AIRequestError::Api { status, .. } => *status >= 500,
+            _ => false,
+        }
+    }
+
+    /// Classify an unsuccessful HTTP response by its status code
+    pub(crate) async fn from_response(response: reqwest::Response) -> Self {
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let message = match response.text().await {
+            Ok(text) => text,
+            Err(e) => return AIRequestError::Network(e),
+        };
+
+        match status.as_u16() {
+            401 | 403 => AIRequestError::Auth { message },
+            429 => AIRequestError::RateLimited { retry_after },
+            status => AIRequestError::Api { status, message },
+        }
+    }
+
+    /// Like `from_response` for a request to `model`, turning the providers' unknown model errors
+    /// into `ModelNotFound`: Ollama's `model "x" not found`, OpenAI's `model_not_found` code and
+    /// Anthropic's `not_found_error` naming the model
+    pub(crate) async fn from_model_response(response: reqwest::Response, model: &str) -> Self {
+        match Self::from_response(response).await {
+            AIRequestError::Api { status: 400 | 404, message } if is_model_not_found(&message) => {
+                AIRequestError::ModelNotFound { model: model.to_string() }
+            }
+            error => error,
+        }
+    }
+}
+
+fn is_model_not_found(body: &str) -> bool {
+    let Ok(json) = serde_json::from_str::<serde_json::Value>(body) else {
+        let body = body.to_lowercase();
+        return body.contains("model") && body.contains("not found");
+    };
+    let error = &json["error"];
+    match error.as_str() {
+        Some(message) => message.contains("model") && message.contains("not found"),
+        None => {
+            error["code"] == "model_not_found"
+                || (error["type"] == "not_found_error"
+                    && error["message"].as_str().is_some_and(|message| message.starts_with("model:")))
+        }
+    }
+}
+
 impl fmt::Display for AIRequestError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
+            AIRequestError::Auth { message } => write!(f, "Authentication failed: {}", message),
+            AIRequestError::RateLimited { retry_after: Some(delay) } => {
+                write!(f, "Rate limited, retry after {}s", delay.as_secs())
+            }
+            AIRequestError::RateLimited { retry_after: None } => write!(f, "Rate limited"),
             AIRequestError::Network(e) => write!(f, "Network error: {}", e),
-            AIRequestError::Json(e) => write!(f, "JSON error: {}", e),
+            AIRequestError::Decode(msg) => write!(f, "Decode error: {}", msg),
+            AIRequestError::Api { status, message } => write!(f, "API error ({}): {}", status, message),
+            AIRequestError::Unsupported(msg) => write!(f, "Unsupported: {}", msg),
+            AIRequestError::ModelNotFound { model } => write!(f, "Model not found: {}", model),
+            AIRequestError::InvalidImage { name, message } => write!(f, "Invalid image {}: {}", name, message),
+            AIRequestError::InvalidJson { message, .. } => write!(f, "Invalid JSON response: {}", message),
+            AIRequestError::BudgetExceeded { totals } => write!(
+                f,
+                "Usage budget exceeded after {} tokens (${:.4})",
+                totals.total_tokens, totals.cost_usd
+            ),
             AIRequestError::IO(e) => write!(f, "IO error: {}", e),
             AIRequestError::Other(msg) => write!(f, "Error: {}", msg),
         }
     }
 }
 
-impl std::error::Error for AIRequestError {}
+impl std::error::Error for AIRequestError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AIRequestError::Network(e) => Some(e),
+            AIRequestError::IO(e) => Some(e),
+            _ => None,
+        }
+    }
+}
 
 impl From<reqwest::Error> for AIRequestError {
     fn from(err: reqwest::Error) -> Self {
@@ -29,7 +139,7 @@ impl From<reqwest::Error> for AIRequestError {
 
 impl From<serde_json::Error> for AIRequestError {
     fn from(err: serde_json::Error) -> Self {
-        AIRequestError::Json(err)
+        AIRequestError::Decode(err.to_string())
     }
 }
 
@@ -37,4 +147,44 @@ impl From<std::io::Error> for AIRequestError {
     fn from(err: std::io::Error) -> Self {
         AIRequestError::IO(err)
     }
-}
\ No newline at end of file
+}
+
+impl From<String> for AIRequestError {
+    fn from(msg: String) -> Self {
+        AIRequestError::Other(msg)
+    }
+}
+
+impl From<&str> for AIRequestError {
+    fn from(msg: &str) -> Self {
+        AIRequestError::Other(msg.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_not_found_bodies() {
+        // Ollama
+        assert!(is_model_not_found(r#"{"error":"model \"llama9\" not found, try pulling it first"}"#));
+        assert!(is_model_not_found(r#"{"error":"model 'x' not found"}"#));
+        // OpenAI
+        assert!(is_model_not_found(
+            r#"{"error":{"message":"The model `gpt-9` does not exist","type":"invalid_request_error","param":null,"code":"model_not_found"}}"#
+        ));
+        // Anthropic
+        assert!(is_model_not_found(r#"{"type":"error","error":{"type":"not_found_error","message":"model: claude-9"}}"#));
+        // Plain text from a proxy or an OpenAI-compatible server
+        assert!(is_model_not_found("Model gpt-9 not found"));
+    }
+
+    #[test]
+    fn other_not_found_bodies() {
+        assert!(!is_model_not_found(r#"{"error":"file not found"}"#));
+        assert!(!is_model_not_found(r#"{"error":{"message":"Unknown endpoint","type":"invalid_request_error","code":null}}"#));
+        assert!(!is_model_not_found(r#"{"type":"error","error":{"type":"not_found_error","message":"Not found"}}"#));
+        assert!(!is_model_not_found("404 page not found"));
+    }
+}
@@ -1,49 +1,81 @@
 use regex::Regex;
 use crate::core::{Tool, ToolCall, Function};
 
+const DEFAULT_TOOL_TEMPLATE: &str = "\n\nYou have access to the following tools. When you need to use a tool, respond with:\n\n<tool_call>\n{\"function\": {\"name\": \"function_name\", \"arguments\": {\"param1\": \"value1\", \"param2\": \"value2\"}}}\n</tool_call>\n\nAvailable tools:\n\n{tools}When using tools, wrap the JSON in <tool_call></tool_call> tags as shown above. Don't feel obligated to use tool calls if it doesn't make sense to do so or you weren't instructed. Normally you'll want to present your results to the user after making a tool call, as the user doesn't know the result, unless explicitly told otherwise (example: the user wants many consecutive tool calls).\n";
+
+const CODE_FENCE_TOOL_TEMPLATE: &str = "\n\nYou have access to the following tools. To use a tool, reply with a JSON object in a tool_call code block:\n\n```tool_call\n{\"name\": \"function_name\", \"arguments\": {\"param1\": \"value1\"}}\n```\n\nAvailable tools:\n\n{tools}Use one code block per tool call. Only call a tool when it helps answer the user, and present the results to the user afterwards.\n";
+
+/// Prompt and markup used for tool calls on models without native tool support.
+/// Calls are read from between `open_tag` and `close_tag` as JSON, either
+/// `{"function": {"name", "arguments"}}` or `{"name", "arguments"}`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FallbackConfig {
+    /// Tool instructions appended to the system prompt; `{tools}` is replaced with the tool list
+    pub template: String,
+    pub open_tag: String,
+    pub close_tag: String,
+}
+
+impl Default for FallbackConfig {
+    /// `<tool_call>` XML tags
+    fn default() -> Self {
+        Self {
+            template: DEFAULT_TOOL_TEMPLATE.to_string(),
+            open_tag: "<tool_call>".to_string(),
+            close_tag: "</tool_call>".to_string(),
+        }
+    }
+}
+
+impl FallbackConfig {
+    /// JSON in a ```` ```tool_call ```` code fence, which some small models follow more reliably than XML
+    pub fn json_code_fence() -> Self {
+        Self {
+            template: CODE_FENCE_TOOL_TEMPLATE.to_string(),
+            open_tag: "```tool_call".to_string(),
+            close_tag: "```".to_string(),
+        }
+    }
+
+    fn call_regex(&self) -> Option<Regex> {
+        Regex::new(&format!("(?s){}(.*?){}", regex::escape(&self.open_tag), regex::escape(&self.close_tag))).ok()
+    }
+}
+
 pub struct FallbackToolHandler;
 
 impl FallbackToolHandler {
-    pub fn generate_tool_context(tools: &[Tool]) -> String {
-        if tools.is_empty() {
+    pub fn generate_tool_context<'a>(tools: impl IntoIterator<Item = &'a Tool>) -> String {
+        Self::generate_tool_context_with(&FallbackConfig::default(), tools)
+    }
+
+    pub fn generate_tool_context_with<'a>(config: &FallbackConfig, tools: impl IntoIterator<Item = &'a Tool>) -> String {
+        let mut tools = tools.into_iter().peekable();
+        if tools.peek().is_none() {
             return String::new();
         }
 
-        let mut context = String::from("\n\nYou have access to the following tools. When you need to use a tool, respond with:\n\n<tool_call>\n{\"function\": {\"name\": \"function_name\", \"arguments\": {\"param1\": \"value1\", \"param2\": \"value2\"}}}\n</tool_call>\n\nAvailable tools:\n\n");
-        
+        let mut tool_list = String::new();
         for tool in tools {
-            context.push_str(&format!("{}: {}\n", tool.name, tool.description));
-            context.push_str(&format!("Parameters schema: {}\n\n", serde_json::to_string_pretty(&tool.parameters).unwrap_or_default()));
+            tool_list.push_str(&format!("{}: {}\n", tool.name, tool.description));
+            tool_list.push_str(&format!("Parameters schema: {}\n\n", serde_json::to_string_pretty(&tool.parameters).unwrap_or_default()));
         }
-        
-        context.push_str("When using tools, wrap the JSON in <tool_call></tool_call> tags as shown above. Don't feel obligated to use tool calls if it doesn't make sense to do so or you weren't instructed. Normally you'll want to present your results to the user after making a tool call, as the user doesn't know the result, unless explicitly told otherwise (example: the user wants many consecutive tool calls).\n");
-        context
+        config.template.replace("{tools}", &tool_list)
     }
 
     pub fn parse_fallback_tool_calls(content: &str) -> Option<Vec<ToolCall>> {
-        let xml_regex = Regex::new(r"(?s)<tool_call>(.*?)</tool_call>").ok()?;
-        
-        let mut all_tool_calls = Vec::new();
-        
-        for caps in xml_regex.captures_iter(content) {
-            if let Some(json_str) = caps.get(1) {
-                let json_content = json_str.as_str().trim();
-                
-                if let Ok(parsed) = serde_json::from_str::<serde_json::Value>(json_content) {
-                    if let (Some(name), Some(arguments)) = (
-                        parsed.get("function").and_then(|f| f.get("name")).and_then(|n| n.as_str()),
-                        parsed.get("function").and_then(|f| f.get("arguments"))
-                    ) {
-                        all_tool_calls.push(ToolCall {
-                            id: None, // Fallback mode doesn't have tool IDs
-                            function: Function {
-                                name: name.to_string(),
-                                arguments: arguments.clone(),
-                            }
-                        });
-                    }
-                }
-            }
+        Self::parse_fallback_tool_calls_with(&FallbackConfig::default(), content)
+    }
+
+    /// Tool calls between the configured tags, anywhere in `content`. Prose around the tags,
+    /// code fences around or inside them, several calls per response and a final block the
+    /// model didn't close are all tolerated
+    pub fn parse_fallback_tool_calls_with(config: &FallbackConfig, content: &str) -> Option<Vec<ToolCall>> {
+        let (mut all_tool_calls, last_end) = Self::parse_closed_tool_calls_with(config, content);
+
+        // Models often stop generating right before the closing tag
+        if let Some(pos) = content[last_end..].find(config.open_tag.as_str()) {
+            push_tool_calls(&content[last_end + pos + config.open_tag.len()..], &mut all_tool_calls);
         }
         
         if !all_tool_calls.is_empty() {
@@ -53,11 +85,40 @@ impl FallbackToolHandler {
         }
     }
 
+    /// Tool calls in the closed blocks of `content`, with the offset right after the last one.
+    /// Used while streaming, where the text past that offset may still become another call
+    pub fn parse_closed_tool_calls_with(config: &FallbackConfig, content: &str) -> (Vec<ToolCall>, usize) {
+        let Some(call_regex) = config.call_regex() else {
+            return (Vec::new(), 0);
+        };
+
+        let mut tool_calls = Vec::new();
+        let mut last_end = 0;
+        for caps in call_regex.captures_iter(content) {
+            if let Some(json_str) = caps.get(1) {
+                push_tool_calls(json_str.as_str(), &mut tool_calls);
+            }
+            last_end = caps.get(0).map_or(last_end, |m| m.end());
+        }
+        (tool_calls, last_end)
+    }
+
     pub fn process_fallback_response(content: &str) -> (String, Option<Vec<ToolCall>>) {
-        if let Some(tool_calls) = Self::parse_fallback_tool_calls(content) {
-            // Remove the tool call XML from the content
-            let xml_regex = Regex::new(r"(?s)<tool_call>.*?</tool_call>").unwrap();
-            let cleaned_content = xml_regex.replace_all(content, "").trim().to_string();
+        Self::process_fallback_response_with(&FallbackConfig::default(), content)
+    }
+
+    pub fn process_fallback_response_with(config: &FallbackConfig, content: &str) -> (String, Option<Vec<ToolCall>>) {
+        if let Some(tool_calls) = Self::parse_fallback_tool_calls_with(config, content) {
+            // Remove the tool call markup from the content, along with an unclosed last call
+            // and the code fences that wrapped the calls
+            let mut cleaned_content = match config.call_regex() {
+                Some(call_regex) => call_regex.replace_all(content, "").into_owned(),
+                None => content.to_string(),
+            };
+            if let Some(pos) = cleaned_content.find(config.open_tag.as_str()) {
+                cleaned_content.truncate(pos);
+            }
+            let cleaned_content = empty_fence_regex().replace_all(&cleaned_content, "").trim().to_string();
             
             // If cleaned content is empty or very short, indicate tool usage
             let final_content = if cleaned_content.len() < 10 {
@@ -69,10 +130,125 @@ impl FallbackToolHandler {
             (final_content, Some(tool_calls))
         } else {
             // Check for incomplete tool calls and remove them
-            let incomplete_regex = Regex::new(r"<tool_call>\s*$").unwrap();
-            let cleaned_content = incomplete_regex.replace_all(content, "").trim().to_string();
+            let cleaned_content = content.trim_end();
+            let cleaned_content = cleaned_content.strip_suffix(config.open_tag.as_str()).unwrap_or(cleaned_content);
             
-            (cleaned_content, None)
+            (cleaned_content.trim().to_string(), None)
         }
     }
-}
\ No newline at end of file
+}
+
+// Reads one call, or an array of calls, from the text between the tags
+fn push_tool_calls(inner: &str, tool_calls: &mut Vec<ToolCall>) {
+    let Some(parsed) = parse_tool_json(inner) else {
+        return;
+    };
+    let calls = match parsed {
+        serde_json::Value::Array(calls) => calls,
+        call => vec![call],
+    };
+    for parsed in &calls {
+        // Both the nested OpenAI-like shape and a flat one are accepted
+        let call = parsed.get("function").unwrap_or(parsed);
+        if let (Some(name), Some(arguments)) = (
+            call.get("name").and_then(|n| n.as_str()),
+            call.get("arguments"),
+        ) {
+            tool_calls.push(ToolCall {
+                id: None, // Fallback mode doesn't have tool IDs
+                function: Function {
+                    name: name.to_string(),
+                    arguments: arguments.clone(),
+                }
+            });
+        }
+    }
+}
+
+// The JSON inside a tool call block, which models sometimes put in a ```json fence or
+// surround with a few words
+fn parse_tool_json(inner: &str) -> Option<serde_json::Value> {
+    let mut text = inner.trim();
+    if let Some(fenced) = text.strip_prefix("```") {
+        // Skip the language tag on the fence line
+        let body = fenced.find('\n').map_or(fenced, |pos| &fenced[pos + 1..]);
+        text = body.trim_end().strip_suffix("```").unwrap_or(body).trim();
+    }
+    if let Ok(parsed) = serde_json::from_str(text) {
+        return Some(parsed);
+    }
+
+    let start = text.find(['{', '['])?;
+    let end = text.rfind(['}', ']'])?;
+    if end <= start {
+        return None;
+    }
+    serde_json::from_str(&text[start..=end]).ok()
+}
+
+// Code fences left empty once the tool calls inside them are removed
+fn empty_fence_regex() -> Regex {
+    Regex::new(r"```[\w-]*\s*```").unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn names(tool_calls: &[ToolCall]) -> Vec<&str> {
+        tool_calls.iter().map(|call| call.function.name.as_str()).collect()
+    }
+
+    #[test]
+    fn fenced_json_inside_tags() {
+        let content = "<tool_call>\n```json\n{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Oslo\"}}\n```\n</tool_call>";
+
+        let tool_calls = FallbackToolHandler::parse_fallback_tool_calls(content).unwrap();
+
+        assert_eq!(names(&tool_calls), ["get_weather"]);
+        assert_eq!(tool_calls[0].function.arguments, json!({"city": "Oslo"}));
+    }
+
+    #[test]
+    fn prose_around_the_json() {
+        let content = "Let me check.\n<tool_call>Calling {\"function\": {\"name\": \"get_time\", \"arguments\": {}}} now</tool_call>\nOne moment.";
+
+        let (text, tool_calls) = FallbackToolHandler::process_fallback_response(content);
+
+        assert_eq!(names(&tool_calls.unwrap()), ["get_time"]);
+        assert_eq!(text, "Let me check.\n\nOne moment.");
+    }
+
+    #[test]
+    fn several_calls_in_one_response() {
+        let content = concat!(
+            "<tool_call>{\"name\": \"get_weather\", \"arguments\": {\"city\": \"Oslo\"}}</tool_call>\n",
+            "<tool_call>[{\"name\": \"get_time\", \"arguments\": {}}, {\"name\": \"get_date\", \"arguments\": {}}]</tool_call>\n",
+            // Cut off before the closing tag
+            "<tool_call>{\"name\": \"get_news\", \"arguments\": {\"topic\": \"rust\"}}",
+        );
+
+        let tool_calls = FallbackToolHandler::parse_fallback_tool_calls(content).unwrap();
+
+        assert_eq!(names(&tool_calls), ["get_weather", "get_time", "get_date", "get_news"]);
+        assert_eq!(tool_calls[3].function.arguments, json!({"topic": "rust"}));
+    }
+
+    #[test]
+    fn code_fence_config() {
+        let config = FallbackConfig::json_code_fence();
+        let content = "Sure.\n```tool_call\n{\"name\": \"get_time\", \"arguments\": {}}\n```";
+
+        let (_, tool_calls) = FallbackToolHandler::process_fallback_response_with(&config, content);
+
+        assert_eq!(names(&tool_calls.unwrap()), ["get_time"]);
+    }
+
+    #[test]
+    fn no_calls_without_valid_json() {
+        assert!(FallbackToolHandler::parse_fallback_tool_calls("<tool_call>not json</tool_call>").is_none());
+        assert!(FallbackToolHandler::parse_fallback_tool_calls("Just an answer.").is_none());
+    }
+}
@@ -0,0 +1,168 @@
+use crate::core::{Message, estimate_message_tokens, estimate_messages_tokens};
+
+/// How `truncate_to_fit` picks the messages to drop
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationStrategy {
+    /// Drop the oldest messages one by one
+    DropOldest,
+    /// Drop the oldest messages, taking an assistant tool call together with its tool results,
+    /// and keep dropping until the history starts with a user message again
+    #[default]
+    DropOldestKeepingPairs,
+}
+
+/// Drop the oldest messages until the estimated prompt size fits in `max_tokens`, returning how
+/// many were dropped. System messages and the current turn (the most recent user message and
+/// everything after it) are always kept, so the result can still exceed `max_tokens` when those
+/// alone are too large.
+///
+/// Sizes come from `estimate_message_tokens`, so leave some headroom below the real context window.
+pub fn truncate_to_fit(messages: &mut Vec<Message>, max_tokens: u32, strategy: TruncationStrategy) -> usize {
+    let last_user = messages.iter().rposition(|msg| msg.role == "user");
+    // Dropping stops at the current turn, so its tool calls are never split from their results
+    let limit = last_user.unwrap_or(messages.len());
+
+    let mut total = estimate_messages_tokens(messages);
+    let mut dropped = vec![false; messages.len()];
+    let mut index = 0;
+    while index < limit {
+        if messages[index].role == "system" {
+            index += 1;
+            continue;
+        }
+        if total <= max_tokens {
+            // A dropped prefix can leave the history opening with an assistant reply or tool result,
+            // which some providers reject, so keeping pairs trims on up to the next user message
+            let realign = strategy == TruncationStrategy::DropOldestKeepingPairs
+                && dropped.contains(&true)
+                && messages[index].role != "user"
+                && last_user.is_some();
+            if !realign {
+                break;
+            }
+        }
+        let end = match strategy {
+            TruncationStrategy::DropOldest => index + 1,
+            TruncationStrategy::DropOldestKeepingPairs => pair_end(messages, index),
+        };
+        for i in index..end {
+            dropped[i] = true;
+            total = total.saturating_sub(estimate_message_tokens(&messages[i]));
+        }
+        index = end;
+    }
+
+    let count = dropped.iter().filter(|&&d| d).count();
+    let mut flags = dropped.into_iter();
+    messages.retain(|_| !flags.next().unwrap_or(false));
+    count
+}
+
+/// End of the group starting at `start`: an assistant message with tool calls owns the tool results after it
+fn pair_end(messages: &[Message], start: usize) -> usize {
+    let mut end = start + 1;
+    if messages[start].tool_calls.as_ref().is_some_and(|calls| !calls.is_empty()) {
+        while end < messages.len() && messages[end].role == "tool" {
+            end += 1;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::core::{Function, ToolCall};
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn tool_call(id: &str) -> Message {
+        Message {
+            role: "assistant".to_string(),
+            tool_calls: Some(vec![ToolCall {
+                id: Some(id.to_string()),
+                function: Function {
+                    name: "search".to_string(),
+                    arguments: json!({"query": "weather"}),
+                },
+            }]),
+            ..Default::default()
+        }
+    }
+
+    fn roles(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|msg| msg.role.as_str()).collect()
+    }
+
+    // Every tool result follows the assistant message that made the call
+    fn tool_results_paired(messages: &[Message]) -> bool {
+        messages.iter().enumerate().filter(|(_, msg)| msg.role == "tool").all(|(index, _)| {
+            messages[..index]
+                .iter()
+                .rev()
+                .find(|msg| msg.role != "tool")
+                .is_some_and(|msg| msg.tool_calls.is_some())
+        })
+    }
+
+    fn history() -> Vec<Message> {
+        vec![
+            message("system", "You are a helpful assistant."),
+            message("user", "What's the weather in Oslo?"),
+            tool_call("call_1"),
+            message("tool", "Sunny, 21 degrees"),
+            message("assistant", "It's sunny and 21 degrees in Oslo."),
+            message("user", "And tomorrow?"),
+            tool_call("call_2"),
+            message("tool", "Rain, 15 degrees"),
+        ]
+    }
+
+    #[test]
+    fn fitting_history_is_untouched() {
+        for strategy in [TruncationStrategy::DropOldest, TruncationStrategy::DropOldestKeepingPairs] {
+            let mut messages = history();
+
+            assert_eq!(truncate_to_fit(&mut messages, 100_000, strategy), 0);
+            assert_eq!(roles(&messages), roles(&history()));
+        }
+    }
+
+    #[test]
+    fn system_messages_and_current_turn_are_kept() {
+        for strategy in [TruncationStrategy::DropOldest, TruncationStrategy::DropOldestKeepingPairs] {
+            let mut messages = history();
+
+            assert_eq!(truncate_to_fit(&mut messages, 0, strategy), 4);
+            assert_eq!(roles(&messages), ["system", "user", "assistant", "tool"]);
+            assert_eq!(messages[1].content, "And tomorrow?");
+            assert!(tool_results_paired(&messages));
+        }
+    }
+
+    #[test]
+    fn keeping_pairs_drops_tool_calls_with_their_results() {
+        // Just enough room once the first question and its tool call are gone
+        let full = estimate_messages_tokens(&history());
+        let budget = full - estimate_message_tokens(&history()[1]) - estimate_message_tokens(&history()[2]);
+
+        let mut messages = history();
+        assert_eq!(truncate_to_fit(&mut messages, budget, TruncationStrategy::DropOldest), 2);
+        assert_eq!(roles(&messages), ["system", "tool", "assistant", "user", "assistant", "tool"]);
+        assert!(!tool_results_paired(&messages));
+
+        // The tool result goes with its call, and the history is realigned to start at a user message
+        let mut messages = history();
+        assert_eq!(truncate_to_fit(&mut messages, budget, TruncationStrategy::DropOldestKeepingPairs), 4);
+        assert_eq!(roles(&messages), ["system", "user", "assistant", "tool"]);
+        assert!(tool_results_paired(&messages));
+    }
+}
@@ -0,0 +1,87 @@
+use base64::{Engine as _, engine::general_purpose};
+
+/// Image formats recognized from their file signature
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    Jpeg,
+    Png,
+    Gif,
+    Webp,
+    Bmp,
+    Tiff,
+}
+
+impl ImageFormat {
+    /// Detect the format from the first bytes of the image
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [0xFF, 0xD8, 0xFF, ..] => Some(ImageFormat::Jpeg),
+            [0x89, b'P', b'N', b'G', ..] => Some(ImageFormat::Png),
+            [b'G', b'I', b'F', b'8', ..] => Some(ImageFormat::Gif),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some(ImageFormat::Webp),
+            [b'B', b'M', ..] => Some(ImageFormat::Bmp),
+            [b'I', b'I', 0x2A, 0x00, ..] | [b'M', b'M', 0x00, 0x2A, ..] => Some(ImageFormat::Tiff),
+            _ => None,
+        }
+    }
+
+    /// Detect the format of base64 encoded image data, decoding only its header
+    pub fn detect_base64(data: &str) -> Option<Self> {
+        // 16 base64 characters decode to the 12 bytes the signatures need
+        let header = data.get(..16)?;
+        let bytes = general_purpose::STANDARD.decode(header).ok()?;
+        Self::detect(&bytes)
+    }
+
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            ImageFormat::Jpeg => "image/jpeg",
+            ImageFormat::Png => "image/png",
+            ImageFormat::Gif => "image/gif",
+            ImageFormat::Webp => "image/webp",
+            ImageFormat::Bmp => "image/bmp",
+            ImageFormat::Tiff => "image/tiff",
+        }
+    }
+}
+
+/// True for GIFs with a looping extension and WebPs with the animation flag set
+pub fn is_animated_image(bytes: &[u8]) -> bool {
+    match ImageFormat::detect(bytes) {
+        Some(ImageFormat::Gif) => bytes.windows(11).any(|window| window == b"NETSCAPE2.0"),
+        // The VP8X chunk follows the RIFF header, its flags byte has bit 1 set for animations
+        Some(ImageFormat::Webp) => bytes.get(12..16) == Some(b"VP8X") && bytes.get(20).is_some_and(|flags| flags & 0x02 != 0),
+        _ => false,
+    }
+}
+
+/// The first frame of an animated GIF or WebP as a PNG, for providers that only accept still images
+#[cfg(feature = "image-resize")]
+pub fn first_frame(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    // Decoding without the animation API yields the first frame
+    let decoded = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+    let mut output = std::io::Cursor::new(Vec::new());
+    decoded.write_to(&mut output, image::ImageFormat::Png).map_err(|e| e.to_string())?;
+    Ok(output.into_inner())
+}
+
+/// Downscale an image so its longest side is at most `max_dimension`, keeping the aspect ratio.
+/// Images already small enough are returned as is; resized JPEGs stay JPEG, everything else becomes PNG
+#[cfg(feature = "image-resize")]
+pub fn resize_image(bytes: &[u8], max_dimension: u32) -> Result<Vec<u8>, String> {
+    use image::ImageFormat as Codec;
+
+    let decoded = image::load_from_memory(bytes).map_err(|e| e.to_string())?;
+    if decoded.width().max(decoded.height()) <= max_dimension {
+        return Ok(bytes.to_vec());
+    }
+
+    let resized = decoded.resize(max_dimension, max_dimension, image::imageops::FilterType::Lanczos3);
+    let codec = match ImageFormat::detect(bytes) {
+        Some(ImageFormat::Jpeg) => Codec::Jpeg,
+        _ => Codec::Png,
+    };
+    let mut output = std::io::Cursor::new(Vec::new());
+    resized.write_to(&mut output, codec).map_err(|e| e.to_string())?;
+    Ok(output.into_inner())
+}
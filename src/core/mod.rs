@@ -2,8 +2,27 @@ pub mod types;
 pub mod tool;
 pub mod error;
 pub mod fallback;
+pub mod usage;
+pub mod options;
+pub mod pricing;
+pub mod redaction;
+pub mod image;
+pub mod tokens;
+pub mod history;
+pub mod http;
+pub mod models;
+pub(crate) mod trace;
 
 pub use types::*;
 pub use tool::*;
 pub use error::*;
-pub use fallback::*;
\ No newline at end of file
+pub use fallback::*;
+pub use usage::*;
+pub use options::*;
+pub use pricing::*;
+pub use redaction::*;
+pub use image::*;
+pub use tokens::*;
+pub use history::*;
+pub use http::*;
+pub use models::*;
\ No newline at end of file
@@ -0,0 +1,124 @@
+use crate::core::MonoModel;
+
+// Name fragments of model families that accept images, for listings without capability metadata
+const VISION_MODEL_HINTS: &[&str] = &[
+    "vision", "llava", "moondream", "minicpm-v", "gemma3", "llama4", "qwen2.5vl", "qwen2.5-vl", "qwen2-vl", "-vl",
+    "mistral-small3.1", "pixtral", "gpt-4o", "gpt-4.1", "gpt-4-turbo", "gpt-5", "o1", "o3", "o4", "claude",
+];
+
+// Name fragments of model families trained for native tool calling
+const TOOL_MODEL_HINTS: &[&str] = &[
+    "gpt-3.5-turbo", "gpt-4", "gpt-5", "o1", "o3", "o4", "claude", "llama3.1", "llama3.2", "llama3.3", "llama4",
+    "qwen2.5", "qwen3", "mistral", "mixtral", "command-r", "firefunction", "hermes", "granite3", "smollm2",
+];
+
+// Completion, embedding and media models listed alongside chat models
+const NON_CHAT_HINTS: &[&str] = &[
+    "embed", "whisper", "tts", "dall-e", "moderation", "davinci", "babbage", "-instruct", "transcribe", "image",
+];
+
+impl MonoModel {
+    /// Whether the model accepts images, from the provider's capability metadata when the listing
+    /// has it (OpenRouter), otherwise guessed from the model name
+    pub fn is_vision_capable(&self) -> bool {
+        match &self.capabilities {
+            Some(capabilities) => capabilities.vision,
+            None => self.is_chat_model() && self.matches_hint(VISION_MODEL_HINTS) && !self.is_legacy_claude(),
+        }
+    }
+
+    /// Whether the model supports native tool calls, from capability metadata when available,
+    /// otherwise guessed from the model name. Other models still get tools through the XML fallback
+    pub fn is_tool_capable(&self) -> bool {
+        match &self.capabilities {
+            Some(capabilities) => capabilities.tools,
+            None => self.is_chat_model() && self.matches_hint(TOOL_MODEL_HINTS) && !self.is_legacy_claude(),
+        }
+    }
+
+    /// False for embedding, speech, image generation and legacy completion models
+    pub fn is_chat_model(&self) -> bool {
+        !self.matches_hint(NON_CHAT_HINTS)
+    }
+
+    // OpenRouter ids carry a vendor prefix, e.g. `openai/gpt-4o`
+    fn base_id(&self) -> String {
+        let id = self.id.to_lowercase();
+        match id.rsplit_once('/') {
+            Some((_, base)) => base.to_string(),
+            None => id,
+        }
+    }
+
+    fn matches_hint(&self, hints: &[&str]) -> bool {
+        let id = self.base_id();
+        hints.iter().any(|hint| id.contains(hint))
+    }
+
+    // Claude 2 and Instant predate vision and tool use
+    fn is_legacy_claude(&self) -> bool {
+        let id = self.base_id();
+        id.starts_with("claude-2") || id.starts_with("claude-instant")
+    }
+}
+
+/// Criteria for picking models out of `get_available_models`, combined with AND:
+/// `models.iter().filter(ModelFilter::new().vision().predicate())`
+#[derive(Debug, Clone, Default)]
+pub struct ModelFilter {
+    vision: bool,
+    tools: bool,
+    chat_only: bool,
+    min_context_length: Option<u32>,
+    id_contains: Option<String>,
+}
+
+impl ModelFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only models that accept images, see `MonoModel::is_vision_capable`
+    pub fn vision(mut self) -> Self {
+        self.vision = true;
+        self
+    }
+
+    /// Only models with native tool calls, see `MonoModel::is_tool_capable`
+    pub fn tools(mut self) -> Self {
+        self.tools = true;
+        self
+    }
+
+    /// Skip embedding, speech, image generation and legacy completion models
+    pub fn chat(mut self) -> Self {
+        self.chat_only = true;
+        self
+    }
+
+    /// Only models reporting a context window of at least `tokens`. Models without a reported
+    /// context length are excluded
+    pub fn min_context_length(mut self, tokens: u32) -> Self {
+        self.min_context_length = Some(tokens);
+        self
+    }
+
+    /// Only models whose id contains `text`, ignoring case
+    pub fn id_contains(mut self, text: impl Into<String>) -> Self {
+        self.id_contains = Some(text.into().to_lowercase());
+        self
+    }
+
+    pub fn matches(&self, model: &MonoModel) -> bool {
+        (!self.vision || model.is_vision_capable())
+            && (!self.tools || model.is_tool_capable())
+            && (!self.chat_only || model.is_chat_model())
+            && self.min_context_length.is_none_or(|min| model.context_length.is_some_and(|length| length >= min))
+            && self.id_contains.as_ref().is_none_or(|text| model.id.to_lowercase().contains(text.as_str()))
+    }
+
+    /// The filter as a closure for `Iterator::filter` over `&MonoModel`
+    pub fn predicate(self) -> impl Fn(&&MonoModel) -> bool {
+        move |model: &&MonoModel| self.matches(model)
+    }
+}
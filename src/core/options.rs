@@ -0,0 +1,69 @@
+use serde_json::Value;
+
+use crate::core::{ToolChoice, check_value};
+
+/// Shape the model's output must take
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum ResponseFormat {
+    #[default]
+    Text,
+    /// Any valid JSON object
+    Json,
+    /// JSON matching the given JSON schema
+    JsonSchema(Value),
+}
+
+impl ResponseFormat {
+    /// Parse a complete response the way this format requires: any JSON for `Text`, an object for
+    /// `Json`, and for `JsonSchema` a value matching the schema (required keys, types, enums; the
+    /// same subset as `Tool::validate_arguments`). A surrounding code fence is tolerated
+    pub fn validate(&self, text: &str) -> Result<Value, String> {
+        let text = text.trim();
+        let json = text
+            .strip_prefix("```json")
+            .or_else(|| text.strip_prefix("```"))
+            .and_then(|rest| rest.strip_suffix("```"))
+            .map(str::trim)
+            .unwrap_or(text);
+        let value: Value = serde_json::from_str(json).map_err(|e| format!("invalid JSON: {}", e))?;
+        match self {
+            ResponseFormat::Json if !value.is_object() => Err("expected a JSON object".to_string()),
+            ResponseFormat::JsonSchema(schema) => check_value(&value, schema, "response").map(|_| value),
+            _ => Ok(value),
+        }
+    }
+}
+
+/// Per-request generation settings shared by all providers.
+///
+/// Unset fields fall back to the client's own defaults, so the same client can serve
+/// requests with different settings concurrently. Providers ignore settings they don't support.
+#[derive(Debug, Clone, Default)]
+pub struct ChatOptions {
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    pub stop: Option<Vec<String>>,
+    pub seed: Option<i64>, // Not supported by Anthropic
+    pub frequency_penalty: Option<f32>, // Not supported by Anthropic
+    pub presence_penalty: Option<f32>, // Not supported by Anthropic
+    pub tool_choice: Option<ToolChoice>, // Emulated through the prompt on Ollama
+    pub response_format: Option<ResponseFormat>,
+}
+
+impl ChatOptions {
+    /// These options with every unset field taken from `defaults`
+    pub fn with_defaults(self, defaults: &ChatOptions) -> ChatOptions {
+        ChatOptions {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            max_tokens: self.max_tokens.or(defaults.max_tokens),
+            stop: self.stop.or_else(|| defaults.stop.clone()),
+            seed: self.seed.or(defaults.seed),
+            frequency_penalty: self.frequency_penalty.or(defaults.frequency_penalty),
+            presence_penalty: self.presence_penalty.or(defaults.presence_penalty),
+            tool_choice: self.tool_choice.or_else(|| defaults.tool_choice.clone()),
+            response_format: self.response_format.or_else(|| defaults.response_format.clone()),
+        }
+    }
+}
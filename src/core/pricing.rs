@@ -0,0 +1,64 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::{MonoModel, TokenUsage};
+
+/// Price of a model in US dollars per token
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input_per_token: f64,
+    pub output_per_token: f64,
+}
+
+impl ModelPrice {
+    /// Prices as usually published, in dollars per million tokens
+    pub fn per_million(input: f64, output: f64) -> Self {
+        Self {
+            input_per_token: input / 1_000_000.0,
+            output_per_token: output / 1_000_000.0,
+        }
+    }
+}
+
+/// Model ID to price mapping used to estimate request costs
+#[derive(Debug, Clone, Default)]
+pub struct PriceTable {
+    prices: HashMap<String, ModelPrice>,
+}
+
+impl PriceTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Table of the prices reported by a model listing (OpenRouter), keyed by model id
+    pub fn from_models(models: &[MonoModel]) -> Self {
+        let mut table = Self::new();
+        for model in models {
+            if let Some(price) = model.pricing {
+                table.insert(model.id.clone(), price);
+            }
+        }
+        table
+    }
+
+    pub fn insert(&mut self, model: impl Into<String>, price: ModelPrice) {
+        self.prices.insert(model.into(), price);
+    }
+
+    pub fn get(&self, model: &str) -> Option<&ModelPrice> {
+        self.prices.get(model)
+    }
+
+    /// Cost of a request, or `None` if the model isn't priced or the usage lacks token counts
+    pub fn estimate_cost(&self, model: &str, usage: &TokenUsage) -> Option<f64> {
+        let price = self.get(model)?;
+        let prompt_tokens = usage.prompt_tokens?;
+        let completion_tokens = usage.completion_tokens?;
+        Some(
+            prompt_tokens as f64 * price.input_per_token
+                + completion_tokens as f64 * price.output_per_token,
+        )
+    }
+}
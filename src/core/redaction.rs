@@ -0,0 +1,166 @@
+use regex::Regex;
+
+// Text without a possible cut point is flushed once it grows past this, so a long digit run can't stall the stream
+const MAX_PENDING: usize = 256;
+
+/// Which PII patterns to mask in streamed content. Pattern-based redaction is best-effort, not guaranteed
+#[derive(Debug, Clone)]
+pub struct PiiConfig {
+    pub emails: bool,
+    pub phone_numbers: bool,
+    pub credit_cards: bool, // Digit runs of card length that pass the Luhn check
+    pub mask: String,
+}
+
+impl Default for PiiConfig {
+    fn default() -> Self {
+        Self {
+            emails: true,
+            phone_numbers: true,
+            credit_cards: true,
+            mask: "[REDACTED]".to_string(),
+        }
+    }
+}
+
+/// Masks PII in streamed text. Text that could still be the start of a match is held back
+/// until the next chunk or `flush`, so matches split across chunks are caught too
+pub struct PiiRedactor {
+    config: PiiConfig,
+    email: Regex,
+    phone: Regex,
+    card: Regex,
+    pending: String,
+}
+
+impl PiiRedactor {
+    pub fn new(config: PiiConfig) -> Self {
+        Self {
+            config,
+            email: Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}").unwrap(),
+            phone: Regex::new(r"(?:\+\d{1,3}[\s.-]?)?(?:\(\d{3}\)\s?|\b\d{3}[\s.-]?)\d{3}[\s.-]?\d{4}\b").unwrap(),
+            card: Regex::new(r"\b(?:\d[ -]?){12,18}\d\b").unwrap(),
+            pending: String::new(),
+        }
+    }
+
+    /// Returns the redacted text that is safe to emit so far
+    pub fn process_chunk(&mut self, chunk: &str) -> String {
+        self.pending.push_str(chunk);
+
+        let cut = match last_cut_point(&self.pending) {
+            Some(cut) => cut,
+            None if self.pending.len() > MAX_PENDING => self.pending.len(),
+            None => return String::new(),
+        };
+        let ready: String = self.pending.drain(..cut).collect();
+        self.redact(&ready)
+    }
+
+    /// Returns whatever is still held back, redacted. Call once the stream ends
+    pub fn flush(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending);
+        self.redact(&rest)
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        if self.config.emails {
+            text = self.email.replace_all(&text, self.config.mask.as_str()).into_owned();
+        }
+        // Cards before phones, since a card number contains phone-shaped digit groups
+        if self.config.credit_cards {
+            text = self
+                .card
+                .replace_all(&text, |caps: &regex::Captures| {
+                    if passes_luhn(&caps[0]) {
+                        self.config.mask.clone()
+                    } else {
+                        caps[0].to_string()
+                    }
+                })
+                .into_owned();
+        }
+        if self.config.phone_numbers {
+            text = self.phone.replace_all(&text, self.config.mask.as_str()).into_owned();
+        }
+        text
+    }
+}
+
+/// Byte index just past the last whitespace that no pattern can span: emails contain none,
+/// and phone and card separators always follow a digit or a closing parenthesis
+fn last_cut_point(text: &str) -> Option<usize> {
+    let mut previous = None;
+    let mut cut = None;
+    for (index, c) in text.char_indices() {
+        if c.is_whitespace() && !matches!(previous, Some(p) if p == ')' || char::is_ascii_digit(&p)) {
+            cut = Some(index + c.len_utf8());
+        }
+        previous = Some(c);
+    }
+    cut
+}
+
+fn passes_luhn(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| if i % 2 == 1 { if d * 2 > 9 { d * 2 - 9 } else { d * 2 } } else { d })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "Mail jane.doe@example.com or call (555) 123-4567, card 4111 1111 1111 1111. Order 4111 1111 1111 1112 shipped.";
+    const REDACTED: &str = "Mail [REDACTED] or call [REDACTED], card [REDACTED]. Order 4111 1111 1111 1112 shipped.";
+
+    #[test]
+    fn redacts_whole_text() {
+        let redactor = PiiRedactor::new(PiiConfig::default());
+
+        assert_eq!(redactor.redact(TEXT), REDACTED);
+    }
+
+    #[test]
+    fn matches_split_at_every_offset() {
+        for split in 1..TEXT.len() {
+            let mut redactor = PiiRedactor::new(PiiConfig::default());
+            let mut output = redactor.process_chunk(&TEXT[..split]);
+            output.push_str(&redactor.process_chunk(&TEXT[split..]));
+            output.push_str(&redactor.flush());
+
+            assert_eq!(output, REDACTED, "split at byte {}", split);
+        }
+    }
+
+    #[test]
+    fn streams_one_byte_at_a_time() {
+        let mut redactor = PiiRedactor::new(PiiConfig::default());
+        let mut output: String = (0..TEXT.len()).map(|i| redactor.process_chunk(&TEXT[i..i + 1])).collect();
+        output.push_str(&redactor.flush());
+
+        assert_eq!(output, REDACTED);
+    }
+
+    #[test]
+    fn disabled_patterns_are_kept() {
+        let config = PiiConfig {
+            emails: false,
+            phone_numbers: false,
+            mask: "***".to_string(),
+            ..Default::default()
+        };
+        let redactor = PiiRedactor::new(config);
+
+        assert_eq!(
+            redactor.redact(TEXT),
+            "Mail jane.doe@example.com or call (555) 123-4567, card ***. Order 4111 1111 1111 1112 shipped."
+        );
+    }
+}
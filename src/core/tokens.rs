@@ -0,0 +1,29 @@
+use crate::core::Message;
+
+// Chat formats wrap every message in a few tokens of role and separator markup
+const TOKENS_PER_MESSAGE: u32 = 4;
+
+/// Rough token count of `text`, about four characters per token for English prose
+pub fn estimate_tokens(text: &str) -> u32 {
+    text.chars().count().div_ceil(4) as u32
+}
+
+/// Rough token count of a single message, including its tool calls and per-message markup
+pub fn estimate_message_tokens(message: &Message) -> u32 {
+    let tool_calls = message
+        .tool_calls
+        .as_ref()
+        .map(|calls| {
+            calls
+                .iter()
+                .map(|call| estimate_tokens(&call.function.name) + estimate_tokens(&call.function.arguments.to_string()))
+                .sum()
+        })
+        .unwrap_or(0);
+    TOKENS_PER_MESSAGE + estimate_tokens(&message.role) + estimate_tokens(&message.content) + tool_calls
+}
+
+/// Rough token count of a whole conversation
+pub fn estimate_messages_tokens(messages: &[Message]) -> u32 {
+    messages.iter().map(estimate_message_tokens).sum()
+}
@@ -1,8 +1,263 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use futures_util::future::join_all;
 use serde_json::Value;
 
+use crate::core::ToolCall;
+use crate::core::trace::trace_event;
+
+pub type ToolFuture = Pin<Box<dyn Future<Output = Result<String, String>> + Send>>;
+
+/// A tool's implementation. `Sync` functions run on the blocking thread pool, `Async` ones are awaited
+pub enum ToolFunction {
+    Sync(Arc<dyn Fn(Value) -> Result<String, String> + Send + Sync>),
+    Async(Arc<dyn Fn(Value) -> ToolFuture + Send + Sync>),
+}
+
 pub struct Tool {
     pub name: String,
     pub description: String,
     pub parameters: Value,
-    pub function: Box<dyn Fn(serde_json::Value) -> String + Send + Sync>,
-}
\ No newline at end of file
+    pub function: ToolFunction,
+}
+
+impl Tool {
+    /// Tool backed by a closure, which can capture application state such as an `Arc<Mutex<_>>`.
+    /// `parameters` is the JSON schema of the arguments object the closure receives
+    pub fn new(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: Value,
+        function: impl Fn(Value) -> Result<String, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+            function: ToolFunction::Sync(Arc::new(function)),
+        }
+    }
+
+    /// Like `Tool::new`, for closures that return a future
+    pub fn new_async<F, Fut>(
+        name: impl Into<String>,
+        description: impl Into<String>,
+        parameters: Value,
+        function: F,
+    ) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, String>> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            parameters,
+            function: ToolFunction::Async(Arc::new(move |arguments| Box::pin(function(arguments)))),
+        }
+    }
+
+    /// Check `arguments` against the `parameters` schema: required keys present, declared types and
+    /// enum values matched, nested objects and array items included. A lightweight subset of JSON
+    /// Schema meant to catch malformed model output, not a full validator
+    pub fn validate_arguments(&self, arguments: &Value) -> Result<(), String> {
+        // Some models send no arguments at all for tools whose parameters are all optional
+        if arguments.is_null() && self.parameters["type"] == "object" {
+            return check_value(&Value::Object(Default::default()), &self.parameters, "arguments");
+        }
+        check_value(arguments, &self.parameters, "arguments")
+    }
+
+    /// Run the tool, turning a failed call into an error message the model can read
+    pub async fn call(&self, arguments: Value) -> String {
+        if let Err(e) = self.validate_arguments(&arguments) {
+            return tool_output(Err(e));
+        }
+        let result = match &self.function {
+            ToolFunction::Sync(function) => function(arguments),
+            ToolFunction::Async(function) => function(arguments).await,
+        };
+        tool_output(result)
+    }
+}
+
+fn tool_output(result: Result<String, String>) -> String {
+    match result {
+        Ok(output) => output,
+        Err(e) => format!("Error: {}", e),
+    }
+}
+
+pub(crate) fn check_value(value: &Value, schema: &Value, path: &str) -> Result<(), String> {
+    if let Some(expected) = schema.get("type") {
+        let types: Vec<&str> = match expected {
+            Value::String(name) => vec![name.as_str()],
+            Value::Array(names) => names.iter().filter_map(Value::as_str).collect(),
+            _ => Vec::new(),
+        };
+        if !types.is_empty() && !types.iter().any(|name| matches_type(value, name)) {
+            return Err(format!("{} should be {}, got {}", path, types.join(" or "), type_name(value)));
+        }
+    }
+
+    if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+        && !allowed.contains(value)
+    {
+        return Err(format!("{} should be one of {}", path, Value::Array(allowed.clone())));
+    }
+
+    match value {
+        Value::Object(object) => {
+            if let Some(required) = schema.get("required").and_then(Value::as_array) {
+                for key in required.iter().filter_map(Value::as_str) {
+                    if !object.contains_key(key) {
+                        return Err(format!("missing required parameter {}.{}", path, key));
+                    }
+                }
+            }
+            if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+                for (key, field) in object {
+                    if let Some(field_schema) = properties.get(key) {
+                        check_value(field, field_schema, &format!("{}.{}", path, key))?;
+                    }
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(item_schema) = schema.get("items") {
+                for (index, item) in items.iter().enumerate() {
+                    check_value(item, item_schema, &format!("{}[{}]", path, index))?;
+                }
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+fn matches_type(value: &Value, name: &str) -> bool {
+    match name {
+        "object" => value.is_object(),
+        "array" => value.is_array(),
+        "string" => value.is_string(),
+        "boolean" => value.is_boolean(),
+        "null" => value.is_null(),
+        "number" => value.is_number(),
+        // Models often write whole numbers as 2.0
+        "integer" => value.is_i64() || value.is_u64() || value.as_f64().is_some_and(|n| n.fract() == 0.0),
+        // Unknown type names aren't ours to reject
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+/// Run the requested tools concurrently, so slow tools don't wait on each other.
+/// Results keep the order of `tool_calls`; calls to unknown tools give `None`
+pub(crate) async fn call_tools(tools: &[Arc<Tool>], tool_calls: &[ToolCall]) -> Vec<Option<String>> {
+    let calls = tool_calls.iter().map(|tool_call| {
+        let tool = tools.iter().find(|t| t.name == tool_call.function.name).cloned();
+        let arguments = tool_call.function.arguments.clone();
+        async move {
+            if tool.is_none() {
+                trace_event!(warn, tool = %tool_call.function.name, "model called an unknown tool");
+            }
+            let tool = tool?;
+            // Malformed arguments go back to the model instead of reaching the tool function
+            if let Err(e) = tool.validate_arguments(&arguments) {
+                trace_event!(warn, tool = %tool.name, error = %e, "tool arguments rejected");
+                return Some(tool_output(Err(e)));
+            }
+            trace_event!(debug, tool = %tool.name, "dispatching tool call");
+            let result = match &tool.function {
+                ToolFunction::Sync(function) => {
+                    let function = function.clone();
+                    tokio::task::spawn_blocking(move || tool_output(function(arguments)))
+                        .await
+                        .unwrap_or_else(|e| format!("Error: {}", e))
+                }
+                ToolFunction::Async(function) => tool_output(function(arguments).await),
+            };
+            trace_event!(debug, tool = %tool.name, output_len = result.len(), "tool call finished");
+            Some(result)
+        }
+    });
+    join_all(calls).await
+}
+
+/// Which tool the model is allowed or required to call
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ToolChoice {
+    /// The model decides whether to call a tool
+    #[default]
+    Auto,
+    /// The model must not call any tool
+    None,
+    /// The model must call at least one tool
+    Required,
+    /// The model must call the named tool
+    Specific(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::core::Function;
+
+    fn call(name: &str) -> ToolCall {
+        ToolCall {
+            id: None,
+            function: Function {
+                name: name.to_string(),
+                arguments: json!({}),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn call_tools_runs_calls_concurrently() {
+        let delay = Duration::from_millis(300);
+        let tools = vec![
+            Arc::new(Tool::new("blocking", "", json!({"type": "object"}), move |_| {
+                std::thread::sleep(delay);
+                Ok("blocking done".to_string())
+            })),
+            Arc::new(Tool::new_async("sleeping", "", json!({"type": "object"}), move |_| async move {
+                tokio::time::sleep(delay).await;
+                Ok("sleeping done".to_string())
+            })),
+        ];
+        let calls = vec![call("blocking"), call("sleeping"), call("blocking")];
+
+        let started = Instant::now();
+        let results = call_tools(&tools, &calls).await;
+        let elapsed = started.elapsed();
+
+        assert_eq!(
+            results,
+            vec![
+                Some("blocking done".to_string()),
+                Some("sleeping done".to_string()),
+                Some("blocking done".to_string()),
+            ]
+        );
+        // Sequential calls would take three delays
+        assert!(elapsed >= delay);
+        assert!(elapsed < delay * 2, "tool calls took {:?}", elapsed);
+    }
+}
@@ -0,0 +1,58 @@
+use std::pin::Pin;
+use std::time::Instant;
+
+use futures_util::Stream;
+
+use crate::core::{AIRequestError, ChatStreamItem};
+
+type ChatStream = Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>;
+
+// Forwards to the `tracing` macro of the same level with the `tracing` feature, expands to nothing without it
+macro_rules! trace_event {
+    ($level:ident, $($arg:tt)+) => {
+        #[cfg(feature = "tracing")]
+        tracing::$level!($($arg)+);
+    };
+}
+pub(crate) use trace_event;
+
+/// Wraps a chat stream in a `chat_request` span with events for the response headers, the first
+/// chunk, stream errors and completion with token counts, each with the latency since `started`
+#[cfg(feature = "tracing")]
+pub(crate) fn trace_chat_stream(stream: ChatStream, provider: &'static str, model: &str, started: Instant) -> ChatStream {
+    use futures_util::StreamExt;
+
+    let span = tracing::info_span!("chat_request", provider, model);
+    span.in_scope(|| tracing::debug!(latency_ms = started.elapsed().as_millis() as u64, "response headers received"));
+
+    let mut first_chunk = true;
+    Box::pin(stream.map(move |item| {
+        let _entered = span.enter();
+        let latency_ms = started.elapsed().as_millis() as u64;
+        match &item {
+            Ok(item) => {
+                if first_chunk {
+                    first_chunk = false;
+                    tracing::debug!(latency_ms, "first chunk received");
+                }
+                if item.done {
+                    let usage = item.usage.as_ref();
+                    tracing::info!(
+                        latency_ms,
+                        prompt_tokens = usage.and_then(|usage| usage.prompt_tokens),
+                        completion_tokens = usage.and_then(|usage| usage.completion_tokens),
+                        finish_reason = item.finish_reason.as_deref(),
+                        "chat request completed"
+                    );
+                }
+            }
+            Err(error) => tracing::warn!(latency_ms, fatal = error.is_fatal(), %error, "chat stream error"),
+        }
+        item
+    }))
+}
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) fn trace_chat_stream(stream: ChatStream, _provider: &'static str, _model: &str, _started: Instant) -> ChatStream {
+    stream
+}
@@ -1,13 +1,62 @@
+use futures_util::{Stream, StreamExt};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
+use crate::core::{AIRequestError, ImageFormat, ModelPrice};
+
+/// Receives every raw line of a chat response stream before it is parsed, e.g. to capture payloads for bug reports
+pub type RawChunkHook = Arc<dyn Fn(&str) + Send + Sync>;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
 pub struct Message {
     pub role: String,
     pub content: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub images: Option<Vec<String>>,
+    pub images: Option<Vec<String>>, // Base64 image data or http(s) image URLs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_media_types: Option<Vec<String>>, // Media type of each entry of `images`, e.g. "image/png"; detected from the data when missing or empty
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_calls: Option<Vec<ToolCall>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_name: Option<String>, // Tool that produced a tool-role message (Ollama)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>, // Call a tool-role message answers, when the server assigned ids (Ollama)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>, // Participant name, e.g. to tell agents or users apart (OpenAI, ignored by the others)
+    #[serde(skip)]
+    pub cache: bool, // Anthropic prompt caching breakpoint: everything up to and including this message is cached
+}
+
+impl Message {
+    /// Attach base64 image data with an explicit media type, e.g. `"image/png"`
+    pub fn add_image(&mut self, data: impl Into<String>, media_type: impl Into<String>) {
+        let images = self.images.get_or_insert_with(Vec::new);
+        images.push(data.into());
+        // Earlier images without a type get empty entries, which fall back to detection
+        let media_types = self.image_media_types.get_or_insert_with(Vec::new);
+        media_types.resize(images.len() - 1, String::new());
+        media_types.push(media_type.into());
+    }
+
+    /// Media type of the base64 image at `index`: the one set in `image_media_types`, otherwise
+    /// detected from the data, falling back to JPEG
+    pub fn image_media_type(&self, index: usize) -> &str {
+        if let Some(media_type) = self.image_media_types.as_ref().and_then(|types| types.get(index))
+            && !media_type.is_empty()
+        {
+            return media_type;
+        }
+        self.images
+            .as_ref()
+            .and_then(|images| images.get(index))
+            .and_then(|data| ImageFormat::detect_base64(data))
+            .map_or("image/jpeg", |format| format.media_type())
+    }
+}
+
+/// True when an entry of `Message.images` is a remote URL rather than base64 data
+pub fn is_image_url(image: &str) -> bool {
+    image.starts_with("http://") || image.starts_with("https://")
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -30,22 +79,32 @@ pub struct MonoModel {
     pub provider: String,
     pub size: Option<u64>, // Size in bytes for local models
     pub created: Option<u64>, // Creation timestamp for cloud models
+    pub context_length: Option<u32>, // Context window in tokens, when the listing reports it (OpenRouter)
+    pub capabilities: Option<ProviderCapabilities>, // When the listing reports them (OpenRouter)
+    pub pricing: Option<ModelPrice>, // When the listing reports it (OpenRouter)
 }
 
-#[derive(Debug, Clone)]
+/// What a model accepts, as reported by the provider's model listing
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ProviderCapabilities {
+    pub vision: bool,
+    pub tools: bool,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct TokenUsage {
     pub prompt_tokens: Option<u32>,
     pub completion_tokens: Option<u32>,
     pub total_tokens: Option<u32>,
+    pub cost_usd: Option<f64>, // Reported by the provider or estimated from a PriceTable
+    pub generation_id: Option<String>, // Provider ID of the completion, for billing reconciliation
+    pub cache_creation_tokens: Option<u32>, // Prompt tokens written to the prompt cache (Anthropic)
+    pub cache_read_tokens: Option<u32>, // Prompt tokens served from the prompt cache (Anthropic)
 }
 
 impl TokenUsage {
     pub fn new() -> Self {
-        Self {
-            prompt_tokens: None,
-            completion_tokens: None,
-            total_tokens: None,
-        }
+        Self::default()
     }
 
     pub fn with_tokens(prompt: u32, completion: u32) -> Self {
@@ -53,6 +112,10 @@ impl TokenUsage {
             prompt_tokens: Some(prompt),
             completion_tokens: Some(completion),
             total_tokens: Some(prompt + completion),
+            cost_usd: None,
+            generation_id: None,
+            cache_creation_tokens: None,
+            cache_read_tokens: None,
         }
     }
 }
@@ -60,9 +123,110 @@ impl TokenUsage {
 #[derive(Debug)]
 pub struct ChatStreamItem {
     pub content: String,
-    pub tool_calls: Option<Vec<ToolCall>>,
+    pub tool_calls: Option<Vec<ToolCall>>, // May be split over several items (Ollama fallback mode), collect them all
     pub done: bool,
     pub usage: Option<TokenUsage>,
+    pub finish_reason: Option<String>, // Provider stop reason, set on the final item
+    pub reasoning: Option<String>, // Thinking output, kept out of content
+    pub filtered: Option<String>, // Raw markup hidden from content, when capture is enabled (Ollama)
+    pub citations: Option<Vec<Citation>>, // Sources backing the content (Anthropic)
+    pub timings: Option<Timings>, // Generation timings, set on the final item (Ollama)
+}
+
+/// Generation timings as reported by the server, durations in nanoseconds
+#[derive(Debug, Clone, Default)]
+pub struct Timings {
+    pub total_duration: Option<u64>,
+    pub load_duration: Option<u64>,
+    pub prompt_eval_duration: Option<u64>,
+    pub eval_duration: Option<u64>,
+    pub tokens_per_second: Option<f64>, // Completion tokens over eval_duration
+}
+
+/// A source span backing part of the response
+#[derive(Debug, Clone)]
+pub struct Citation {
+    pub cited_text: String,
+    pub document_index: Option<u32>,
+    pub document_title: Option<String>,
+    pub location: Option<CitationLocation>,
+}
+
+/// Where in the source document the cited text is. Ranges are as reported by the provider
+#[derive(Debug, Clone, PartialEq)]
+pub enum CitationLocation {
+    Chars { start: u32, end: u32 },
+    Pages { start: u32, end: u32 },
+    ContentBlocks { start: u32, end: u32 },
+    Url(String),
+}
+
+/// Collected result of a full chat response
+#[derive(Debug, Clone, Default)]
+pub struct ChatResult {
+    pub content: String,
+    /// Thinking output, kept apart from `content`
+    pub reasoning: Option<String>,
+    pub tool_calls: Option<Vec<ToolCall>>,
+    pub finish_reason: Option<String>,
+    pub usage: Option<TokenUsage>,
+    pub citations: Vec<Citation>,
+}
+
+impl ChatResult {
+    /// True when the model returned neither content nor tool calls,
+    /// e.g. a content filter stop or an empty completion. Check `finish_reason` for why.
+    pub fn is_empty(&self) -> bool {
+        self.content.trim().is_empty()
+            && self.tool_calls.as_ref().is_none_or(|calls| calls.is_empty())
+    }
+
+    /// Consume a chat stream into the full response with its tool calls, usage and finish reason,
+    /// e.g. for streams from `send_chat_request_with_options` or `send_chat_request_cancellable`.
+    /// Non-fatal decode errors are skipped, see `AIRequestError::is_fatal`
+    pub async fn collect<S>(mut stream: S) -> Result<ChatResult, AIRequestError>
+    where
+        S: Stream<Item = Result<ChatStreamItem, AIRequestError>> + Unpin,
+    {
+        let mut result = ChatResult::default();
+
+        while let Some(item) = stream.next().await {
+            match item {
+                Ok(item) => {
+                    if result.push_item(item) {
+                        break;
+                    }
+                }
+                Err(error) if !error.is_fatal() => {}
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(result)
+    }
+
+    // Add a stream item to the result, true once the stream is done
+    pub(crate) fn push_item(&mut self, item: ChatStreamItem) -> bool {
+        if !item.content.is_empty() {
+            self.content.push_str(&item.content);
+        }
+        if let Some(reasoning) = item.reasoning {
+            self.reasoning.get_or_insert_with(String::new).push_str(&reasoning);
+        }
+        // Ollama's fallback mode emits each tool call as soon as it's complete
+        if let Some(tc) = item.tool_calls {
+            self.tool_calls.get_or_insert_with(Vec::new).extend(tc);
+        }
+        if item.usage.is_some() {
+            self.usage = item.usage;
+        }
+        if item.finish_reason.is_some() {
+            self.finish_reason = item.finish_reason;
+        }
+        if let Some(citations) = item.citations {
+            self.citations.extend(citations);
+        }
+        item.done
+    }
 }
 
 #[derive(Debug)]
@@ -73,10 +237,69 @@ pub struct PullProgress {
     pub completed: Option<u64>,
 }
 
+// The optional fields are defaulted since older Ollama versions leave them out
 #[derive(Deserialize, Debug)]
 pub struct ModelInfo {
     pub license: String,
     pub modelfile: String,
     pub parameters: String,
     pub template: String,
+    #[serde(default)]
+    pub details: Option<ModelDetails>,
+    #[serde(default)]
+    pub model_info: Option<serde_json::Map<String, serde_json::Value>>, // GGUF metadata, keyed like `llama.context_length`
+    #[serde(default)]
+    pub capabilities: Option<Vec<String>>, // e.g. "completion", "vision", "tools"; reported since Ollama 0.6.4
+    #[serde(default)]
+    pub projector_info: Option<serde_json::Map<String, serde_json::Value>>, // Vision projector of LLaVA style models
+}
+
+/// Model family and quantization, as shown by `ollama show`
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct ModelDetails {
+    #[serde(default)]
+    pub parent_model: Option<String>,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub family: Option<String>,
+    #[serde(default)]
+    pub families: Option<Vec<String>>,
+    #[serde(default)]
+    pub parameter_size: Option<String>, // e.g. "8.0B"
+    #[serde(default)]
+    pub quantization_level: Option<String>, // e.g. "Q4_K_M"
+}
+
+impl ModelInfo {
+    /// Whether the model accepts images. Uses the reported capabilities, and for older servers a
+    /// vision projector, a `clip`/`mllama` family or vision tensors in the model metadata
+    pub fn supports_vision(&self) -> bool {
+        if let Some(capabilities) = &self.capabilities {
+            return capabilities.iter().any(|capability| capability == "vision");
+        }
+        let vision_family = self.details.as_ref().and_then(|details| details.families.as_ref()).is_some_and(|families| {
+            families.iter().any(|family| family == "clip" || family == "mllama")
+        });
+        let vision_metadata = self.model_info.as_ref().is_some_and(|model_info| {
+            model_info.keys().any(|key| key.contains(".vision."))
+        });
+        self.projector_info.is_some() || vision_family || vision_metadata
+    }
+
+    /// The context window the model was trained with, from the model metadata. `None` for older
+    /// servers that don't report it. A `num_ctx` parameter may still limit the window actually used
+    pub fn context_length(&self) -> Option<u64> {
+        let model_info = self.model_info.as_ref()?;
+        let architecture = model_info.get("general.architecture").and_then(|value| value.as_str());
+        architecture
+            .and_then(|architecture| model_info.get(&format!("{}.context_length", architecture)))
+            .or_else(|| {
+                model_info
+                    .iter()
+                    .find(|(key, _)| key.ends_with(".context_length"))
+                    .map(|(_, value)| value)
+            })
+            .and_then(|value| value.as_u64())
+    }
 }
\ No newline at end of file
@@ -0,0 +1,191 @@
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::core::TokenUsage;
+
+/// Receives token usage every time a chat stream reports it, e.g. for cost dashboards
+pub trait UsageSink: Send + Sync {
+    fn record(&self, provider: &str, model: &str, usage: &TokenUsage, cost: Option<f64>);
+}
+
+/// Cap for `UsageTracker::budget_exceeded`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UsageLimit {
+    /// Prompt plus completion tokens
+    Tokens(u64),
+    /// US dollars, counting reported costs and estimates from the price table
+    CostUsd(f64),
+}
+
+/// Cumulative usage recorded by a `UsageTracker`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub prompt_tokens: u64,
+    pub completion_tokens: u64,
+    pub total_tokens: u64,
+    pub cost_usd: f64,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    totals: UsageTotals,
+    limit: Option<UsageLimit>,
+}
+
+/// Running total of tokens and cost across requests, e.g. for one conversation or agent run.
+/// Clones share the same totals, so one can be attached with `NaoriAI::set_usage_tracker` while
+/// another is read by the caller
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl UsageTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tracker whose client refuses further chat requests once `limit` is reached
+    pub fn with_limit(limit: UsageLimit) -> Self {
+        let tracker = Self::default();
+        tracker.set_limit(Some(limit));
+        tracker
+    }
+
+    /// Limit checked by `NaoriAI` before each chat request, see `limit_exceeded`
+    pub fn set_limit(&self, limit: Option<UsageLimit>) {
+        self.state.lock().unwrap().limit = limit;
+    }
+
+    pub fn limit(&self) -> Option<UsageLimit> {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Add one response's usage, for totals kept by hand, e.g. from `ChatResult.usage`
+    pub fn add(&self, usage: &TokenUsage) {
+        let mut state = self.state.lock().unwrap();
+        let totals = &mut state.totals;
+        let prompt_tokens = usage.prompt_tokens.unwrap_or(0) as u64;
+        let completion_tokens = usage.completion_tokens.unwrap_or(0) as u64;
+        totals.requests += 1;
+        totals.prompt_tokens += prompt_tokens;
+        totals.completion_tokens += completion_tokens;
+        totals.total_tokens += usage.total_tokens.map_or(prompt_tokens + completion_tokens, u64::from);
+        totals.cost_usd += usage.cost_usd.unwrap_or(0.0);
+    }
+
+    pub fn totals(&self) -> UsageTotals {
+        self.state.lock().unwrap().totals
+    }
+
+    /// Start counting from zero again, keeping the limit
+    pub fn reset(&self) {
+        self.state.lock().unwrap().totals = UsageTotals::default();
+    }
+
+    /// Whether the totals have reached `limit`
+    pub fn budget_exceeded(&self, limit: UsageLimit) -> bool {
+        let totals = self.totals();
+        match limit {
+            UsageLimit::Tokens(max) => totals.total_tokens >= max,
+            UsageLimit::CostUsd(max) => totals.cost_usd >= max,
+        }
+    }
+
+    /// Whether the totals have reached the tracker's own limit, false without one
+    pub fn limit_exceeded(&self) -> bool {
+        self.limit().is_some_and(|limit| self.budget_exceeded(limit))
+    }
+}
+
+impl UsageSink for UsageTracker {
+    fn record(&self, _provider: &str, _model: &str, usage: &TokenUsage, _cost: Option<f64>) {
+        self.add(usage);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageFileFormat {
+    Csv,
+    Jsonl,
+}
+
+/// Appends one line per recorded usage to a CSV or JSONL file
+pub struct FileUsageSink {
+    file: Mutex<File>,
+    format: UsageFileFormat,
+}
+
+impl FileUsageSink {
+    /// Open (or create) the file for appending. A CSV header is written when the file is empty
+    pub fn new(path: impl AsRef<Path>, format: UsageFileFormat) -> std::io::Result<Self> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if format == UsageFileFormat::Csv && file.metadata()?.len() == 0 {
+            writeln!(file, "timestamp,provider,model,prompt_tokens,completion_tokens,total_tokens,cost_usd")?;
+        }
+        Ok(Self {
+            file: Mutex::new(file),
+            format,
+        })
+    }
+
+    pub fn csv(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::new(path, UsageFileFormat::Csv)
+    }
+
+    pub fn jsonl(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Self::new(path, UsageFileFormat::Jsonl)
+    }
+}
+
+impl UsageSink for FileUsageSink {
+    fn record(&self, provider: &str, model: &str, usage: &TokenUsage, cost: Option<f64>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+
+        let line = match self.format {
+            UsageFileFormat::Csv => {
+                let field = |value: Option<String>| value.unwrap_or_default();
+                format!(
+                    "{},{},{},{},{},{},{}",
+                    timestamp,
+                    csv_escape(provider),
+                    csv_escape(model),
+                    field(usage.prompt_tokens.map(|n| n.to_string())),
+                    field(usage.completion_tokens.map(|n| n.to_string())),
+                    field(usage.total_tokens.map(|n| n.to_string())),
+                    field(cost.map(|c| c.to_string())),
+                )
+            }
+            UsageFileFormat::Jsonl => serde_json::json!({
+                "timestamp": timestamp,
+                "provider": provider,
+                "model": model,
+                "prompt_tokens": usage.prompt_tokens,
+                "completion_tokens": usage.completion_tokens,
+                "total_tokens": usage.total_tokens,
+                "cost_usd": cost,
+            })
+            .to_string(),
+        };
+
+        // Logging must never break a chat, so write failures are ignored
+        if let Ok(mut file) = self.file.lock() {
+            let _ = writeln!(file, "{}", line);
+        }
+    }
+}
+
+fn csv_escape(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -3,7 +3,9 @@ pub mod providers;
 pub mod naori;
 
 // Re-export core types
-pub use core::{Message, ToolCall, Function, ChatStreamItem, PullProgress, ModelInfo, Tool, FallbackToolHandler, AIRequestError, MonoModel};
+pub use core::{Message, ToolCall, Function, ChatStreamItem, ChatResult, RawChunkHook, Timings, Citation, CitationLocation, PullProgress, ModelInfo, ModelDetails, Tool, ToolFunction, ToolFuture, ToolChoice, ChatOptions, ResponseFormat, FallbackToolHandler, FallbackConfig, AIRequestError, MonoModel, ModelFilter, ProviderCapabilities, TokenUsage, PriceTable, ModelPrice, PiiConfig, PiiRedactor, ImageFormat, is_animated_image, estimate_tokens, estimate_message_tokens, estimate_messages_tokens, truncate_to_fit, TruncationStrategy, ClientConfig, UsageSink, FileUsageSink, UsageTracker, UsageTotals, UsageLimit};
+
+pub use tokio_util::sync::CancellationToken;
 
 // Main interface
-pub use naori::NaoriAI;
\ No newline at end of file
+pub use naori::{NaoriAI, NaoriAIBuilder, ProviderKind, BenchmarkResult, BenchmarkStats};
\ No newline at end of file
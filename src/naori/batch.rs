@@ -0,0 +1,55 @@
+use std::time::Duration;
+
+use futures_util::{StreamExt, stream};
+
+use crate::core::{Message, ChatResult, AIRequestError};
+use super::client::NaoriAI;
+
+// First backoff when a rate limited response doesn't say how long to wait, doubled per attempt
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(1);
+// Longest wait between attempts, also for a server asking for more
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(60);
+
+impl NaoriAI {
+    /// Run independent conversations as complete requests, e.g. for labeling a dataset. At most
+    /// `max_concurrent` requests are in flight, so the batch stays under provider rate limits.
+    /// Requests failing with a retryable error (see `AIRequestError::is_retryable`) are retried up
+    /// to `max_retries` times, waiting as long as a rate limit asks or backing off exponentially,
+    /// at most a minute per attempt.
+    /// Results come back in input order, one per conversation, so single failures don't sink the batch.
+    pub async fn batch_complete(
+        &self,
+        conversations: Vec<Vec<Message>>,
+        max_concurrent: usize,
+        max_retries: u32,
+    ) -> Vec<Result<ChatResult, AIRequestError>> {
+        let mut results: Vec<_> = stream::iter(conversations.into_iter().enumerate())
+            .map(|(index, messages)| async move { (index, self.complete_with_retries(messages, max_retries).await) })
+            .buffer_unordered(max_concurrent.max(1))
+            .collect()
+            .await;
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+
+    async fn complete_with_retries(&self, messages: Vec<Message>, max_retries: u32) -> Result<ChatResult, AIRequestError> {
+        let mut attempt = 0;
+        loop {
+            let result = match self.send_chat_request(&messages).await {
+                Ok(stream) => ChatResult::collect(stream).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Err(e) if e.is_retryable() && attempt < max_retries => {
+                    let delay = match &e {
+                        AIRequestError::RateLimited { retry_after: Some(delay) } => *delay,
+                        _ => RETRY_BASE_DELAY.saturating_mul(2u32.saturating_pow(attempt)),
+                    };
+                    tokio::time::sleep(delay.min(RETRY_MAX_DELAY)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
@@ -0,0 +1,97 @@
+use std::time::{Duration, Instant};
+use futures_util::StreamExt;
+
+use crate::core::{Message, AIRequestError};
+use super::client::NaoriAI;
+
+/// Mean and percentiles of one metric across benchmark runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkStats {
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+}
+
+impl BenchmarkStats {
+    fn from_samples(mut samples: Vec<f64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_by(|a, b| a.total_cmp(b));
+        let percentile = |p: f64| samples[((samples.len() - 1) as f64 * p).round() as usize];
+        Some(Self {
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+        })
+    }
+}
+
+/// Timings of a prompt run several times against the same model
+#[derive(Debug, Clone)]
+pub struct BenchmarkResult {
+    pub runs: usize,
+    pub time_to_first_token_ms: Option<BenchmarkStats>,
+    /// Completion tokens per second after the first token, for runs that reported usage
+    pub tokens_per_second: Option<BenchmarkStats>,
+    pub total_latency_ms: Option<BenchmarkStats>,
+}
+
+impl NaoriAI {
+    /// Run `prompt` `runs` times in sequence and measure time to first token, generation speed
+    /// and total latency. Times are wall-clock on the client, so network latency is included.
+    pub async fn benchmark(&self, prompt: &str, runs: usize) -> Result<BenchmarkResult, AIRequestError> {
+        let messages = vec![Message {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+            ..Default::default()
+        }];
+
+        let mut first_token_times = Vec::new();
+        let mut token_rates = Vec::new();
+        let mut total_times = Vec::new();
+
+        for _ in 0..runs {
+            let start = Instant::now();
+            let mut first_token: Option<Duration> = None;
+            let mut completion_tokens = None;
+
+            let mut stream = self.send_chat_request(&messages).await?;
+            while let Some(item) = stream.next().await {
+                let item = match item {
+                    Ok(item) => item,
+                    Err(error) if !error.is_fatal() => continue,
+                    Err(error) => return Err(error),
+                };
+                if first_token.is_none() && (!item.content.is_empty() || item.reasoning.is_some()) {
+                    first_token = Some(start.elapsed());
+                }
+                if let Some(usage) = &item.usage {
+                    completion_tokens = usage.completion_tokens;
+                }
+                if item.done {
+                    break;
+                }
+            }
+            let total = start.elapsed();
+
+            total_times.push(total.as_secs_f64() * 1000.0);
+            if let Some(first_token) = first_token {
+                first_token_times.push(first_token.as_secs_f64() * 1000.0);
+                let generation_time = (total - first_token).as_secs_f64();
+                if let Some(tokens) = completion_tokens
+                    && generation_time > 0.0
+                {
+                    token_rates.push(tokens as f64 / generation_time);
+                }
+            }
+        }
+
+        Ok(BenchmarkResult {
+            runs,
+            time_to_first_token_ms: BenchmarkStats::from_samples(first_token_times),
+            tokens_per_second: BenchmarkStats::from_samples(token_rates),
+            total_latency_ms: BenchmarkStats::from_samples(total_times),
+        })
+    }
+}
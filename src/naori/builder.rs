@@ -0,0 +1,161 @@
+use std::time::Duration;
+
+use reqwest::Client;
+
+use crate::core::{AIRequestError, ClientConfig};
+use crate::providers::anthropic::AnthropicClient;
+use crate::providers::openai::OpenAIClient;
+use crate::providers::ollama::OllamaClient;
+use super::client::{NaoriAI, Provider, GROQ_BASE_URL, MISTRAL_BASE_URL, OPENROUTER_BASE_URL};
+
+/// Which backend `NaoriAI::builder()` connects to. OpenRouter, Groq and Mistral use the OpenAI
+/// client with their own default base URL and tool schema settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProviderKind {
+    Ollama,
+    Anthropic,
+    OpenAI,
+    OpenRouter,
+    Groq,
+    Mistral,
+}
+
+/// Step by step construction of a `NaoriAI`, checked when calling `build()`
+#[derive(Default)]
+pub struct NaoriAIBuilder {
+    provider: Option<ProviderKind>,
+    api_key: Option<String>,
+    model: Option<String>,
+    base_url: Option<String>,
+    http_client: Option<Client>,
+    client_config: ClientConfig,
+    headers: Vec<(String, String)>,
+}
+
+impl NaoriAI {
+    /// Configure a client step by step instead of picking a constructor:
+    /// `NaoriAI::builder().provider(ProviderKind::OpenAI).api_key(key).model(model).build()?`
+    pub fn builder() -> NaoriAIBuilder {
+        NaoriAIBuilder::default()
+    }
+}
+
+impl NaoriAIBuilder {
+    pub fn provider(mut self, provider: ProviderKind) -> Self {
+        self.provider = Some(provider);
+        self
+    }
+
+    /// Required for every provider except Ollama, where it is sent as a bearer token when set
+    pub fn api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.model = Some(model.into());
+        self
+    }
+
+    /// API base URL, e.g. for gateways or OpenAI-compatible servers. Defaults to the provider's
+    /// public API; required for Ollama, where it is the server endpoint
+    pub fn base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = Some(base_url.into());
+        self
+    }
+
+    /// Same as `base_url`, under the name the Ollama constructors use
+    pub fn endpoint(self, endpoint: impl Into<String>) -> Self {
+        self.base_url(endpoint)
+    }
+
+    /// Limit for the whole request, including the streamed response
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.client_config.timeout = Some(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.client_config.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Route all requests through `proxy_url`, see `ClientConfig::proxy`
+    pub fn proxy(mut self, proxy_url: impl Into<String>) -> Self {
+        self.client_config.proxy = Some(proxy_url.into());
+        self
+    }
+
+    /// Use a pre-built `reqwest::Client`. Can't be combined with `timeout`, `connect_timeout` or `proxy`,
+    /// which configure the client the builder creates itself
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    /// Custom header sent with every request, see `NaoriAI::set_header`
+    pub fn header(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.headers.push((name.into(), value.into()));
+        self
+    }
+
+    /// Check the configuration and create the client. Fails when the provider, model, API key of a
+    /// cloud provider or Ollama endpoint is missing, or when a proxy URL or header is invalid
+    pub fn build(self) -> Result<NaoriAI, AIRequestError> {
+        let kind = self.provider.ok_or_else(|| missing("provider"))?;
+        let model = self.model.ok_or_else(|| missing("model"))?;
+
+        let uses_client_config = self.client_config.timeout.is_some()
+            || self.client_config.connect_timeout.is_some()
+            || self.client_config.proxy.is_some();
+        let client = match self.http_client {
+            Some(_) if uses_client_config => {
+                return Err(AIRequestError::Other(
+                    "NaoriAI builder: set either http_client or timeout/proxy settings, not both".to_string(),
+                ));
+            }
+            Some(client) => client,
+            None => self.client_config.build()?,
+        };
+
+        let provider = match kind {
+            ProviderKind::Ollama => {
+                let endpoint = self.base_url.ok_or_else(|| missing("endpoint"))?;
+                let mut ollama = OllamaClient::with_client(client, endpoint, model);
+                ollama.set_api_key(self.api_key);
+                Provider::Ollama(ollama)
+            }
+            ProviderKind::Anthropic => {
+                let api_key = self.api_key.ok_or_else(|| missing("api_key"))?;
+                let base_url = self
+                    .base_url
+                    .unwrap_or_else(|| crate::providers::anthropic::client::DEFAULT_BASE_URL.to_string());
+                Provider::Anthropic(AnthropicClient::with_client(client, api_key, model, base_url))
+            }
+            ProviderKind::OpenAI | ProviderKind::OpenRouter | ProviderKind::Groq | ProviderKind::Mistral => {
+                let api_key = self.api_key.ok_or_else(|| missing("api_key"))?;
+                let default_base_url = match kind {
+                    ProviderKind::OpenRouter => OPENROUTER_BASE_URL,
+                    ProviderKind::Groq => GROQ_BASE_URL,
+                    ProviderKind::Mistral => MISTRAL_BASE_URL,
+                    _ => crate::providers::openai::client::DEFAULT_BASE_URL,
+                };
+                let base_url = self.base_url.unwrap_or_else(|| default_base_url.to_string());
+                let mut openai = OpenAIClient::with_client(client, api_key, model, base_url);
+                // Groq and Mistral reject the additionalProperties field of strict tool schemas
+                if matches!(kind, ProviderKind::Groq | ProviderKind::Mistral) {
+                    openai.set_strict_tools(false);
+                }
+                Provider::OpenAI(openai)
+            }
+        };
+
+        let mut naori = NaoriAI::from_provider(provider);
+        naori.set_headers(self.headers)?;
+        Ok(naori)
+    }
+}
+
+fn missing(field: &str) -> AIRequestError {
+    AIRequestError::Other(format!("NaoriAI builder: {} is required", field))
+}
@@ -1,13 +1,27 @@
-use std::error::Error;
+use std::ops::ControlFlow;
 use std::pin::Pin;
-use futures_util::{Stream, StreamExt};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use futures_util::{Stream, StreamExt, stream};
 use base64::{Engine as _, engine::general_purpose};
+use tokio_util::sync::CancellationToken;
 
-use crate::core::{Message, ToolCall, ChatStreamItem, PullProgress, ModelInfo, Tool, MonoModel};
-use crate::providers::ollama::{OllamaClient, Model};
+use crate::core::{Message, ToolCall, ChatStreamItem, ChatResult, ChatOptions, ResponseFormat, PullProgress, ModelInfo, Tool, MonoModel, ProviderCapabilities, TokenUsage, UsageSink, UsageTracker, PriceTable, PiiConfig, PiiRedactor, RawChunkHook, ImageFormat, AIRequestError, is_image_url, is_animated_image};
+use crate::core::trace::trace_chat_stream;
+use crate::providers::ollama::{OllamaClient, OllamaOptions, Model};
 use crate::providers::anthropic::AnthropicClient;
 use crate::providers::openai::OpenAIClient;
 
+// Per image size limits of the cloud providers
+const ANTHROPIC_MAX_IMAGE_BYTES: usize = 5 * 1024 * 1024;
+const OPENAI_MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+// OpenAI-compatible services with their own constructor
+pub(super) const OPENROUTER_BASE_URL: &str = "https://openrouter.ai/api/v1";
+pub(super) const GROQ_BASE_URL: &str = "https://api.groq.com/openai/v1";
+pub(super) const MISTRAL_BASE_URL: &str = "https://api.mistral.ai/v1";
+
 pub enum Provider {
     Ollama(OllamaClient),
     Anthropic(AnthropicClient),
@@ -15,51 +29,318 @@ pub enum Provider {
 }
 
 pub struct NaoriAI {
-    provider: Provider,
+    pub(super) provider: Provider,
+    usage_sink: Option<Arc<dyn UsageSink>>,
+    usage_tracker: Option<UsageTracker>,
+    price_table: Option<Arc<PriceTable>>,
+    pii_redaction: Option<PiiConfig>,
+    pub(super) max_tool_iterations: usize,
+    default_options: ChatOptions, // Sampling settings for requests that leave them unset
 }
 
 impl NaoriAI {
-    /// Create Ollama client with endpoint URL and model name
-    pub fn ollama(endpoint: String, model: String) -> Self {
+    pub(super) fn from_provider(provider: Provider) -> Self {
         Self {
-            provider: Provider::Ollama(OllamaClient::new(endpoint, model)),
+            provider,
+            usage_sink: None,
+            usage_tracker: None,
+            price_table: None,
+            pii_redaction: None,
+            max_tool_iterations: 10,
+            default_options: ChatOptions::default(),
         }
     }
 
+    /// Create Ollama client with endpoint URL and model name
+    pub fn ollama(endpoint: String, model: String) -> Self {
+        Self::from_provider(Provider::Ollama(OllamaClient::new(endpoint, model)))
+    }
+
     /// Create Anthropic client with API key and model name
     pub fn anthropic(api_key: String, model: String) -> Self {
-        Self {
-            provider: Provider::Anthropic(AnthropicClient::new(api_key, model)),
-        }
+        Self::from_provider(Provider::Anthropic(AnthropicClient::new(api_key, model)))
+    }
+
+    /// Create Anthropic client with custom base URL (for gateways, corporate proxies, etc.)
+    pub fn anthropic_custom(api_key: String, model: String, base_url: String) -> Self {
+        Self::from_provider(Provider::Anthropic(AnthropicClient::with_base_url(api_key, model, base_url)))
     }
 
     /// Create OpenAI client with API key and model name
     pub fn openai(api_key: String, model: String) -> Self {
-        Self {
-            provider: Provider::OpenAI(OpenAIClient::new(api_key, model)),
-        }
+        Self::from_provider(Provider::OpenAI(OpenAIClient::new(api_key, model)))
     }
 
     /// Create OpenRouter client with API key and model name (wraps OpenAI with OpenRouter base URL)
     pub fn openrouter(api_key: String, model: String) -> Self {
-        Self {
-            provider: Provider::OpenAI(OpenAIClient::with_base_url(
-                api_key,
-                model,
-                "https://openrouter.ai/api/v1".to_string(),
-            )),
-        }
+        Self::from_provider(Provider::OpenAI(OpenAIClient::with_base_url(
+            api_key,
+            model,
+            OPENROUTER_BASE_URL.to_string(),
+        )))
+    }
+
+    /// Create Groq client with API key and model name (wraps OpenAI with Groq's base URL and strict tools off)
+    pub fn groq(api_key: String, model: String) -> Self {
+        let mut client = OpenAIClient::with_base_url(api_key, model, GROQ_BASE_URL.to_string());
+        client.set_strict_tools(false);
+        Self::from_provider(Provider::OpenAI(client))
+    }
+
+    /// Create Mistral (La Plateforme) client with API key and model name (wraps OpenAI with Mistral's
+    /// base URL, leaving out the `additionalProperties` field Mistral rejects in tool schemas)
+    pub fn mistral(api_key: String, model: String) -> Self {
+        let mut client = OpenAIClient::with_base_url(api_key, model, MISTRAL_BASE_URL.to_string());
+        client.set_strict_tools(false);
+        Self::from_provider(Provider::OpenAI(client))
     }
 
     /// Create OpenAI client with custom base URL (for vLLM, local deployments, etc.)
     pub fn openai_custom(api_key: String, model: String, base_url: String) -> Self {
-        Self {
-            provider: Provider::OpenAI(OpenAIClient::with_base_url(api_key, model, base_url)),
+        Self::from_provider(Provider::OpenAI(OpenAIClient::with_base_url(api_key, model, base_url)))
+    }
+
+    /// Create Azure OpenAI client for a deployment. `endpoint` is the resource URL, e.g.
+    /// `https://my-resource.openai.azure.com`, and the deployment name stands in for the model
+    pub fn azure_openai(endpoint: String, deployment: String, api_key: String, api_version: String) -> Self {
+        Self::from_provider(Provider::OpenAI(OpenAIClient::azure(endpoint, deployment, api_key, api_version)))
+    }
+
+    /// Create Ollama client for a server that requires a bearer token, e.g. Ollama cloud
+    /// (`https://ollama.com`) or a local server behind an authenticating reverse proxy
+    pub fn ollama_with_key(endpoint: String, model: String, api_key: String) -> Self {
+        let mut client = OllamaClient::new(endpoint, model);
+        client.set_api_key(Some(api_key));
+        Self::from_provider(Provider::Ollama(client))
+    }
+
+    /// Create Ollama client that sends its requests through `client`, e.g. one with a proxy,
+    /// custom root certificates or timeouts, or a connection pool shared with other clients
+    pub fn ollama_with_client(client: reqwest::Client, endpoint: String, model: String) -> Self {
+        Self::from_provider(Provider::Ollama(OllamaClient::with_client(client, endpoint, model)))
+    }
+
+    /// Create Anthropic client that sends its requests through `client`
+    pub fn anthropic_with_client(client: reqwest::Client, api_key: String, model: String) -> Self {
+        Self::from_provider(Provider::Anthropic(AnthropicClient::with_client(
+            client,
+            api_key,
+            model,
+            crate::providers::anthropic::client::DEFAULT_BASE_URL.to_string(),
+        )))
+    }
+
+    /// Create OpenAI client that sends its requests through `client`
+    pub fn openai_with_client(client: reqwest::Client, api_key: String, model: String) -> Self {
+        Self::from_provider(Provider::OpenAI(OpenAIClient::with_client(
+            client,
+            api_key,
+            model,
+            crate::providers::openai::client::DEFAULT_BASE_URL.to_string(),
+        )))
+    }
+
+    /// Create OpenAI-compatible client with custom base URL that sends its requests through `client`
+    pub fn openai_custom_with_client(client: reqwest::Client, api_key: String, model: String, base_url: String) -> Self {
+        Self::from_provider(Provider::OpenAI(OpenAIClient::with_client(client, api_key, model, base_url)))
+    }
+
+    /// Record token usage to `sink` whenever a chat stream reports it
+    pub fn set_usage_sink(&mut self, sink: Box<dyn UsageSink>) {
+        self.usage_sink = Some(Arc::from(sink));
+    }
+
+    /// Stop recording token usage
+    pub fn clear_usage_sink(&mut self) {
+        self.usage_sink = None;
+    }
+
+    /// Add up token usage and cost in `tracker`, next to the usage sink. Once the tracker's limit is
+    /// reached, further chat requests fail with `AIRequestError::BudgetExceeded` before being sent
+    pub fn set_usage_tracker(&mut self, tracker: UsageTracker) {
+        self.usage_tracker = Some(tracker);
+    }
+
+    pub fn usage_tracker(&self) -> Option<&UsageTracker> {
+        self.usage_tracker.as_ref()
+    }
+
+    pub fn clear_usage_tracker(&mut self) {
+        self.usage_tracker = None;
+    }
+
+    fn check_usage_budget(&self) -> Result<(), AIRequestError> {
+        match &self.usage_tracker {
+            Some(tracker) if tracker.limit_exceeded() => {
+                Err(AIRequestError::BudgetExceeded { totals: tracker.totals() })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Provider name as used in MonoModel.provider and usage records
+    pub fn provider_name(&self) -> &'static str {
+        match &self.provider {
+            Provider::Ollama(_) => "Ollama",
+            Provider::Anthropic(_) => "Anthropic",
+            Provider::OpenAI(_) => "OpenAI",
+        }
+    }
+
+    /// Estimate `TokenUsage.cost_usd` from this table when the provider doesn't report a cost
+    pub fn set_price_table(&mut self, price_table: PriceTable) {
+        self.price_table = Some(Arc::new(price_table));
+    }
+
+    pub fn clear_price_table(&mut self) {
+        self.price_table = None;
+    }
+
+    /// Mask emails, phone numbers and card numbers in streamed content. Best-effort, pattern based
+    pub fn set_pii_redaction(&mut self, config: PiiConfig) {
+        self.pii_redaction = Some(config);
+    }
+
+    pub fn clear_pii_redaction(&mut self) {
+        self.pii_redaction = None;
+    }
+
+    /// Run streamed content through the PII redactor when one is configured
+    fn redact_pii(
+        &self,
+        stream: Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>> {
+        let Some(config) = self.pii_redaction.clone() else {
+            return stream;
+        };
+        let redactor = Arc::new(Mutex::new(PiiRedactor::new(config)));
+        let leftover = redactor.clone();
+        // Set once a done item or a fatal error ended the stream
+        let ended = Arc::new(AtomicBool::new(false));
+        let ended_tail = ended.clone();
+
+        let redacted = stream.map(move |mut item| {
+            match &mut item {
+                Ok(item) => {
+                    let mut redactor = redactor.lock().unwrap();
+                    item.content = redactor.process_chunk(&item.content);
+                    if item.done {
+                        item.content.push_str(&redactor.flush());
+                        ended.store(true, Ordering::Relaxed);
+                    }
+                }
+                Err(error) if error.is_fatal() => ended.store(true, Ordering::Relaxed),
+                Err(_) => {}
+            }
+            item
+        });
+        // Streams that end without a done item or an error still release the held back text
+        let tail = stream::once(async move {
+            if ended_tail.load(Ordering::Relaxed) {
+                String::new()
+            } else {
+                leftover.lock().unwrap().flush()
+            }
+        })
+        .filter_map(|rest| async move {
+            (!rest.is_empty()).then(|| {
+                Ok(ChatStreamItem {
+                    content: rest,
+                    tool_calls: None,
+                    done: true,
+                    usage: None,
+                    finish_reason: None,
+                    reasoning: None,
+                    filtered: None,
+                    citations: None,
+                    timings: None,
+                })
+            })
+        });
+        Box::pin(redacted.chain(tail))
+    }
+
+    /// Wrap a chat stream in a `chat_request` tracing span, a no-op without the `tracing` feature
+    fn trace_chat(
+        &self,
+        stream: Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>,
+        started: Instant,
+    ) -> Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>> {
+        trace_chat_stream(stream, self.provider_name(), self.model(), started)
+    }
+
+    /// Fill in estimated costs and forward usage reported by a chat stream to the configured usage sink
+    fn track_usage(
+        &self,
+        stream: Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>,
+    ) -> Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>> {
+        if self.usage_sink.is_none() && self.usage_tracker.is_none() && self.price_table.is_none() {
+            return stream;
+        }
+        let sink = self.usage_sink.clone();
+        let tracker = self.usage_tracker.clone();
+        let price_table = self.price_table.clone();
+        let provider = self.provider_name();
+        let model = self.model().to_string();
+
+        Box::pin(stream.map(move |mut item| {
+            if let Ok(item) = &mut item
+                && let Some(usage) = &mut item.usage
+            {
+                account_usage(usage, provider, &model, sink.as_deref(), tracker.as_ref(), price_table.as_deref());
+            }
+            item
+        }))
+    }
+
+    // Ollama's /api/generate doesn't go through the chat stream, so the budget check and usage
+    // accounting of `track_usage` happen here
+    async fn ollama_generate(
+        &self,
+        client: &OllamaClient,
+        prompt: &str,
+        images: Vec<String>,
+        options: Option<OllamaOptions>,
+    ) -> Result<(String, Option<TokenUsage>), AIRequestError> {
+        self.check_usage_budget()?;
+        let options = match options {
+            Some(options) => Some(options.with_chat_defaults(&self.default_options)),
+            None => OllamaOptions::from_chat_options(&self.default_options),
+        };
+        let (response, mut usage) = client.generate_with_images_and_usage(prompt, images, options).await?;
+        if let Some(usage) = &mut usage {
+            account_usage(usage, self.provider_name(), self.model(), self.usage_sink.as_deref(), self.usage_tracker.as_ref(), self.price_table.as_deref());
         }
+        Ok((response, usage))
+    }
+
+    async fn ollama_generate_stream(
+        &self,
+        client: &OllamaClient,
+        prompt: &str,
+        images: Vec<String>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AIRequestError>> + Send>>, AIRequestError> {
+        self.check_usage_budget()?;
+        let options = OllamaOptions::from_chat_options(&self.default_options);
+        let stream = client.generate_stream_with_usage(prompt, images, options).await?;
+        let sink = self.usage_sink.clone();
+        let tracker = self.usage_tracker.clone();
+        let price_table = self.price_table.clone();
+        let provider = self.provider_name();
+        let model = self.model().to_string();
+
+        Ok(Box::pin(stream.map(move |item| {
+            item.map(|(text, usage)| {
+                if let Some(mut usage) = usage {
+                    account_usage(&mut usage, provider, &model, sink.as_deref(), tracker.as_ref(), price_table.as_deref());
+                }
+                text
+            })
+        })))
     }
 
     /// Add function tool to client. Automatically enables fallback mode for non-supporting models
-    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), Box<dyn Error>> {
+    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), AIRequestError> {
         match &mut self.provider {
             Provider::Ollama(client) => client.add_tool(tool).await,
             Provider::Anthropic(client) => client.add_tool(tool).await,
@@ -67,6 +348,33 @@ impl NaoriAI {
         }
     }
 
+    /// Remove a registered tool by name, `false` if there was none
+    pub fn remove_tool(&mut self, name: &str) -> bool {
+        match &mut self.provider {
+            Provider::Ollama(client) => client.remove_tool(name),
+            Provider::Anthropic(client) => client.remove_tool(name),
+            Provider::OpenAI(client) => client.remove_tool(name),
+        }
+    }
+
+    /// Remove all registered tools
+    pub fn clear_tools(&mut self) {
+        match &mut self.provider {
+            Provider::Ollama(client) => client.clear_tools(),
+            Provider::Anthropic(client) => client.clear_tools(),
+            Provider::OpenAI(client) => client.clear_tools(),
+        }
+    }
+
+    /// Names of the registered tools
+    pub fn list_tools(&self) -> Vec<&str> {
+        match &self.provider {
+            Provider::Ollama(client) => client.list_tools(),
+            Provider::Anthropic(client) => client.list_tools(),
+            Provider::OpenAI(client) => client.list_tools(),
+        }
+    }
+
     /// Check if client is using fallback tool calling (XML prompting vs native tools)
     pub async fn is_fallback_mode(&self) -> bool {
         match &self.provider {
@@ -85,6 +393,99 @@ impl NaoriAI {
         }
     }
 
+    /// Send a custom header with every API request, e.g. `HTTP-Referer` and `X-Title` for OpenRouter
+    /// app attribution. Fails for invalid names or values and for headers the provider sets itself
+    /// (`Authorization` on OpenAI, `x-api-key`, `anthropic-version` and `anthropic-beta` on Anthropic, `Content-Type` on all)
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), AIRequestError> {
+        match &mut self.provider {
+            Provider::Ollama(client) => client.set_header(name, value),
+            Provider::Anthropic(client) => client.set_header(name, value),
+            Provider::OpenAI(client) => client.set_header(name, value),
+        }
+    }
+
+    /// Set several custom headers at once, stopping at the first invalid one
+    pub fn set_headers<K: AsRef<str>, V: AsRef<str>>(&mut self, headers: impl IntoIterator<Item = (K, V)>) -> Result<(), AIRequestError> {
+        for (name, value) in headers {
+            self.set_header(name.as_ref(), value.as_ref())?;
+        }
+        Ok(())
+    }
+
+    /// Call `hook` with every raw line of chat responses before it is parsed (SSE lines for
+    /// OpenAI and Anthropic, JSON lines for Ollama), e.g. to capture unexpected payloads
+    pub fn on_raw_chunk(&mut self, hook: impl Fn(&str) + Send + Sync + 'static) {
+        self.set_raw_chunk_hook(Some(Arc::new(hook)));
+    }
+
+    /// Stop passing raw response lines to the hook set with `on_raw_chunk`
+    pub fn clear_raw_chunk_hook(&mut self) {
+        self.set_raw_chunk_hook(None);
+    }
+
+    fn set_raw_chunk_hook(&mut self, hook: Option<RawChunkHook>) {
+        match &mut self.provider {
+            Provider::Ollama(client) => client.set_raw_chunk_hook(hook),
+            Provider::Anthropic(client) => client.set_raw_chunk_hook(hook),
+            Provider::OpenAI(client) => client.set_raw_chunk_hook(hook),
+        }
+    }
+
+    /// Constrain responses to plain JSON or a JSON schema (Anthropic falls back to prompt instructions)
+    pub fn set_response_format(&mut self, response_format: ResponseFormat) {
+        match &mut self.provider {
+            Provider::Ollama(client) => client.set_response_format(response_format),
+            Provider::Anthropic(client) => client.set_response_format(response_format),
+            Provider::OpenAI(client) => client.set_response_format(response_format),
+        }
+    }
+
+    /// Move this client onto another provider, e.g. for a provider toggle in settings. Only the
+    /// provider client of `new_provider` is used.
+    ///
+    /// Carried over:
+    /// - tools, response format, debug mode and the raw chunk hook
+    /// - default options such as temperature and max tokens, see `set_default_options`
+    /// - usage sink, usage tracker, price table, PII redaction and the tool iteration cap
+    ///
+    /// Taken from `new_provider`, so reset unless set there: endpoint or base URL, API key, custom
+    /// headers, Anthropic tool choice, thinking budget, API version and beta features, OpenAI lenient
+    /// streaming, done markers and strict tools, and Ollama keep-alive, context window, filtered
+    /// capture and fallback tool settings
+    pub fn switch_provider(&mut self, new_provider: NaoriAI) {
+        let debug_mode = self.debug_mode();
+        let (tools, response_format, raw_chunk_hook) = match &mut self.provider {
+            Provider::Ollama(client) => (client.take_tools(), client.response_format().clone(), client.raw_chunk_hook()),
+            Provider::Anthropic(client) => (client.take_tools(), client.response_format().clone(), client.raw_chunk_hook()),
+            Provider::OpenAI(client) => (client.take_tools(), client.response_format().clone(), client.raw_chunk_hook()),
+        };
+
+        self.provider = new_provider.provider;
+        self.set_debug_mode(debug_mode);
+        self.set_response_format(response_format);
+        self.set_raw_chunk_hook(raw_chunk_hook);
+        match &mut self.provider {
+            Provider::Ollama(client) => client.extend_tools(tools),
+            Provider::Anthropic(client) => client.extend_tools(tools),
+            Provider::OpenAI(client) => client.extend_tools(tools),
+        }
+    }
+
+    /// Settings such as temperature and max tokens for every chat and generate request, used where
+    /// the request's own `ChatOptions` leave them unset
+    pub fn set_default_options(&mut self, options: ChatOptions) {
+        self.default_options = options;
+    }
+
+    pub fn default_options(&self) -> &ChatOptions {
+        &self.default_options
+    }
+
+    /// Cap on tool rounds `chat_with_tools` runs before returning, 10 by default
+    pub fn set_max_tool_iterations(&mut self, max_iterations: usize) {
+        self.max_tool_iterations = max_iterations;
+    }
+
     /// Check if debug mode is enabled
     pub fn debug_mode(&self) -> bool {
         match &self.provider {
@@ -95,7 +496,7 @@ impl NaoriAI {
     }
 
     /// Check if model supports native tool calling by examining template
-    pub async fn supports_tool_calls(&self) -> Result<bool, Box<dyn Error>> {
+    pub async fn supports_tool_calls(&self) -> Result<bool, AIRequestError> {
         match &self.provider {
             Provider::Ollama(client) => client.supports_tool_calls().await,
             Provider::Anthropic(client) => client.supports_tool_calls().await,
@@ -103,28 +504,170 @@ impl NaoriAI {
         }
     }
 
+    /// Whether the current model accepts images, to check before sending them. Ollama reads the
+    /// model's capabilities from `/api/show` and OpenRouter its model listing; for other models it is
+    /// guessed from the name, see `MonoModel::is_vision_capable`
+    pub async fn supports_vision(&self) -> Result<bool, AIRequestError> {
+        let listed = match &self.provider {
+            Provider::Ollama(client) => return client.supports_vision().await,
+            Provider::OpenAI(client) if client.is_openrouter() => {
+                self.get_available_models().await?.into_iter().find(|model| model.id == self.model())
+            }
+            _ => None,
+        };
+        let model = listed.unwrap_or_else(|| MonoModel {
+            id: self.model().to_string(),
+            name: self.model().to_string(),
+            provider: self.provider_name().to_string(),
+            size: None,
+            created: None,
+            context_length: None,
+            capabilities: None,
+            pricing: None,
+        });
+        Ok(model.is_vision_capable())
+    }
+
     /// Send chat request with real-time streaming response
     pub async fn send_chat_request(
         &self,
         messages: &[Message],
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.send_chat_request_with_options(messages, ChatOptions::default()).await
+    }
+
+    /// Send chat request with per-request settings overriding the client defaults
+    pub async fn send_chat_request_with_options(
+        &self,
+        messages: &[Message],
+        options: ChatOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.check_usage_budget()?;
+        let started = Instant::now();
+        let options = options.with_defaults(&self.default_options);
+        let stream = match &self.provider {
+            Provider::Ollama(client) => client.send_chat_request_with_chat_options(messages, &options).await?,
+            Provider::Anthropic(client) => client.send_chat_request_with_options(messages, &options).await?,
+            Provider::OpenAI(client) => client.send_chat_request_with_options(messages, &options).await?,
+        };
+        Ok(self.redact_pii(self.track_usage(self.trace_chat(stream, started))))
+    }
+
+    /// Like `send_chat_request`, taking the history by value. Ollama then adds the tool context and
+    /// downloaded images to the messages in place instead of copying the whole conversation first,
+    /// which matters for long histories; the cloud providers convert the messages either way
+    pub async fn send_chat_request_owned(
+        &self,
+        messages: Vec<Message>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.send_chat_request_owned_with_options(messages, ChatOptions::default()).await
+    }
+
+    /// `send_chat_request_with_options` taking the history by value, see `send_chat_request_owned`
+    pub async fn send_chat_request_owned_with_options(
+        &self,
+        messages: Vec<Message>,
+        options: ChatOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.check_usage_budget()?;
+        let started = Instant::now();
+        let options = options.with_defaults(&self.default_options);
+        let stream = match &self.provider {
+            Provider::Ollama(client) => client.send_chat_request_owned_with_chat_options(messages, &options).await?,
+            Provider::Anthropic(client) => client.send_chat_request_with_options(&messages, &options).await?,
+            Provider::OpenAI(client) => client.send_chat_request_with_options(&messages, &options).await?,
+        };
+        Ok(self.redact_pii(self.track_usage(self.trace_chat(stream, started))))
+    }
+
+    /// Streaming chat with Ollama's own options, e.g. `num_ctx`, without going through `as_ollama()`.
+    /// Cloud providers receive the options with a cross-provider equivalent (temperature, top_p,
+    /// num_predict as max_tokens, stop, seed and penalties), the rest is ignored
+    pub async fn send_chat_request_with_ollama_options(
+        &self,
+        messages: &[Message],
+        options: OllamaOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.check_usage_budget()?;
+        let started = Instant::now();
+        let defaults = &self.default_options;
+        let stream = match &self.provider {
+            Provider::Ollama(client) => client.send_chat_request_stream_with_options(messages, Some(options.with_chat_defaults(defaults))).await?,
+            Provider::Anthropic(client) => client.send_chat_request_with_options(messages, &options.to_chat_options().with_defaults(defaults)).await?,
+            Provider::OpenAI(client) => client.send_chat_request_with_options(messages, &options.to_chat_options().with_defaults(defaults)).await?,
+        };
+        Ok(self.redact_pii(self.track_usage(self.trace_chat(stream, started))))
+    }
+
+    /// The JSON body a chat request with these messages would send to the provider, including tools,
+    /// the system prompt and Ollama's fallback tool context, without making the request
+    pub async fn build_request_json(&self, messages: &[Message]) -> Result<serde_json::Value, AIRequestError> {
+        self.build_request_json_with_options(messages, ChatOptions::default()).await
+    }
+
+    /// Like `build_request_json`, with the per-request settings of `send_chat_request_with_options`
+    pub async fn build_request_json_with_options(
+        &self,
+        messages: &[Message],
+        options: ChatOptions,
+    ) -> Result<serde_json::Value, AIRequestError> {
+        let options = options.with_defaults(&self.default_options);
         match &self.provider {
-            Provider::Ollama(client) => client.send_chat_request(messages).await,
-            Provider::Anthropic(client) => client.send_chat_request(messages).await,
-            Provider::OpenAI(client) => client.send_chat_request(messages).await,
+            Provider::Ollama(client) => client.build_request_json(messages, &options).await,
+            Provider::Anthropic(client) => client.build_request_json(messages, &options),
+            Provider::OpenAI(client) => client.build_request_json(messages, &options),
         }
     }
 
-    /// Send chat request without streaming, returns complete response and tool calls
+    /// Streaming chat that ends as soon as `token` is cancelled, e.g. from a "stop generating" button.
+    /// The underlying HTTP response is dropped at that point, closing the connection
+    pub async fn send_chat_request_cancellable(
+        &self,
+        messages: &[Message],
+        token: CancellationToken,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        let stream = self.send_chat_request(messages).await?;
+        Ok(Box::pin(stream.take_until(token.cancelled_owned())))
+    }
+
+    /// Send chat request without streaming, returns complete response and tool calls.
+    /// Use `chat` to also get usage and the finish reason
     pub async fn send_chat_request_no_stream(
         &self,
         messages: &[Message],
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
-        match &self.provider {
-            Provider::Ollama(client) => client.send_chat_request_no_stream(messages).await,
-            Provider::Anthropic(client) => client.send_chat_request_no_stream(messages).await,
-            Provider::OpenAI(client) => client.send_chat_request_no_stream(messages).await,
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
+        let result = self.chat(messages).await?;
+        Ok((result.content, result.tool_calls))
+    }
+
+    /// Send chat request and collect the full response, including finish reason and usage
+    pub async fn chat(&self, messages: &[Message]) -> Result<ChatResult, AIRequestError> {
+        let stream = self.send_chat_request(messages).await?;
+        ChatResult::collect(stream).await
+    }
+
+    /// Send chat request and pass each streamed item to `on_item` as it arrives, for callers that
+    /// prefer a callback to polling a stream (GUIs, FFI). Returning `ControlFlow::Break(())` aborts
+    /// the request; the result then holds what arrived up to that point
+    pub async fn stream_chat_with_callback<F>(&self, messages: &[Message], mut on_item: F) -> Result<ChatResult, AIRequestError>
+    where
+        F: FnMut(&ChatStreamItem) -> ControlFlow<()>,
+    {
+        let mut stream = self.send_chat_request(messages).await?;
+        let mut result = ChatResult::default();
+        while let Some(item) = stream.next().await {
+            let item = match item {
+                Ok(item) => item,
+                Err(error) if !error.is_fatal() => continue,
+                Err(error) => return Err(error),
+            };
+            let flow = on_item(&item);
+            // Dropping the stream closes the connection
+            if result.push_item(item) || flow.is_break() {
+                break;
+            }
         }
+        Ok(result)
     }
 
     /// Send chat request with images from file paths, returns real-time streaming response
@@ -132,9 +675,15 @@ impl NaoriAI {
         &self,
         messages: &[Message],
         image_paths: Vec<String>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.check_usage_budget()?;
+        let started = Instant::now();
         match &self.provider {
-            Provider::Ollama(client) => client.send_chat_request_with_images(messages, image_paths).await,
+            Provider::Ollama(client) => {
+                let options = OllamaOptions::from_chat_options(&self.default_options);
+                let stream = client.send_chat_request_with_images_stream_and_options(messages, image_paths, options).await?;
+                Ok(self.redact_pii(self.track_usage(self.trace_chat(stream, started))))
+            }
             Provider::Anthropic(_) => {
                 // For Anthropic, images should be encoded in the messages directly
                 // This method is provided for backward compatibility with Ollama-style usage
@@ -146,6 +695,7 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request(&messages_with_images).await
             }
@@ -159,6 +709,7 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request(&messages_with_images).await
             }
@@ -170,9 +721,13 @@ impl NaoriAI {
         &self,
         messages: &[Message],
         image_paths: Vec<String>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         match &self.provider {
-            Provider::Ollama(client) => client.send_chat_request_with_images_no_stream(messages, image_paths).await,
+            Provider::Ollama(_) => {
+                let stream = self.send_chat_request_with_images(messages, image_paths).await?;
+                let result = ChatResult::collect(stream).await?;
+                Ok((result.content, result.tool_calls))
+            }
             Provider::Anthropic(_) => {
                 // For Anthropic, images should be encoded in the messages directly
                 let mut messages_with_images = messages.to_vec();
@@ -183,6 +738,7 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request_no_stream(&messages_with_images).await
             }
@@ -196,6 +752,7 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request_no_stream(&messages_with_images).await
             }
@@ -207,9 +764,15 @@ impl NaoriAI {
         &self,
         messages: &[Message],
         images_data: Vec<Vec<u8>>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.check_usage_budget()?;
+        let started = Instant::now();
         match &self.provider {
-            Provider::Ollama(client) => client.send_chat_request_with_images_data(messages, images_data).await,
+            Provider::Ollama(client) => {
+                let options = OllamaOptions::from_chat_options(&self.default_options);
+                let stream = client.send_chat_request_with_images_data_stream_and_options(messages, images_data, options).await?;
+                Ok(self.redact_pii(self.track_usage(self.trace_chat(stream, started))))
+            }
             Provider::Anthropic(_) => {
                 // For Anthropic, images should be encoded in the messages directly
                 let mut messages_with_images = messages.to_vec();
@@ -220,6 +783,7 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request(&messages_with_images).await
             }
@@ -233,6 +797,7 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request(&messages_with_images).await
             }
@@ -244,9 +809,13 @@ impl NaoriAI {
         &self,
         messages: &[Message],
         images_data: Vec<Vec<u8>>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         match &self.provider {
-            Provider::Ollama(client) => client.send_chat_request_with_images_data_no_stream(messages, images_data).await,
+            Provider::Ollama(_) => {
+                let stream = self.send_chat_request_with_image_data(messages, images_data).await?;
+                let result = ChatResult::collect(stream).await?;
+                Ok((result.content, result.tool_calls))
+            }
             Provider::Anthropic(_) => {
                 // For Anthropic, images should be encoded in the messages directly
                 let mut messages_with_images = messages.to_vec();
@@ -257,6 +826,7 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request_no_stream(&messages_with_images).await
             }
@@ -270,57 +840,169 @@ impl NaoriAI {
                         encoded_images.push(encoded);
                     }
                     last_message.images = Some(encoded_images);
+                    last_message.image_media_types = None;
                 }
                 self.send_chat_request_no_stream(&messages_with_images).await
             }
         }
     }
 
+    /// Send chat request with images given as http(s) URLs, returns real-time streaming response.
+    /// OpenAI and Anthropic fetch the URLs themselves; for Ollama they are downloaded and encoded first.
+    /// Entries that aren't URLs are read from disk, so local and remote images can be mixed.
+    pub async fn send_chat_request_with_image_urls(
+        &self,
+        messages: &[Message],
+        image_urls: Vec<String>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        let messages_with_images = self.attach_image_urls(messages, image_urls).await?;
+        self.send_chat_request(&messages_with_images).await
+    }
+
+    /// Send chat request with images given as http(s) URLs, returns complete response and tool calls
+    pub async fn send_chat_request_with_image_urls_no_stream(
+        &self,
+        messages: &[Message],
+        image_urls: Vec<String>,
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
+        let messages_with_images = self.attach_image_urls(messages, image_urls).await?;
+        self.send_chat_request_no_stream(&messages_with_images).await
+    }
+
+    async fn attach_image_urls(&self, messages: &[Message], image_urls: Vec<String>) -> Result<Vec<Message>, AIRequestError> {
+        let mut messages_with_images = messages.to_vec();
+        if let Some(last_message) = messages_with_images.last_mut() {
+            let mut images = Vec::new();
+            for image in image_urls {
+                if is_image_url(&image) {
+                    images.push(image);
+                } else {
+                    images.push(self.encode_image_file(&image).await?);
+                }
+            }
+            last_message.images = Some(images);
+        }
+        Ok(messages_with_images)
+    }
+
     /// Generate single completion from prompt without conversation context
-    pub async fn generate(&self, prompt: &str) -> Result<String, Box<dyn Error>> {
+    pub async fn generate(&self, prompt: &str) -> Result<String, AIRequestError> {
         match &self.provider {
-            Provider::Ollama(client) => client.generate(prompt).await,
-            Provider::Anthropic(client) => {
+            Provider::Ollama(client) => Ok(self.ollama_generate(client, prompt, Vec::new(), None).await?.0),
+            Provider::Anthropic(_) => {
                 // Convert prompt to messages format for Anthropic
                 let messages = vec![Message {
                     role: "user".to_string(),
                     content: prompt.to_string(),
-                    images: None,
-                    tool_calls: None,
+                    ..Default::default()
                 }];
-                let (response, _) = client.send_chat_request_no_stream(&messages).await?;
+                let (response, _) = self.send_chat_request_no_stream(&messages).await?;
                 Ok(response)
             }
-            Provider::OpenAI(client) => {
+            Provider::OpenAI(_) => {
                 // Convert prompt to messages format for OpenAI
                 let messages = vec![Message {
                     role: "user".to_string(),
                     content: prompt.to_string(),
-                    images: None,
-                    tool_calls: None,
+                    ..Default::default()
                 }];
-                let (response, _) = client.send_chat_request_no_stream(&messages).await?;
+                let (response, _) = self.send_chat_request_no_stream(&messages).await?;
                 Ok(response)
             }
         }
     }
 
+    /// Generate single completion from prompt with per-request `ChatOptions`, e.g. a `seed`
+    /// for reproducible output. Settings a provider doesn't support are ignored
+    pub async fn generate_with_options(&self, prompt: &str, options: ChatOptions) -> Result<String, AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => {
+                let options = OllamaOptions::from_chat_options(&options);
+                Ok(self.ollama_generate(client, prompt, Vec::new(), options).await?.0)
+            }
+            Provider::Anthropic(_) | Provider::OpenAI(_) => {
+                let messages = vec![Message {
+                    role: "user".to_string(),
+                    content: prompt.to_string(),
+                    ..Default::default()
+                }];
+                let stream = self.send_chat_request_with_options(&messages, options).await?;
+                Ok(ChatResult::collect(stream).await?.content)
+            }
+        }
+    }
+
+    /// Generate single completion for a prompt about `images` (base64 data or image URLs, as in
+    /// `Message.images`), e.g. captions or OCR without a conversation. Needs a vision model
+    pub async fn generate_with_images(&self, prompt: &str, images: Vec<String>) -> Result<String, AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => Ok(self.ollama_generate(client, prompt, images, None).await?.0),
+            Provider::Anthropic(_) | Provider::OpenAI(_) => {
+                let messages = vec![Message {
+                    role: "user".to_string(),
+                    content: prompt.to_string(),
+                    images: Some(images),
+                    ..Default::default()
+                }];
+                let (response, _) = self.send_chat_request_no_stream(&messages).await?;
+                Ok(response)
+            }
+        }
+    }
+
+    /// Streaming variant of `generate_with_images`
+    pub async fn generate_stream_with_images(
+        &self,
+        prompt: &str,
+        images: Vec<String>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AIRequestError>> + Send>>, AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => self.ollama_generate_stream(client, prompt, images).await,
+            Provider::Anthropic(_) | Provider::OpenAI(_) => {
+                let messages = vec![Message {
+                    role: "user".to_string(),
+                    content: prompt.to_string(),
+                    images: Some(images),
+                    ..Default::default()
+                }];
+                let stream = self.send_chat_request(&messages).await?;
+                Ok(Box::pin(stream.map(|item| item.map(|chat_item| chat_item.content))))
+            }
+        }
+    }
+
+    /// Generate single completion from prompt along with its token usage, which also goes to the
+    /// usage sink. Ollama reports the eval counts of `/api/generate`, the others the stream's final usage
+    pub async fn generate_with_usage(&self, prompt: &str) -> Result<(String, Option<TokenUsage>), AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => self.ollama_generate(client, prompt, Vec::new(), None).await,
+            Provider::Anthropic(_) | Provider::OpenAI(_) => {
+                let messages = vec![Message {
+                    role: "user".to_string(),
+                    content: prompt.to_string(),
+                    ..Default::default()
+                }];
+                let result = ChatResult::collect(self.send_chat_request(&messages).await?).await?;
+                Ok((result.content, result.usage))
+            }
+        }
+    }
+
     /// Generate streaming completion from prompt without conversation context
     pub async fn generate_stream(
         &self,
         prompt: &str,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AIRequestError>> + Send>>, AIRequestError> {
         match &self.provider {
-            Provider::Ollama(client) => client.generate_stream(prompt).await,
-            Provider::Anthropic(client) => {
+            Provider::Ollama(client) => self.ollama_generate_stream(client, prompt, Vec::new()).await,
+            Provider::Anthropic(_) => {
                 // Convert prompt to messages format for Anthropic and convert stream
                 let messages = vec![Message {
                     role: "user".to_string(),
                     content: prompt.to_string(),
-                    images: None,
-                    tool_calls: None,
+                    ..Default::default()
                 }];
-                let stream = client.send_chat_request(&messages).await?;
+                let stream = self.send_chat_request(&messages).await?;
                 let mapped_stream = stream.map(|item| {
                     match item {
                         Ok(chat_item) => Ok(chat_item.content),
@@ -329,15 +1011,14 @@ impl NaoriAI {
                 });
                 Ok(Box::pin(mapped_stream))
             }
-            Provider::OpenAI(client) => {
+            Provider::OpenAI(_) => {
                 // Convert prompt to messages format for OpenAI and convert stream
                 let messages = vec![Message {
                     role: "user".to_string(),
                     content: prompt.to_string(),
-                    images: None,
-                    tool_calls: None,
+                    ..Default::default()
                 }];
-                let stream = client.send_chat_request(&messages).await?;
+                let stream = self.send_chat_request(&messages).await?;
                 let mapped_stream = stream.map(|item| {
                     match item {
                         Ok(chat_item) => Ok(chat_item.content),
@@ -349,8 +1030,22 @@ impl NaoriAI {
         }
     }
 
+    /// Streaming completion of a bare prompt as full `ChatStreamItem`s, keeping usage, tool calls
+    /// and finish reason that `generate_stream` drops
+    pub async fn generate_stream_items(
+        &self,
+        prompt: &str,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        let messages = vec![Message {
+            role: "user".to_string(),
+            content: prompt.to_string(),
+            ..Default::default()
+        }];
+        self.send_chat_request(&messages).await
+    }
+
     /// Get available models from any provider
-    pub async fn get_available_models(&self) -> Result<Vec<MonoModel>, Box<dyn Error>> {
+    pub async fn get_available_models(&self) -> Result<Vec<MonoModel>, AIRequestError> {
         match &self.provider {
             Provider::Ollama(client) => {
                 let models = client.list_local_models().await?;
@@ -360,6 +1055,9 @@ impl NaoriAI {
                     provider: "Ollama".to_string(),
                     size: Some(m.size),
                     created: None,
+                    context_length: None,
+                    capabilities: None,
+                    pricing: None,
                 }).collect())
             }
             Provider::Anthropic(client) => {
@@ -370,44 +1068,104 @@ impl NaoriAI {
                     provider: "Anthropic".to_string(),
                     size: None,
                     created: Some(m.created_at.parse().unwrap_or(0)),
+                    context_length: None,
+                    capabilities: None,
+                    pricing: None,
                 }).collect())
             }
             Provider::OpenAI(client) => {
                 let models = client.get_available_models().await?;
-                Ok(models.into_iter().map(|m| MonoModel {
-                    id: m.id.clone(),
-                    name: m.id,
-                    provider: "OpenAI".to_string(),
-                    size: None,
-                    created: Some(m.created),
+                let openrouter = client.is_openrouter();
+                Ok(models.into_iter().map(|m| {
+                    let pricing = if openrouter { m.openrouter_price() } else { None };
+                    // Only OpenRouter describes modalities and supported parameters
+                    let capabilities = m.architecture.as_ref().map(|architecture| ProviderCapabilities {
+                        vision: architecture.input_modalities.iter().any(|modality| modality == "image"),
+                        tools: m.supported_parameters.as_ref().is_some_and(|params| params.iter().any(|p| p == "tools")),
+                    });
+                    MonoModel {
+                        id: m.id.clone(),
+                        name: m.name.unwrap_or(m.id),
+                        provider: "OpenAI".to_string(),
+                        size: None,
+                        created: Some(m.created),
+                        context_length: m.context_length,
+                        capabilities,
+                        pricing,
+                    }
                 }).collect())
             }
         }
     }
 
     /// List locally installed models (legacy method, use get_available_models instead)
-    pub async fn list_local_models(&self) -> Result<Vec<Model>, Box<dyn Error>> {
+    pub async fn list_local_models(&self) -> Result<Vec<Model>, AIRequestError> {
         match &self.provider {
             Provider::Ollama(client) => client.list_local_models().await,
-            _ => Err("list_local_models is only supported for Ollama provider".into()),
+            _ => Err(AIRequestError::Unsupported("list_local_models is only supported for Ollama provider".to_string())),
         }
     }
 
     /// Get detailed model information including template and parameters
-    pub async fn show_model_info(&self, model_name: &str) -> Result<ModelInfo, Box<dyn Error>> {
+    pub async fn show_model_info(&self, model_name: &str) -> Result<ModelInfo, AIRequestError> {
         match &self.provider {
             Provider::Ollama(client) => client.show_model_info(model_name).await,
-            Provider::Anthropic(_) => Err("show_model_info is not supported for Anthropic provider".into()),
-            Provider::OpenAI(_) => Err("show_model_info is not supported for OpenAI provider".into()),
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("show_model_info is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("show_model_info is not supported for OpenAI provider".to_string())),
         }
     }
 
     /// Download model from provider registry (provider-specific operation)
-    pub async fn pull_model(&self, model_name: &str) -> Result<(), Box<dyn Error>> {
+    pub async fn pull_model(&self, model_name: &str) -> Result<(), AIRequestError> {
         match &self.provider {
             Provider::Ollama(client) => client.pull_model(model_name).await,
-            Provider::Anthropic(_) => Err("pull_model is not supported for Anthropic provider".into()),
-            Provider::OpenAI(_) => Err("pull_model is not supported for OpenAI provider".into()),
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("pull_model is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("pull_model is not supported for OpenAI provider".to_string())),
+        }
+    }
+
+    /// Check whether a model is installed locally, matching the exact tag (provider-specific operation)
+    pub async fn model_exists(&self, model_name: &str) -> Result<bool, AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => client.model_exists(model_name).await,
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("model_exists is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("model_exists is not supported for OpenAI provider".to_string())),
+        }
+    }
+
+    /// Copy a local model under a new name (provider-specific operation)
+    pub async fn copy_model(&self, source: &str, destination: &str) -> Result<(), AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => client.copy_model(source, destination).await,
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("copy_model is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("copy_model is not supported for OpenAI provider".to_string())),
+        }
+    }
+
+    /// Delete a locally installed model (provider-specific operation)
+    pub async fn delete_model(&self, model_name: &str) -> Result<(), AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => client.delete_model(model_name).await,
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("delete_model is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("delete_model is not supported for OpenAI provider".to_string())),
+        }
+    }
+
+    /// Load the model into memory before a latency-sensitive session (provider-specific operation)
+    pub async fn preload_model(&self) -> Result<(), AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => client.preload_model().await,
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("preload_model is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("preload_model is not supported for OpenAI provider".to_string())),
+        }
+    }
+
+    /// Unload the model to free memory right away (provider-specific operation)
+    pub async fn unload_model(&self) -> Result<(), AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => client.unload_model().await,
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("unload_model is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("unload_model is not supported for OpenAI provider".to_string())),
         }
     }
 
@@ -415,11 +1173,11 @@ impl NaoriAI {
     pub async fn pull_model_stream(
         &self,
         model_name: &str,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<PullProgress, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<PullProgress, AIRequestError>> + Send>>, AIRequestError> {
         match &self.provider {
             Provider::Ollama(client) => client.pull_model_stream(model_name).await,
-            Provider::Anthropic(_) => Err("pull_model_stream is not supported for Anthropic provider".into()),
-            Provider::OpenAI(_) => Err("pull_model_stream is not supported for OpenAI provider".into()),
+            Provider::Anthropic(_) => Err(AIRequestError::Unsupported("pull_model_stream is not supported for Anthropic provider".to_string())),
+            Provider::OpenAI(_) => Err(AIRequestError::Unsupported("pull_model_stream is not supported for OpenAI provider".to_string())),
         }
     }
 
@@ -486,14 +1244,208 @@ impl NaoriAI {
         }
     }
 
-    /// Encode image file to base64 string for use in Message.images
-    pub async fn encode_image_file(&self, path: &str) -> Result<String, Box<dyn std::error::Error>> {
+    /// Access underlying OpenAI client for provider-specific operations
+    pub fn as_openai(&self) -> Option<&OpenAIClient> {
+        match &self.provider {
+            Provider::Ollama(_) => None,
+            Provider::Anthropic(_) => None,
+            Provider::OpenAI(client) => Some(client),
+        }
+    }
+
+    /// Access underlying OpenAI client mutably for provider-specific operations
+    pub fn as_openai_mut(&mut self) -> Option<&mut OpenAIClient> {
+        match &mut self.provider {
+            Provider::Ollama(_) => None,
+            Provider::Anthropic(_) => None,
+            Provider::OpenAI(client) => Some(client),
+        }
+    }
+
+    /// Prompt tokens these messages would take, so history can be trimmed before a request fails.
+    /// Anthropic asks its count_tokens endpoint, OpenAI counts locally (exactly with the `tiktoken`
+    /// feature, estimated without), and Ollama estimates from the rendered prompt
+    pub async fn count_tokens(&self, messages: &[Message]) -> Result<u32, AIRequestError> {
+        match &self.provider {
+            Provider::Ollama(client) => client.count_tokens(messages).await,
+            Provider::Anthropic(client) => client.count_tokens(messages).await,
+            Provider::OpenAI(client) => client.count_tokens(messages).await,
+        }
+    }
+
+    /// Encode image file to base64 string for use in Message.images. Fails with
+    /// `AIRequestError::InvalidImage` when the provider doesn't accept the image's format or size.
+    /// Animated images for OpenAI are reduced to their first frame with the `image-resize` feature
+    /// and rejected without it
+    pub async fn encode_image_file(&self, path: &str) -> Result<String, AIRequestError> {
         let image_bytes = std::fs::read(path)?;
+        let image_bytes = self.prepare_image(image_bytes, path).await?;
         Ok(general_purpose::STANDARD.encode(image_bytes))
     }
 
-    /// Encode image bytes to base64 string for use in Message.images
-    pub async fn encode_image_data(&self, bytes: Vec<u8>) -> Result<String, Box<dyn std::error::Error>> {
+    /// Encode image file to base64 like `encode_image_file`, downscaled first so its longest side
+    /// is at most `max_dimension` pixels. Needs the `image-resize` feature
+    #[cfg(feature = "image-resize")]
+    pub async fn encode_image_file_resized(&self, path: &str, max_dimension: u32) -> Result<String, AIRequestError> {
+        let image_bytes = std::fs::read(path)?;
+        // Decoding and resampling are CPU bound, keep them off the async workers
+        let resized = tokio::task::spawn_blocking(move || crate::core::resize_image(&image_bytes, max_dimension))
+            .await
+            .map_err(|e| AIRequestError::Other(e.to_string()))?
+            .map_err(|message| AIRequestError::InvalidImage {
+                name: path.to_string(),
+                message,
+            })?;
+        let resized = self.prepare_image(resized, path).await?;
+        Ok(general_purpose::STANDARD.encode(resized))
+    }
+
+    /// Encode image bytes to base64 string for use in Message.images, checked like `encode_image_file`
+    pub async fn encode_image_data(&self, bytes: Vec<u8>) -> Result<String, AIRequestError> {
+        let bytes = self.prepare_image(bytes, "image data").await?;
         Ok(general_purpose::STANDARD.encode(bytes))
     }
-}
\ No newline at end of file
+
+    /// Convert what the provider can't take as is, then check the result with `check_image`
+    async fn prepare_image(&self, bytes: Vec<u8>, name: &str) -> Result<Vec<u8>, AIRequestError> {
+        #[cfg(feature = "image-resize")]
+        let bytes = if matches!(self.provider, Provider::OpenAI(_)) && is_animated_image(&bytes) {
+            tokio::task::spawn_blocking(move || crate::core::first_frame(&bytes))
+                .await
+                .map_err(|e| AIRequestError::Other(e.to_string()))?
+                .map_err(|message| AIRequestError::InvalidImage {
+                    name: name.to_string(),
+                    message,
+                })?
+        } else {
+            bytes
+        };
+        self.check_image(&bytes, name)?;
+        Ok(bytes)
+    }
+
+    /// Reject images the provider would refuse with an opaque API error. Ollama decodes
+    /// images itself and has no documented limits, so only the cloud providers are checked
+    fn check_image(&self, bytes: &[u8], name: &str) -> Result<(), AIRequestError> {
+        let (provider, max_bytes) = match &self.provider {
+            Provider::Ollama(_) => return Ok(()),
+            Provider::Anthropic(_) => ("Anthropic", ANTHROPIC_MAX_IMAGE_BYTES),
+            Provider::OpenAI(_) => ("OpenAI", OPENAI_MAX_IMAGE_BYTES),
+        };
+        let invalid = |message: String| {
+            Err(AIRequestError::InvalidImage {
+                name: name.to_string(),
+                message,
+            })
+        };
+
+        match ImageFormat::detect(bytes) {
+            Some(ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::Gif | ImageFormat::Webp) => {}
+            Some(format) => {
+                return invalid(format!(
+                    "{} is not supported by {}, convert it to JPEG, PNG, GIF or WebP",
+                    format.media_type(),
+                    provider
+                ));
+            }
+            None => return invalid("unrecognized format, expected JPEG, PNG, GIF or WebP".to_string()),
+        }
+        if matches!(self.provider, Provider::OpenAI(_)) && is_animated_image(bytes) {
+            return invalid("animated images are not supported by OpenAI, enable the image-resize feature to send the first frame".to_string());
+        }
+        if bytes.len() > max_bytes {
+            return invalid(format!(
+                "{:.1} MB exceeds the {} MB per image limit of {}",
+                bytes.len() as f64 / (1024.0 * 1024.0),
+                max_bytes / (1024 * 1024),
+                provider
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Estimate the cost of `usage` unless the provider reported one, and pass it to the usage sink and tracker
+fn account_usage(
+    usage: &mut TokenUsage,
+    provider: &str,
+    model: &str,
+    sink: Option<&dyn UsageSink>,
+    tracker: Option<&UsageTracker>,
+    price_table: Option<&PriceTable>,
+) {
+    // A cost reported by the provider (e.g. OpenRouter) wins over the estimate
+    if usage.cost_usd.is_none()
+        && let Some(table) = price_table
+    {
+        usage.cost_usd = table.estimate_cost(model, usage);
+    }
+    if let Some(sink) = sink {
+        sink.record(provider, model, usage, usage.cost_usd);
+    }
+    if let Some(tracker) = tracker {
+        tracker.add(usage);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(content: &str, done: bool) -> Result<ChatStreamItem, AIRequestError> {
+        Ok(ChatStreamItem {
+            content: content.to_string(),
+            tool_calls: None,
+            done,
+            usage: None,
+            finish_reason: None,
+            reasoning: None,
+            filtered: None,
+            citations: None,
+            timings: None,
+        })
+    }
+
+    fn content(items: &[Result<ChatStreamItem, AIRequestError>]) -> String {
+        items.iter().filter_map(|item| item.as_ref().ok()).map(|item| item.content.as_str()).collect()
+    }
+
+    async fn redacted(items: Vec<Result<ChatStreamItem, AIRequestError>>) -> Vec<Result<ChatStreamItem, AIRequestError>> {
+        let mut client = NaoriAI::ollama("http://localhost:11434".to_string(), "llama3.2".to_string());
+        client.set_pii_redaction(PiiConfig::default());
+        client.redact_pii(Box::pin(stream::iter(items))).collect().await
+    }
+
+    #[tokio::test]
+    async fn held_back_text_released_at_the_end() {
+        let items = redacted(vec![item("Write to jane", false), item(".doe@example.com", false)]).await;
+
+        assert_eq!(content(&items), "Write to [REDACTED]");
+        assert!(items.last().unwrap().as_ref().unwrap().done);
+    }
+
+    #[tokio::test]
+    async fn nothing_follows_a_fatal_error() {
+        let items = redacted(vec![item("Call 555", false), Err(AIRequestError::Other("connection reset".to_string()))]).await;
+
+        assert_eq!(items.len(), 2);
+        assert!(items[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn nothing_follows_the_done_item() {
+        let items = redacted(vec![item("Call 555 123", false), item(" 4567", true)]).await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(content(&items), "Call [REDACTED]");
+    }
+
+    #[tokio::test]
+    async fn decode_errors_do_not_end_the_stream() {
+        let items = redacted(vec![item("Mail jane", false), Err(AIRequestError::Decode("bad line".to_string())), item("@example.com", false)]).await;
+
+        assert!(matches!(items[1], Err(AIRequestError::Decode(_))));
+        assert!(items.last().unwrap().as_ref().unwrap().done);
+        assert_eq!(content(&items), "Mail [REDACTED]");
+    }
+}
@@ -0,0 +1,70 @@
+
+use crate::core::{Message, AIRequestError};
+use super::client::{NaoriAI, Provider};
+
+const CONTINUE_PROMPT: &str = "Continue exactly where you left off. Do not repeat any earlier text.";
+
+impl NaoriAI {
+    /// Generate output longer than a single response by chaining requests until `stop_marker` appears.
+    /// The prompt should ask the model to end with `stop_marker`; it is stripped from the returned text.
+    /// A response cut off by the token limit is always continued, since a marker in it may only be quoted.
+    /// Anthropic continues from an assistant prefill, other providers get the partial output as context.
+    /// At most `max_continuations` follow-up requests are sent after the first one.
+    pub async fn send_chat_request_continued(
+        &self,
+        messages: &[Message],
+        stop_marker: &str,
+        max_continuations: usize,
+    ) -> Result<String, AIRequestError> {
+        if stop_marker.is_empty() {
+            return Err(AIRequestError::Other("stop_marker must not be empty".to_string()));
+        }
+        let mut output = String::new();
+
+        for _ in 0..=max_continuations {
+            let mut request = messages.to_vec();
+            if !output.is_empty() {
+                match &self.provider {
+                    Provider::Anthropic(_) => {
+                        // Anthropic rejects a final assistant message ending in whitespace
+                        output.truncate(output.trim_end().len());
+                        request.push(Message {
+                            role: "assistant".to_string(),
+                            content: output.clone(),
+                            ..Default::default()
+                        });
+                    }
+                    _ => {
+                        request.push(Message {
+                            role: "assistant".to_string(),
+                            content: output.clone(),
+                            ..Default::default()
+                        });
+                        request.push(Message {
+                            role: "user".to_string(),
+                            content: CONTINUE_PROMPT.to_string(),
+                            ..Default::default()
+                        });
+                    }
+                }
+            }
+
+            let result = self.chat(&request).await?;
+            output.push_str(&result.content);
+
+            // OpenAI and Ollama report "length", Anthropic "max_tokens"
+            let truncated = matches!(result.finish_reason.as_deref(), Some("length" | "max_tokens"));
+            if !truncated && let Some(marker_pos) = output.rfind(stop_marker) {
+                output.truncate(marker_pos);
+                return Ok(output.trim_end().to_string());
+            }
+
+            // The model has nothing more to add, continuing would only repeat the request
+            if !truncated && result.content.trim().is_empty() {
+                break;
+            }
+        }
+
+        Ok(output)
+    }
+}
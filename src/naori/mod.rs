@@ -1,5 +1,13 @@
+pub mod batch;
+pub mod benchmark;
+pub mod builder;
 pub mod client;
+pub mod continuation;
+pub mod multiplex;
+pub mod structured;
+pub mod tool_loop;
 
+pub use benchmark::{BenchmarkResult, BenchmarkStats};
+pub use builder::{NaoriAIBuilder, ProviderKind};
 pub use client::*;
-
-
+pub use multiplex::MultiplexedChatStream;
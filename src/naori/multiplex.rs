@@ -0,0 +1,34 @@
+use std::pin::Pin;
+use futures_util::{Stream, StreamExt, stream};
+
+use crate::core::{Message, ChatStreamItem, AIRequestError};
+use super::client::NaoriAI;
+
+/// Merged items of several conversations, tagged with their conversation id
+pub type MultiplexedChatStream<'a> = Pin<Box<dyn Stream<Item = (String, Result<ChatStreamItem, AIRequestError>)> + Send + 'a>>;
+
+impl NaoriAI {
+    /// Drive many conversations from a single task. Items from all conversations are merged
+    /// into one stream tagged with their conversation id, in arrival order.
+    /// At most `max_concurrent` conversations are streaming at any time; the rest wait their turn.
+    /// A conversation that fails to start yields a single error item.
+    pub fn send_chat_requests_multiplexed(
+        &self,
+        conversations: Vec<(String, Vec<Message>)>,
+        max_concurrent: usize,
+    ) -> MultiplexedChatStream<'_> {
+        let conversation_streams = stream::iter(conversations).map(move |(conversation_id, messages)| {
+            stream::once(async move {
+                let items = match self.send_chat_request(&messages).await {
+                    Ok(chat_stream) => chat_stream.boxed(),
+                    Err(e) => stream::iter(vec![Err(e)]).boxed(),
+                };
+                items.map(move |item| (conversation_id.clone(), item))
+            })
+            .flatten()
+            .boxed()
+        });
+
+        Box::pin(conversation_streams.flatten_unordered(max_concurrent.max(1)))
+    }
+}
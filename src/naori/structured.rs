@@ -0,0 +1,51 @@
+use serde_json::Value;
+
+use crate::core::{Message, ChatOptions, ChatResult, ResponseFormat, AIRequestError};
+use super::client::NaoriAI;
+
+impl NaoriAI {
+    /// Chat request for structured output: the complete answer is parsed and checked against
+    /// `format` (see `ResponseFormat::validate`) once the stream ends. A mismatch fails with
+    /// `AIRequestError::InvalidJson` carrying the raw text, unless `retry` is set, in which case the
+    /// model is shown the problem and asked once to send corrected JSON.
+    pub async fn chat_json(
+        &self,
+        messages: &[Message],
+        format: ResponseFormat,
+        retry: bool,
+    ) -> Result<Value, AIRequestError> {
+        let options = ChatOptions {
+            response_format: Some(format.clone()),
+            ..Default::default()
+        };
+        let stream = self.send_chat_request_with_options(messages, options.clone()).await?;
+        let raw = ChatResult::collect(stream).await?.content;
+        let problem = match format.validate(&raw) {
+            Ok(value) => return Ok(value),
+            Err(problem) => problem,
+        };
+        if !retry {
+            return Err(AIRequestError::InvalidJson { message: problem, raw });
+        }
+
+        let mut request = messages.to_vec();
+        request.push(Message {
+            role: "assistant".to_string(),
+            content: raw,
+            ..Default::default()
+        });
+        request.push(Message {
+            role: "user".to_string(),
+            content: format!(
+                "Your response was rejected: {}. Reply with only the corrected JSON, without any other text.",
+                problem
+            ),
+            ..Default::default()
+        });
+        let stream = self.send_chat_request_with_options(&request, options).await?;
+        let raw = ChatResult::collect(stream).await?.content;
+        format
+            .validate(&raw)
+            .map_err(|message| AIRequestError::InvalidJson { message, raw })
+    }
+}
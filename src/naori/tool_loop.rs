@@ -0,0 +1,32 @@
+
+use crate::core::{Message, AIRequestError};
+use super::client::NaoriAI;
+
+impl NaoriAI {
+    /// Chat with automatic tool execution: tool calls are run and their results sent back until the model
+    /// answers without requesting tools. Returns the final text and the full history, including every tool round.
+    /// After `max_tool_iterations` rounds the loop stops; the history then ends with the unexecuted tool calls.
+    pub async fn chat_with_tools(&self, messages: &[Message]) -> Result<(String, Vec<Message>), AIRequestError> {
+        let mut history = messages.to_vec();
+        let mut iterations = 0;
+
+        loop {
+            let result = self.chat(&history).await?;
+            let tool_calls = result.tool_calls.filter(|calls| !calls.is_empty());
+            history.push(Message {
+                role: "assistant".to_string(),
+                content: result.content.clone(),
+                tool_calls: tool_calls.clone(),
+                ..Default::default()
+            });
+
+            match tool_calls {
+                Some(calls) if iterations < self.max_tool_iterations => {
+                    history.extend(self.handle_tool_calls(calls).await);
+                    iterations += 1;
+                }
+                _ => return Ok((result.content, history)),
+            }
+        }
+    }
+}
@@ -1,35 +1,174 @@
 use futures_util::{Stream, StreamExt};
-use reqwest::Client;
-use std::error::Error;
+use reqwest::{Client, RequestBuilder};
+use reqwest::header::HeaderMap;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::collections::HashMap;
 use bytes::Bytes;
 
-use crate::core::{Message, ToolCall, ChatStreamItem, Tool, TokenUsage};
+use crate::core::{Message, ToolCall, ChatStreamItem, Citation, CitationLocation, Tool, ToolChoice, ChatOptions, ResponseFormat, TokenUsage, RawChunkHook, AIRequestError, parse_header, is_image_url, call_tools};
+use crate::core::trace::trace_event;
 use super::types::*;
 
+pub const DEFAULT_BASE_URL: &str = "https://api.anthropic.com/v1";
+pub const DEFAULT_API_VERSION: &str = "2023-06-01";
+
+// Headers this client sets on its own requests
+const RESERVED_HEADERS: &[&str] = &["x-api-key", "anthropic-version", "anthropic-beta", "content-type"];
+
 pub struct AnthropicClient {
     client: Client,
     api_key: String,
     pub model: String,
-    tools: Vec<Tool>,
+    tools: Vec<Arc<Tool>>,
+    base_url: String,
+    tool_choice: ToolChoice,
+    disable_parallel_tool_use: bool,
+    response_format: ResponseFormat,
+    retry_empty_stream: bool,
+    thinking_budget: Option<u32>,
+    raw_chunk_hook: Option<RawChunkHook>,
+    headers: HeaderMap, // Custom headers sent with every request
+    api_version: String,
+    beta_features: Vec<String>,
 }
 
 impl AnthropicClient {
     pub fn new(api_key: String, model: String) -> Self {
+        Self::with_base_url(api_key, model, DEFAULT_BASE_URL.to_string())
+    }
+
+    /// Point the client at an Anthropic-compatible gateway or proxy instead of the public API
+    pub fn with_base_url(api_key: String, model: String, base_url: String) -> Self {
+        Self::with_client(Client::new(), api_key, model, base_url)
+    }
+
+    /// Use a pre-built `reqwest::Client`, e.g. to share a connection pool or set proxies and timeouts.
+    /// `base_url` is usually `DEFAULT_BASE_URL`
+    pub fn with_client(client: Client, api_key: String, model: String, base_url: String) -> Self {
         Self {
-            client: Client::new(),
+            client,
             api_key,
             model,
             tools: Vec::new(),
+            base_url,
+            tool_choice: ToolChoice::Auto,
+            disable_parallel_tool_use: false,
+            response_format: ResponseFormat::Text,
+            retry_empty_stream: false,
+            thinking_budget: None,
+            raw_chunk_hook: None,
+            headers: HeaderMap::new(),
+            api_version: DEFAULT_API_VERSION.to_string(),
+            beta_features: Vec::new(),
         }
     }
 
-    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), Box<dyn Error>> {
-        self.tools.push(tool);
+    pub fn set_base_url(&mut self, base_url: String) {
+        self.base_url = base_url;
+    }
+
+    /// Control whether Claude may, must, or must not call tools
+    pub fn set_tool_choice(&mut self, tool_choice: ToolChoice) {
+        self.tool_choice = tool_choice;
+    }
+
+    /// Limit Claude to at most one tool call per response (or exactly one when a tool is required)
+    pub fn set_disable_parallel_tool_use(&mut self, disable: bool) {
+        self.disable_parallel_tool_use = disable;
+    }
+
+    /// Anthropic has no JSON mode, so JSON formats are requested through the system prompt
+    pub fn set_response_format(&mut self, response_format: ResponseFormat) {
+        self.response_format = response_format;
+    }
+
+    /// Resend the request once when the API closes the stream before sending any content
+    /// or usage. Off by default, since it delays the first token until content arrives.
+    pub fn set_retry_empty_stream(&mut self, retry: bool) {
+        self.retry_empty_stream = retry;
+    }
+
+    /// Enable extended thinking with the given token budget, streamed as `ChatStreamItem.reasoning`.
+    /// The budget must be lower than the request's max tokens.
+    pub fn set_thinking_budget(&mut self, budget_tokens: Option<u32>) {
+        self.thinking_budget = budget_tokens;
+    }
+
+    /// The `anthropic-version` header, `DEFAULT_API_VERSION` unless set
+    pub fn set_api_version(&mut self, version: String) {
+        self.api_version = version;
+    }
+
+    /// Beta features to opt into, sent as the `anthropic-beta` header,
+    /// e.g. `vec!["token-efficient-tools-2025-02-19".to_string()]`
+    pub fn set_beta_features(&mut self, features: Vec<String>) {
+        self.beta_features = features;
+    }
+
+    /// Send a custom header with every request, e.g. for gateway authentication. Headers the
+    /// client sets itself (x-api-key, anthropic-version, anthropic-beta, content-type) are refused
+    /// rather than silently replaced; use `set_api_version` and `set_beta_features` for those
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), AIRequestError> {
+        let (name, value) = parse_header(name, value, RESERVED_HEADERS)?;
+        self.headers.insert(name, value);
         Ok(())
     }
 
+    /// Pass every raw line of chat response streams to `hook` before parsing, `None` to stop
+    pub fn set_raw_chunk_hook(&mut self, hook: Option<RawChunkHook>) {
+        self.raw_chunk_hook = hook;
+    }
+
+    pub(crate) fn raw_chunk_hook(&self) -> Option<RawChunkHook> {
+        self.raw_chunk_hook.clone()
+    }
+
+    // Custom headers, authentication, API version and beta features
+    fn with_api_headers(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request
+            .headers(self.headers.clone())
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", &self.api_version);
+        if self.beta_features.is_empty() {
+            request
+        } else {
+            request.header("anthropic-beta", self.beta_features.join(","))
+        }
+    }
+
+    pub(crate) fn extend_tools(&mut self, tools: Vec<Arc<Tool>>) {
+        self.tools.extend(tools);
+    }
+
+    pub(crate) fn response_format(&self) -> &ResponseFormat {
+        &self.response_format
+    }
+
+    pub(crate) fn take_tools(&mut self) -> Vec<Arc<Tool>> {
+        std::mem::take(&mut self.tools)
+    }
+
+    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), AIRequestError> {
+        self.tools.push(Arc::new(tool));
+        Ok(())
+    }
+
+    /// Remove a registered tool by name, `false` if there was none
+    pub fn remove_tool(&mut self, name: &str) -> bool {
+        let count = self.tools.len();
+        self.tools.retain(|tool| tool.name != name);
+        self.tools.len() != count
+    }
+
+    pub fn clear_tools(&mut self) {
+        self.tools.clear();
+    }
+
+    pub fn list_tools(&self) -> Vec<&str> {
+        self.tools.iter().map(|tool| tool.name.as_str()).collect()
+    }
+
     pub async fn is_fallback_mode(&self) -> bool {
         false // Anthropic has native tool support
     }
@@ -42,22 +181,18 @@ impl AnthropicClient {
         false
     }
 
-    pub async fn supports_tool_calls(&self) -> Result<bool, Box<dyn Error>> {
+    pub async fn supports_tool_calls(&self) -> Result<bool, AIRequestError> {
         Ok(true) // Anthropic Claude models support native tool calling
     }
 
-    pub async fn get_available_models(&self) -> Result<Vec<AnthropicModel>, Box<dyn Error>> {
+    pub async fn get_available_models(&self) -> Result<Vec<AnthropicModel>, AIRequestError> {
         let response = self
-            .client
-            .get("https://api.anthropic.com/v1/models")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
+            .with_api_headers(self.client.get(format!("{}/models", self.base_url)))
             .send()
             .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("Anthropic API error: {}", error_text).into());
+            return Err(AIRequestError::from_response(response).await);
         }
 
         let models_response: AnthropicModelsResponse = response.json().await?;
@@ -73,10 +208,14 @@ impl AnthropicClient {
                 let tool_use_id = parts[1];
                 let result_content = parts[2];
 
-                let content_blocks = vec![ContentBlock::ToolResult {
+                let mut content_blocks = vec![ContentBlock::ToolResult {
                     tool_use_id: tool_use_id.to_string(),
                     content: result_content.to_string(),
+                    cache_control: None,
                 }];
+                if message.cache {
+                    content_blocks[0].set_cache_control(CacheControl::ephemeral());
+                }
 
                 return AnthropicMessage {
                     role: message.role.clone(),
@@ -87,18 +226,28 @@ impl AnthropicClient {
 
         let mut content_blocks = vec![ContentBlock::Text {
             text: message.content.clone(),
+            cache_control: None,
         }];
 
         // Add images if present
         if let Some(images) = &message.images {
-            for image_data in images {
-                content_blocks.insert(0, ContentBlock::Image {
-                    source: ImageSource {
+            for (index, image_data) in images.iter().enumerate() {
+                let source = if is_image_url(image_data) {
+                    ImageSource {
+                        source_type: "url".to_string(),
+                        media_type: None,
+                        data: None,
+                        url: Some(image_data.clone()),
+                    }
+                } else {
+                    ImageSource {
                         source_type: "base64".to_string(),
-                        media_type: "image/jpeg".to_string(), 
-                        data: image_data.clone(),
-                    },
-                });
+                        media_type: Some(message.image_media_type(index).to_string()),
+                        data: Some(image_data.clone()),
+                        url: None,
+                    }
+                };
+                content_blocks.insert(0, ContentBlock::Image { source, cache_control: None });
             }
         }
 
@@ -110,16 +259,77 @@ impl AnthropicClient {
                     id: tool_id,
                     name: tool_call.function.name.clone(),
                     input: tool_call.function.arguments.clone(),
+                    cache_control: None,
                 });
             }
         }
 
+        // The breakpoint goes on the last block, so the whole message is part of the cached prefix
+        if message.cache
+            && let Some(last_block) = content_blocks.last_mut()
+        {
+            last_block.set_cache_control(CacheControl::ephemeral());
+        }
+
         AnthropicMessage {
             role: message.role.clone(),
             content: content_blocks,
         }
     }
 
+    fn convert_tool_choice_to_anthropic(&self, tool_choice: &ToolChoice) -> Option<AnthropicToolChoice> {
+        // Anthropic rejects tool_choice when no tools are sent
+        if self.tools.is_empty() {
+            return None;
+        }
+
+        let disable_parallel_tool_use = self.disable_parallel_tool_use.then_some(true);
+        match tool_choice {
+            // Plain auto is the API default, so leave the field out
+            ToolChoice::Auto if disable_parallel_tool_use.is_none() => None,
+            ToolChoice::Auto => Some(AnthropicToolChoice::Auto { disable_parallel_tool_use }),
+            ToolChoice::None => Some(AnthropicToolChoice::None),
+            ToolChoice::Required => Some(AnthropicToolChoice::Any { disable_parallel_tool_use }),
+            ToolChoice::Specific(name) => Some(AnthropicToolChoice::Tool {
+                name: name.clone(),
+                disable_parallel_tool_use,
+            }),
+        }
+    }
+
+    /// System prompt blocks from the system-role messages, in order, followed by the response format instruction
+    fn build_system(messages: &[Message], response_format: &ResponseFormat) -> Option<Vec<ContentBlock>> {
+        let mut blocks: Vec<ContentBlock> = messages
+            .iter()
+            .filter(|msg| msg.role == "system")
+            .map(|msg| ContentBlock::Text {
+                text: msg.content.clone(),
+                cache_control: msg.cache.then(CacheControl::ephemeral),
+            })
+            .collect();
+        if let Some(instruction) = Self::response_format_instruction(response_format) {
+            blocks.push(ContentBlock::Text {
+                text: instruction,
+                cache_control: None,
+            });
+        }
+        (!blocks.is_empty()).then_some(blocks)
+    }
+
+    fn response_format_instruction(response_format: &ResponseFormat) -> Option<String> {
+        match response_format {
+            ResponseFormat::Text => None,
+            ResponseFormat::Json => Some(
+                "Respond only with a single valid JSON object, without any surrounding text or code fences."
+                    .to_string(),
+            ),
+            ResponseFormat::JsonSchema(schema) => Some(format!(
+                "Respond only with a single valid JSON object matching this JSON schema, without any surrounding text or code fences:\n{}",
+                schema
+            )),
+        }
+    }
+
     fn convert_tools_to_anthropic(&self) -> Vec<AnthropicTool> {
         self.tools
             .iter()
@@ -134,58 +344,161 @@ impl AnthropicClient {
     pub async fn send_chat_request(
         &self,
         messages: &[Message],
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.send_chat_request_with_options(messages, &ChatOptions::default()).await
+    }
+
+    pub async fn send_chat_request_with_options(
+        &self,
+        messages: &[Message],
+        options: &ChatOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        let mut stream = self.open_stream(messages, options).await?;
+        if !self.retry_empty_stream {
+            return Ok(stream);
+        }
+
+        // Hold items back until real output shows up, so an empty stream can be retried
+        let mut buffered = Vec::new();
+        let mut empty = true;
+        while let Some(item) = stream.next().await {
+            let item_done = match &item {
+                Ok(item) => {
+                    if !item.content.is_empty() || item.reasoning.is_some() || item.tool_calls.is_some() || item.usage.is_some() {
+                        empty = false;
+                    }
+                    item.done
+                }
+                // Decode errors don't end the stream, so keep waiting for output after them
+                Err(error) => {
+                    empty = empty && !error.is_fatal();
+                    error.is_fatal()
+                }
+            };
+            buffered.push(item);
+            if !empty || item_done {
+                break;
+            }
+        }
+
+        if empty {
+            // Retry only once so legitimate empty completions still come through
+            trace_event!(info, model = %self.model, "retrying Anthropic request after an empty stream");
+            return self.open_stream(messages, options).await;
+        }
+        Ok(Box::pin(futures_util::stream::iter(buffered).chain(stream)))
+    }
+
+    /// The body `send_chat_request_with_options` would post to `/messages`, without sending it
+    pub fn build_request_json(&self, messages: &[Message], options: &ChatOptions) -> Result<serde_json::Value, AIRequestError> {
+        Ok(serde_json::to_value(self.build_request(messages, options))?)
+    }
+
+    fn build_request(&self, messages: &[Message], options: &ChatOptions) -> AnthropicRequest {
+        // System-role messages aren't allowed in `messages` and go in the top-level system field
         let anthropic_messages: Vec<AnthropicMessage> = messages
             .iter()
+            .filter(|msg| msg.role != "system")
             .map(|msg| self.convert_to_anthropic_message(msg))
             .collect();
+        let system = Self::build_system(
+            messages,
+            options.response_format.as_ref().unwrap_or(&self.response_format),
+        );
 
-
-        let request = AnthropicRequest {
+        AnthropicRequest {
             model: self.model.clone(),
-            max_tokens: 4096,
+            max_tokens: options.max_tokens.unwrap_or(4096),
             messages: anthropic_messages,
-            system: None,
-            temperature: None,
+            system,
+            temperature: options.temperature,
+            top_p: options.top_p,
+            stop_sequences: options.stop.clone(),
             tools: if self.tools.is_empty() {
                 None
             } else {
                 Some(self.convert_tools_to_anthropic())
             },
+            tool_choice: self.convert_tool_choice_to_anthropic(
+                options.tool_choice.as_ref().unwrap_or(&self.tool_choice),
+            ),
+            thinking: self.thinking_budget.map(|budget_tokens| AnthropicThinking {
+                thinking_type: "enabled".to_string(),
+                budget_tokens,
+            }),
             stream: Some(true),
-        };
+        }
+    }
 
+    async fn open_stream(
+        &self,
+        messages: &[Message],
+        options: &ChatOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        let request = self.build_request(messages, options);
         let response = self
-            .client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &self.api_key)
-            .header("anthropic-version", "2023-06-01")
+            .with_api_headers(self.client.post(format!("{}/messages", self.base_url)))
             .header("content-type", "application/json")
             .json(&request)
             .send()
             .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("Anthropic API error: {}", error_text).into());
+            return Err(AIRequestError::from_model_response(response, &self.model).await);
         }
 
         let stream = response.bytes_stream();
         
         // Create a stateful stream processor
-        Ok(Box::pin(AnthropicStreamProcessor::new(stream)))
+        Ok(Box::pin(AnthropicStreamProcessor::new(stream, self.raw_chunk_hook.clone())))
+    }
+
+    /// Prompt tokens for these messages, system prompt and tools as counted by the API, without running the model
+    pub async fn count_tokens(&self, messages: &[Message]) -> Result<u32, AIRequestError> {
+        let request = AnthropicCountTokensRequest {
+            model: self.model.clone(),
+            messages: messages
+                .iter()
+                .filter(|msg| msg.role != "system")
+                .map(|msg| self.convert_to_anthropic_message(msg))
+                .collect(),
+            system: Self::build_system(messages, &self.response_format),
+            tools: if self.tools.is_empty() {
+                None
+            } else {
+                Some(self.convert_tools_to_anthropic())
+            },
+        };
+
+        let response = self
+            .with_api_headers(self.client.post(format!("{}/messages/count_tokens", self.base_url)))
+            .header("content-type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_response(response).await);
+        }
+
+        let count: AnthropicCountTokensResponse = response.json().await?;
+        Ok(count.input_tokens)
     }
 
     pub async fn send_chat_request_no_stream(
         &self,
         messages: &[Message],
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         let mut full_response = String::new();
         let mut tool_calls: Option<Vec<ToolCall>> = None;
         let mut stream = self.send_chat_request(messages).await?;
 
         while let Some(item) = stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
+            let item = match item {
+                Ok(item) => item,
+                Err(error) if !error.is_fatal() => continue,
+                Err(error) => return Err(error),
+            };
             if !item.content.is_empty() {
                 full_response.push_str(&item.content);
             }
@@ -200,14 +513,10 @@ impl AnthropicClient {
     }
 
     pub async fn handle_tool_calls(&self, tool_calls: Vec<ToolCall>) -> Vec<Message> {
+        let results = call_tools(&self.tools, &tool_calls).await;
         let mut tool_responses = Vec::new();
-        for tool_call in tool_calls {
-            if let Some(tool) = self
-                .tools
-                .iter()
-                .find(|t| t.name == tool_call.function.name)
-            {
-                let result = (tool.function)(tool_call.function.arguments.clone());
+        for (tool_call, result) in tool_calls.into_iter().zip(results) {
+            if let Some(result) = result {
                 
                 // Use the tool call ID if available, otherwise use "unknown"
                 let tool_id = tool_call.id.unwrap_or_else(|| "unknown".to_string());
@@ -217,8 +526,7 @@ impl AnthropicClient {
                 tool_responses.push(Message {
                     role: "user".to_string(),
                     content: format!("TOOL_RESULT:{}:{}", tool_id, result),
-                    images: None,
-                    tool_calls: None,
+                    ..Default::default()
                 });
             }
         }
@@ -231,29 +539,254 @@ impl AnthropicClient {
     }
 }
 
+/// Map an error event from the stream to the same error an HTTP status would have produced
+fn convert_stream_error(error: ApiError) -> AIRequestError {
+    let message = format!("{}: {}", error.error_type, error.message);
+    let status = match error.error_type.as_str() {
+        "invalid_request_error" => 400,
+        "authentication_error" | "permission_error" => return AIRequestError::Auth { message },
+        "not_found_error" => 404,
+        "request_too_large" => 413,
+        "rate_limit_error" => return AIRequestError::RateLimited { retry_after: None },
+        "overloaded_error" => 529,
+        _ => 500,
+    };
+    AIRequestError::Api { status, message }
+}
+
+fn convert_citation(citation: AnthropicCitation) -> Citation {
+    let location = match citation.citation_type.as_str() {
+        "char_location" => citation
+            .start_char_index
+            .zip(citation.end_char_index)
+            .map(|(start, end)| CitationLocation::Chars { start, end }),
+        "page_location" => citation
+            .start_page_number
+            .zip(citation.end_page_number)
+            .map(|(start, end)| CitationLocation::Pages { start, end }),
+        "content_block_location" => citation
+            .start_block_index
+            .zip(citation.end_block_index)
+            .map(|(start, end)| CitationLocation::ContentBlocks { start, end }),
+        _ => citation.url.map(CitationLocation::Url),
+    };
+
+    Citation {
+        cited_text: citation.cited_text,
+        document_index: citation.document_index,
+        document_title: citation.document_title.or(citation.title),
+        location,
+    }
+}
+
 // Custom stream processor to handle stateful tool call accumulation
 struct AnthropicStreamProcessor {
     inner: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
-    // Track tool calls being accumulated: tool_id -> (name, accumulated_json)
-    accumulating_tools: HashMap<String, (String, String)>,
-    pending_results: std::collections::VecDeque<Result<ChatStreamItem, String>>,
+    // Track tool calls being accumulated: content block index -> (tool_id, name, accumulated_json)
+    accumulating_tools: HashMap<u32, (String, String, String)>,
+    completed_tools: Vec<ToolCall>,
+    pending_results: std::collections::VecDeque<Result<ChatStreamItem, AIRequestError>>,
     usage: Option<TokenUsage>,
+    stop_reason: Option<String>,
+    raw_chunk_hook: Option<RawChunkHook>,
+    // Bytes of an SSE event that hasn't been completed by a blank line yet
+    buffer: Vec<u8>,
+    finished: bool,
 }
 
 impl AnthropicStreamProcessor {
-    fn new(stream: impl Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static) -> Self {
+    fn new(stream: impl Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static, raw_chunk_hook: Option<RawChunkHook>) -> Self {
         Self {
             inner: Box::pin(stream),
             accumulating_tools: HashMap::new(),
+            completed_tools: Vec::new(),
             pending_results: std::collections::VecDeque::new(),
             usage: None,
+            stop_reason: None,
+            raw_chunk_hook,
+            buffer: Vec::new(),
+            finished: false,
+        }
+    }
+
+    // Handle one line of a complete SSE event, queueing any items it produces
+    fn process_line(&mut self, line: &str) {
+        if line.is_empty() {
+            return;
+        }
+        if let Some(hook) = &self.raw_chunk_hook {
+            hook(line);
+        }
+        let Some(json_str) = line.strip_prefix("data: ") else { return };
+        if json_str.trim() == "[DONE]" {
+            self.pending_results.push_back(Ok(ChatStreamItem {
+                content: String::new(),
+                tool_calls: None,
+                done: true,
+                usage: None,
+                finish_reason: None,
+                reasoning: None,
+                filtered: None,
+                citations: None,
+                timings: None,
+            }));
+            return;
+        }
+
+        let event = match serde_json::from_str::<StreamingEvent>(json_str) {
+            Ok(event) => event,
+            Err(e) => {
+                // Usually a known event with a new field shape, which would otherwise look like missing content
+                trace_event!(warn, error = %e, event = json_str, "unparseable Anthropic stream event");
+                self.pending_results.push_back(Err(AIRequestError::Decode(format!("{} in event {}", e, json_str))));
+                return;
+            }
+        };
+        match event {
+            StreamingEvent::ContentBlockDelta { index, delta } => {
+                match delta {
+                    Delta::TextDelta { text } => {
+                        self.pending_results.push_back(Ok(ChatStreamItem {
+                            content: text,
+                            tool_calls: None,
+                            done: false,
+                            usage: None,
+                            finish_reason: None,
+                            reasoning: None,
+                            filtered: None,
+                            citations: None,
+                            timings: None,
+                        }));
+                    }
+                    Delta::ThinkingDelta { thinking } => {
+                        self.pending_results.push_back(Ok(ChatStreamItem {
+                            content: String::new(),
+                            tool_calls: None,
+                            done: false,
+                            usage: None,
+                            finish_reason: None,
+                            reasoning: Some(thinking),
+                            filtered: None,
+                            citations: None,
+                            timings: None,
+                        }));
+                    }
+                    Delta::CitationsDelta { citation } => {
+                        self.pending_results.push_back(Ok(ChatStreamItem {
+                            content: String::new(),
+                            tool_calls: None,
+                            done: false,
+                            usage: None,
+                            finish_reason: None,
+                            reasoning: None,
+                            filtered: None,
+                            citations: Some(vec![convert_citation(citation)]),
+                            timings: None,
+                        }));
+                    }
+                    Delta::SignatureDelta { .. } => {
+                        // Only needed when replaying thinking blocks
+                    }
+                    Delta::Unknown => {
+                        trace_event!(warn, delta = json_str, "unknown Anthropic content delta");
+                        self.pending_results.push_back(Err(AIRequestError::Decode(format!("Unknown content delta {}", json_str))));
+                    }
+                    Delta::InputJsonDelta { partial_json } => {
+                        // Parallel tool calls stream their arguments in separate blocks
+                        if let Some((_, _, accumulated_json)) = self.accumulating_tools.get_mut(&index) {
+                            accumulated_json.push_str(&partial_json);
+                        }
+                    }
+                }
+            }
+            StreamingEvent::ContentBlockStart { index, content_block: ContentBlock::ToolUse { id, name, .. } } => {
+                // Start accumulating a new tool call
+                self.accumulating_tools.insert(index, (id, name, String::new()));
+            }
+            StreamingEvent::ContentBlockStop { index } => {
+                // Only the stopped block is complete, other tool blocks may still be streaming
+                if let Some((tool_id, tool_name, accumulated_json)) = self.accumulating_tools.remove(&index) {
+                    // Tools without parameters send no argument fragments at all
+                    let accumulated_json = if accumulated_json.trim().is_empty() { "{}" } else { &accumulated_json };
+                    if let Ok(arguments) = serde_json::from_str::<serde_json::Value>(accumulated_json) {
+                        // Create tool call with the ID properly stored
+                        self.completed_tools.push(ToolCall {
+                            id: Some(tool_id),
+                            function: crate::core::Function {
+                                name: tool_name,
+                                arguments,
+                            },
+                        });
+                    }
+                }
+            }
+            StreamingEvent::MessageDelta { delta } => {
+                if delta.stop_reason.is_some() {
+                    self.stop_reason = delta.stop_reason;
+                }
+                if let Some(usage) = delta.usage {
+                    // Input and cache counts arrive with message_start and may be missing here
+                    let started = self.usage.take().unwrap_or_default();
+                    let input_tokens = match usage.input_tokens {
+                        0 => started.prompt_tokens.unwrap_or(0),
+                        tokens => tokens,
+                    };
+                    self.usage = Some(TokenUsage {
+                        prompt_tokens: Some(input_tokens),
+                        completion_tokens: Some(usage.output_tokens),
+                        total_tokens: Some(input_tokens + usage.output_tokens),
+                        cost_usd: None,
+                        generation_id: None,
+                        cache_creation_tokens: usage.cache_creation_input_tokens.or(started.cache_creation_tokens),
+                        cache_read_tokens: usage.cache_read_input_tokens.or(started.cache_read_tokens),
+                    });
+                }
+            }
+            StreamingEvent::MessageStop => {
+                let usage = self.usage.clone();
+                let finish_reason = self.stop_reason.take();
+                // All tool calls of the message are reported together
+                let tool_calls = Some(std::mem::take(&mut self.completed_tools)).filter(|tools| !tools.is_empty());
+                self.pending_results.push_back(Ok(ChatStreamItem {
+                    content: String::new(),
+                    tool_calls,
+                    done: true,
+                    usage,
+                    finish_reason,
+                    reasoning: None,
+                    filtered: None,
+                    citations: None,
+                    timings: None,
+                }));
+            }
+            StreamingEvent::MessageStart { message } => {
+                self.usage = Some(TokenUsage {
+                    prompt_tokens: Some(message.usage.input_tokens),
+                    cache_creation_tokens: message.usage.cache_creation_input_tokens,
+                    cache_read_tokens: message.usage.cache_read_input_tokens,
+                    ..TokenUsage::default()
+                });
+            }
+            StreamingEvent::Error { error } => {
+                // Errors after a 200, e.g. overloaded_error, would otherwise look like an empty response
+                self.pending_results.push_back(Err(convert_stream_error(error)));
+            }
+            StreamingEvent::Ping => {
+                // Ignore ping events
+            }
+            StreamingEvent::Unknown => {
+                trace_event!(warn, event = json_str, "unknown Anthropic stream event");
+                self.pending_results.push_back(Err(AIRequestError::Decode(format!("Unknown stream event {}", json_str))));
+            }
+            _ => {
+                // Handle other event types as needed
+            }
         }
     }
-    
 }
 
 impl Stream for AnthropicStreamProcessor {
-    type Item = Result<ChatStreamItem, String>;
+    type Item = Result<ChatStreamItem, AIRequestError>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
         loop {
@@ -262,120 +795,204 @@ impl Stream for AnthropicStreamProcessor {
                 return std::task::Poll::Ready(Some(result));
             }
 
+            if self.finished {
+                return std::task::Poll::Ready(None);
+            }
+
             // Poll the inner stream
             match self.inner.as_mut().poll_next(cx) {
                 std::task::Poll::Ready(Some(chunk_result)) => {
                     match chunk_result {
                         Ok(chunk) => {
-                            let lines = chunk.split(|&b| b == b'\n');
-
-                            for line in lines {
-                                if line.is_empty() {
-                                    continue;
-                                }
-
-                                // Skip "data: " prefix from SSE
-                                let line_str = String::from_utf8_lossy(line);
-                                if line_str.starts_with("data: ") {
-                                    let json_str = &line_str[6..];
-                                    if json_str.trim() == "[DONE]" {
-                                        self.pending_results.push_back(Ok(ChatStreamItem {
-                                            content: String::new(),
-                                            tool_calls: None,
-                                            done: true,
-                                            usage: None,
-                                        }));
-                                        continue;
-                                    }
-
-                                    if let Ok(event) = serde_json::from_str::<StreamingEvent>(json_str) {
-                                        match event {
-                                            StreamingEvent::ContentBlockDelta { delta, .. } => {
-                                                match delta {
-                                                    Delta::TextDelta { text } => {
-                                                        self.pending_results.push_back(Ok(ChatStreamItem {
-                                                            content: text,
-                                                            tool_calls: None,
-                                                            done: false,
-                                                            usage: None,
-                                                        }));
-                                                    }
-                                                    Delta::InputJsonDelta { partial_json } => {
-                                                        // Find the most recently added tool (last in iteration order)
-                                                        if let Some((_, accumulated_json)) = self.accumulating_tools.values_mut().last() {
-                                                            accumulated_json.push_str(&partial_json);
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                            StreamingEvent::ContentBlockStart { content_block, .. } => {
-                                                if let ContentBlock::ToolUse { id, name, input: _ } = content_block {
-                                                    // Start accumulating a new tool call
-                                                    self.accumulating_tools.insert(id, (name, String::new()));
-                                                }
-                                            }
-                                            StreamingEvent::ContentBlockStop { .. } => {
-                                                // Finish all accumulated tool calls
-                                                let mut completed_tools = Vec::new();
-                                                for (tool_id, (tool_name, accumulated_json)) in self.accumulating_tools.drain() {
-                                                    if let Ok(arguments) = serde_json::from_str::<serde_json::Value>(&accumulated_json) {
-                                                        // Create tool call with the ID properly stored
-                                                        let tool_call = ToolCall {
-                                                            id: Some(tool_id),
-                                                            function: crate::core::Function {
-                                                                name: tool_name,
-                                                                arguments,
-                                                            },
-                                                        };
-                                                        completed_tools.push(tool_call);
-                                                    }
-                                                }
-                                                
-                                                if !completed_tools.is_empty() {
-                                                    self.pending_results.push_back(Ok(ChatStreamItem {
-                                                        content: String::new(),
-                                                        tool_calls: Some(completed_tools),
-                                                        done: false,
-                                                        usage: None,
-                                                    }));
-                                                }
-                                            }
-                                            StreamingEvent::MessageDelta { delta } => {
-                                                if let Some(usage) = delta.usage {
-                                                    self.usage = Some(TokenUsage {
-                                                        prompt_tokens: Some(usage.input_tokens),
-                                                        completion_tokens: Some(usage.output_tokens),
-                                                        total_tokens: Some(usage.input_tokens + usage.output_tokens),
-                                                    });
-                                                }
-                                            }
-                                            StreamingEvent::MessageStop => {
-                                                let usage = self.usage.clone();
-                                                self.pending_results.push_back(Ok(ChatStreamItem {
-                                                    content: String::new(),
-                                                    tool_calls: None,
-                                                    done: true,
-                                                    usage,
-                                                }));
-                                            }
-                                            StreamingEvent::Ping => {
-                                                // Ignore ping events
-                                            }
-                                            _ => {
-                                                // Handle other event types as needed
-                                            }
-                                        }
-                                    }
+                            self.buffer.extend_from_slice(&chunk);
+                            // A data line can be cut anywhere between two chunks, even inside a UTF-8
+                            // character, so only complete events are parsed
+                            while let Some(event_end) = self.buffer.windows(2).position(|pair| pair == b"\n\n") {
+                                let event: Vec<u8> = self.buffer.drain(..event_end + 2).collect();
+                                for line in String::from_utf8_lossy(&event).lines() {
+                                    self.process_line(line);
                                 }
                             }
                             // Continue the loop to check for pending results
                         }
-                        Err(e) => return std::task::Poll::Ready(Some(Err(e.to_string())))
+                        Err(e) => return std::task::Poll::Ready(Some(Err(AIRequestError::Network(e))))
+                    }
+                }
+                std::task::Poll::Ready(None) => {
+                    if self.finished {
+                        return std::task::Poll::Ready(None);
+                    }
+                    // The last event may lack its closing blank line
+                    self.finished = true;
+                    let rest = std::mem::take(&mut self.buffer);
+                    for line in String::from_utf8_lossy(&rest).lines() {
+                        self.process_line(line);
                     }
                 }
-                std::task::Poll::Ready(None) => return std::task::Poll::Ready(None),
                 std::task::Poll::Pending => return std::task::Poll::Pending,
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feed raw SSE bytes through the processor, split into the given chunks
+    async fn process(chunks: Vec<Vec<u8>>) -> Vec<Result<ChatStreamItem, AIRequestError>> {
+        let chunks: Vec<Result<Bytes, reqwest::Error>> = chunks.into_iter().map(|chunk| Ok(Bytes::from(chunk))).collect();
+        AnthropicStreamProcessor::new(futures_util::stream::iter(chunks), None).collect().await
+    }
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: "user".to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    async fn client_with_tool() -> AnthropicClient {
+        let mut client = AnthropicClient::new("key".to_string(), "claude-sonnet-4-5".to_string());
+        let parameters = serde_json::json!({"type": "object", "properties": {}});
+        client.add_tool(Tool::new("get_time", "Current time", parameters, |_| Ok("12:00".to_string()))).await.unwrap();
+        client
+    }
+
+    fn sse(events: &[&str]) -> String {
+        events.iter().map(|event| format!("event: message\ndata: {}\n\n", event)).collect()
+    }
+
+    #[tokio::test]
+    async fn data_line_split_across_chunks() {
+        let body = sse(&[
+            r#"{"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"héllo "}}"#,
+            r#"{"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"wörld"}}"#,
+            r#"{"type":"content_block_stop","index":0}"#,
+            r#"{"type":"message_stop"}"#,
+        ]);
+        let bytes = body.as_bytes();
+
+        // Every cut point, including inside `data:`, the JSON and the multi-byte characters
+        for split in 1..bytes.len() {
+            let items = process(vec![bytes[..split].to_vec(), bytes[split..].to_vec()]).await;
+            let mut content = String::new();
+            for item in &items {
+                let item = item.as_ref().unwrap_or_else(|e| panic!("split at {}: {}", split, e));
+                content.push_str(&item.content);
+            }
+            assert_eq!(content, "héllo wörld", "split at {}", split);
+            assert!(items.last().unwrap().as_ref().unwrap().done);
+        }
+    }
+
+    #[tokio::test]
+    async fn interleaved_tool_blocks_accumulate_by_index() {
+        let body = sse(&[
+            r#"{"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_a","name":"get_weather","input":{}}}"#,
+            r#"{"type":"content_block_start","index":2,"content_block":{"type":"tool_use","id":"toolu_b","name":"get_time","input":{}}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"city\": "}}"#,
+            r#"{"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":"{\"zone\""}}"#,
+            r#"{"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"\"Oslo\"}"}}"#,
+            r#"{"type":"content_block_stop","index":1}"#,
+            r#"{"type":"content_block_delta","index":2,"delta":{"type":"input_json_delta","partial_json":": \"UTC\"}"}}"#,
+            r#"{"type":"content_block_stop","index":2}"#,
+            r#"{"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null}}"#,
+            r#"{"type":"message_stop"}"#,
+        ]);
+        let items = process(vec![body.into_bytes()]).await;
+
+        let last = items.last().unwrap().as_ref().unwrap();
+        assert!(last.done);
+        assert_eq!(last.finish_reason.as_deref(), Some("tool_use"));
+        let calls: Vec<_> = last
+            .tool_calls
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|call| (call.id.as_deref().unwrap(), call.function.name.as_str(), call.function.arguments.clone()))
+            .collect();
+        assert_eq!(
+            calls,
+            vec![
+                ("toolu_a", "get_weather", serde_json::json!({"city": "Oslo"})),
+                ("toolu_b", "get_time", serde_json::json!({"zone": "UTC"})),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn tool_choice_shapes() {
+        let mut client = client_with_tool().await;
+        let messages = [user("What time is it?")];
+        let tool_choice = |client: &AnthropicClient, choice: ToolChoice| {
+            let options = ChatOptions { tool_choice: Some(choice), ..Default::default() };
+            client.build_request_json(&messages, &options).unwrap().get("tool_choice").cloned()
+        };
+
+        assert_eq!(tool_choice(&client, ToolChoice::Auto), None);
+        assert_eq!(tool_choice(&client, ToolChoice::None), Some(serde_json::json!({"type": "none"})));
+        assert_eq!(tool_choice(&client, ToolChoice::Required), Some(serde_json::json!({"type": "any"})));
+        assert_eq!(
+            tool_choice(&client, ToolChoice::Specific("get_time".to_string())),
+            Some(serde_json::json!({"type": "tool", "name": "get_time"}))
+        );
+
+        client.set_disable_parallel_tool_use(true);
+        assert_eq!(
+            tool_choice(&client, ToolChoice::Auto),
+            Some(serde_json::json!({"type": "auto", "disable_parallel_tool_use": true}))
+        );
+        assert_eq!(
+            tool_choice(&client, ToolChoice::Required),
+            Some(serde_json::json!({"type": "any", "disable_parallel_tool_use": true}))
+        );
+
+        // Anthropic rejects tool_choice without tools
+        client.clear_tools();
+        assert_eq!(tool_choice(&client, ToolChoice::Required), None);
+    }
+
+    #[test]
+    fn system_messages_go_in_the_system_field() {
+        let client = AnthropicClient::new("key".to_string(), "claude-sonnet-4-5".to_string());
+        let messages = [
+            Message {
+                role: "system".to_string(),
+                content: "You are terse.".to_string(),
+                ..Default::default()
+            },
+            user("Hi"),
+            Message {
+                role: "system".to_string(),
+                content: "Answer in French.".to_string(),
+                ..Default::default()
+            },
+        ];
+        let body = client.build_request_json(&messages, &ChatOptions::default()).unwrap();
+
+        assert_eq!(
+            body["system"],
+            serde_json::json!([
+                {"type": "text", "text": "You are terse."},
+                {"type": "text", "text": "Answer in French."},
+            ])
+        );
+        let roles: Vec<_> = body["messages"].as_array().unwrap().iter().map(|msg| msg["role"].clone()).collect();
+        assert_eq!(roles, vec![serde_json::json!("user")]);
+
+        let body = client.build_request_json(&[user("Hi")], &ChatOptions::default()).unwrap();
+        assert!(body.get("system").is_none());
+    }
+
+    #[test]
+    fn seed_not_sent() {
+        let client = AnthropicClient::new("key".to_string(), "claude-sonnet-4-5".to_string());
+        let options = ChatOptions { seed: Some(42), ..Default::default() };
+
+        let body = client.build_request_json(&[user("Hi")], &options).unwrap();
+
+        assert!(body.get("seed").is_none());
+    }
+}
@@ -10,28 +10,74 @@ pub struct AnthropicMessage {
 #[serde(tag = "type")]
 pub enum ContentBlock {
     #[serde(rename = "text")]
-    Text { text: String },
+    Text {
+        text: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
     #[serde(rename = "image")]
-    Image { source: ImageSource },
+    Image {
+        source: ImageSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
+    },
     #[serde(rename = "tool_use")]
     ToolUse {
         id: String,
         name: String,
         input: serde_json::Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
     },
     #[serde(rename = "tool_result")]
     ToolResult {
         tool_use_id: String,
         content: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        cache_control: Option<CacheControl>,
     },
+    // Block types added to the API after this client, e.g. server tool results
+    #[serde(other)]
+    Unknown,
+}
+
+impl ContentBlock {
+    /// Mark this block as a prompt caching breakpoint
+    pub fn set_cache_control(&mut self, control: CacheControl) {
+        match self {
+            ContentBlock::Text { cache_control, .. }
+            | ContentBlock::Image { cache_control, .. }
+            | ContentBlock::ToolUse { cache_control, .. }
+            | ContentBlock::ToolResult { cache_control, .. } => *cache_control = Some(control),
+            ContentBlock::Unknown => {}
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CacheControl {
+    #[serde(rename = "type")]
+    pub cache_type: String,
+}
+
+impl CacheControl {
+    pub fn ephemeral() -> Self {
+        Self {
+            cache_type: "ephemeral".to_string(),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ImageSource {
     #[serde(rename = "type")]
     pub source_type: String, 
-    pub media_type: String,  
-    pub data: String,        
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -40,15 +86,69 @@ pub struct AnthropicRequest {
     pub max_tokens: u32,
     pub messages: Vec<AnthropicMessage>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub system: Option<String>,
+    pub system: Option<Vec<ContentBlock>>, // Text blocks only
     #[serde(skip_serializing_if = "Option::is_none")]
     pub temperature: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_sequences: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<AnthropicTool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<AnthropicToolChoice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub thinking: Option<AnthropicThinking>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
 }
 
+// Body of /v1/messages/count_tokens, the prompt related subset of AnthropicRequest
+#[derive(Serialize, Debug)]
+pub struct AnthropicCountTokensRequest {
+    pub model: String,
+    pub messages: Vec<AnthropicMessage>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system: Option<Vec<ContentBlock>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tools: Option<Vec<AnthropicTool>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicCountTokensResponse {
+    pub input_tokens: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnthropicThinking {
+    #[serde(rename = "type")]
+    pub thinking_type: String,
+    pub budget_tokens: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum AnthropicToolChoice {
+    #[serde(rename = "auto")]
+    Auto {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    #[serde(rename = "any")]
+    Any {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    #[serde(rename = "tool")]
+    Tool {
+        name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        disable_parallel_tool_use: Option<bool>,
+    },
+    #[serde(rename = "none")]
+    None,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AnthropicTool {
     pub name: String,
@@ -71,8 +171,13 @@ pub struct AnthropicResponse {
 
 #[derive(Deserialize, Debug)]
 pub struct Usage {
+    #[serde(default)]
     pub input_tokens: u32,
     pub output_tokens: u32,
+    #[serde(default)]
+    pub cache_creation_input_tokens: Option<u32>,
+    #[serde(default)]
+    pub cache_read_input_tokens: Option<u32>,
 }
 
 // Streaming event types
@@ -98,6 +203,9 @@ pub enum StreamingEvent {
     Ping,
     #[serde(rename = "error")]
     Error { error: ApiError },
+    // Event types added to the API after this client, reported as a non-fatal decode error
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Deserialize, Debug)]
@@ -120,6 +228,32 @@ pub enum Delta {
     TextDelta { text: String },
     #[serde(rename = "input_json_delta")]
     InputJsonDelta { partial_json: String },
+    #[serde(rename = "thinking_delta")]
+    ThinkingDelta { thinking: String },
+    #[serde(rename = "signature_delta")]
+    SignatureDelta { signature: String },
+    #[serde(rename = "citations_delta")]
+    CitationsDelta { citation: AnthropicCitation },
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct AnthropicCitation {
+    #[serde(rename = "type")]
+    pub citation_type: String,
+    #[serde(default)]
+    pub cited_text: String,
+    pub document_index: Option<u32>,
+    pub document_title: Option<String>,
+    pub start_char_index: Option<u32>,
+    pub end_char_index: Option<u32>,
+    pub start_page_number: Option<u32>,
+    pub end_page_number: Option<u32>,
+    pub start_block_index: Option<u32>,
+    pub end_block_index: Option<u32>,
+    pub url: Option<String>,
+    pub title: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -1,13 +1,16 @@
 use base64::{Engine as _, engine::general_purpose};
 use futures_util::{Stream, StreamExt};
-use reqwest::Client;
+use reqwest::{Client, RequestBuilder};
+use reqwest::header::HeaderMap;
 use serde_json::json;
-use std::error::Error;
 use std::pin::Pin;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
 
-use crate::core::{Message, ToolCall, ChatStreamItem, PullProgress, ModelInfo, Tool, FallbackToolHandler, TokenUsage};
+use crate::core::{Message, ToolCall, ChatStreamItem, ChatOptions, ToolChoice, ResponseFormat, PullProgress, ModelInfo, Tool, FallbackToolHandler, FallbackConfig, TokenUsage, Timings, RawChunkHook, AIRequestError, parse_header, is_image_url, call_tools, estimate_tokens, estimate_messages_tokens};
+use crate::core::trace::trace_event;
 use super::{OllamaOptions, ChatResponse, Model, ListModelsResponse};
-use super::utilities::StreamingXmlFilter;
+use super::utilities::{StreamingXmlFilter, render_legacy_template, parse_version};
 
 
 impl Tool {
@@ -23,22 +26,54 @@ impl Tool {
     }
 }
 
+// Headers this client sets on its own requests
+const RESERVED_HEADERS: &[&str] = &["content-type"];
+
+// A streamed generate fragment with the usage reported on the final one
+type GenerateChunk = Result<(String, Option<TokenUsage>), AIRequestError>;
+
+// First release that renders prompts for `_debug_render_only` instead of running the model
+const RENDER_ONLY_MIN_VERSION: (u32, u32, u32) = (0, 12, 0);
+
 pub struct OllamaClient {
     client: Client,
     pub endpoint: String,
     pub model: String,
-    tools: Vec<Tool>,
+    tools: Vec<Arc<Tool>>,
     debug_mode: bool,
+    capture_filtered: bool,
+    response_format: ResponseFormat,
+    keep_alive: Option<String>,
+    context_window: Option<u32>,
+    raw_chunk_hook: Option<RawChunkHook>,
+    headers: HeaderMap, // Custom headers sent with every request
+    api_key: Option<String>, // Bearer token for Ollama cloud and authenticating proxies
+    fallback_config: FallbackConfig,
+    template_cache: Mutex<HashMap<String, String>>, // Chat templates by model name, see `model_template`
 }
 
 impl OllamaClient {
     pub fn new(endpoint: String, model: String) -> Self {
+        Self::with_client(Client::new(), endpoint, model)
+    }
+
+    /// Use a pre-built `reqwest::Client`, e.g. to share a connection pool or set proxies and timeouts
+    pub fn with_client(client: Client, endpoint: String, model: String) -> Self {
         Self {
-            client: Client::new(),
+            client,
             endpoint,
             model,
             tools: Vec::new(),
             debug_mode: false,
+            capture_filtered: false,
+            response_format: ResponseFormat::Text,
+            keep_alive: None,
+            context_window: None,
+            raw_chunk_hook: None,
+            headers: HeaderMap::new(),
+            api_key: None,
+            fallback_config: FallbackConfig::default(),
+            template_cache: Mutex::new(HashMap::new()),
         }
     }
 
@@ -50,14 +85,119 @@ impl OllamaClient {
         self.debug_mode
     }
 
-    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), Box<dyn Error>> {
-        self.tools.push(tool);
+    /// Emit the markup hidden from content (tool call XML, `<think>` blocks) in
+    /// `ChatStreamItem.filtered` instead of dropping it, e.g. for a collapsible panel
+    pub fn set_capture_filtered(&mut self, capture: bool) {
+        self.capture_filtered = capture;
+    }
+
+    pub fn capture_filtered(&self) -> bool {
+        self.capture_filtered
+    }
+
+    /// Constrain responses to JSON via the `format` field
+    pub fn set_response_format(&mut self, response_format: ResponseFormat) {
+        self.response_format = response_format;
+    }
+
+    /// How long the model stays loaded after a request, e.g. `"5m"`, `"0"` to unload
+    /// right away or `"-1"` to keep it loaded. `None` uses the server default
+    pub fn set_keep_alive(&mut self, keep_alive: Option<String>) {
+        self.keep_alive = keep_alive;
+    }
+
+    /// Context window in tokens (`num_ctx`) for every chat, generate and preload request, unless a
+    /// request's `OllamaOptions` set their own. Ollama otherwise uses a small default (2048 or 4096
+    /// tokens depending on the version) and silently drops the start of longer prompts. Larger
+    /// windows take more VRAM for the KV cache, and a model loaded with a different size is reloaded
+    pub fn set_context_window(&mut self, tokens: u32) {
+        self.context_window = Some(tokens);
+    }
+
+    pub fn context_window(&self) -> Option<u32> {
+        self.context_window
+    }
+
+    /// Send a custom header with every request, e.g. for gateway authentication. Headers the
+    /// client sets itself (content-type) are refused rather than silently replaced
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), AIRequestError> {
+        let (name, value) = parse_header(name, value, RESERVED_HEADERS)?;
+        self.headers.insert(name, value);
+        Ok(())
+    }
+
+    /// Send `api_key` as a bearer token with every request, for Ollama cloud or a server behind an
+    /// authenticating proxy. Takes precedence over an `Authorization` header set with `set_header`
+    pub fn set_api_key(&mut self, api_key: Option<String>) {
+        self.api_key = api_key;
+    }
+
+    fn with_api_headers(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request.headers(self.headers.clone());
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key),
+            None => request,
+        }
+    }
+
+    /// Prompt and markup for tool calls on models without native tool support, see `FallbackConfig`
+    pub fn set_fallback_config(&mut self, config: FallbackConfig) {
+        self.fallback_config = config;
+    }
+
+    pub fn fallback_config(&self) -> &FallbackConfig {
+        &self.fallback_config
+    }
+
+    // Hides the tool call markup of the fallback config from content
+    fn xml_filter(&self) -> StreamingXmlFilter {
+        StreamingXmlFilter::with_tool_tags(&self.fallback_config.open_tag, &self.fallback_config.close_tag)
+    }
+
+    /// Pass every raw line of chat response streams to `hook` before parsing, `None` to stop
+    pub fn set_raw_chunk_hook(&mut self, hook: Option<RawChunkHook>) {
+        self.raw_chunk_hook = hook;
+    }
+
+    pub(crate) fn raw_chunk_hook(&self) -> Option<RawChunkHook> {
+        self.raw_chunk_hook.clone()
+    }
+
+    pub(crate) fn extend_tools(&mut self, tools: Vec<Arc<Tool>>) {
+        self.tools.extend(tools);
+    }
+
+    pub(crate) fn response_format(&self) -> &ResponseFormat {
+        &self.response_format
+    }
+
+    pub(crate) fn take_tools(&mut self) -> Vec<Arc<Tool>> {
+        std::mem::take(&mut self.tools)
+    }
+
+    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), AIRequestError> {
+        self.tools.push(Arc::new(tool));
         
         // Tool support is now determined dynamically when needed
         
         Ok(())
     }
 
+    /// Remove a registered tool by name, `false` if there was none
+    pub fn remove_tool(&mut self, name: &str) -> bool {
+        let count = self.tools.len();
+        self.tools.retain(|tool| tool.name != name);
+        self.tools.len() != count
+    }
+
+    pub fn clear_tools(&mut self) {
+        self.tools.clear();
+    }
+
+    pub fn list_tools(&self) -> Vec<&str> {
+        self.tools.iter().map(|tool| tool.name.as_str()).collect()
+    }
+
     pub async fn is_fallback_mode(&self) -> bool {
         if self.tools.is_empty() {
             false // No tools, no fallback needed
@@ -68,21 +208,40 @@ impl OllamaClient {
     }
 
 
-    pub async fn supports_tool_calls(&self) -> Result<bool, Box<dyn Error>> {
-        let model_info = self.show_model_info(&self.model).await?;
-        
+    pub async fn supports_tool_calls(&self) -> Result<bool, AIRequestError> {
+        let template = self.model_template().await?;
+
         // The definitive way to check tool support is the presence of .Tools in the template
         // All models that support tools use the .Tools variable in their prompt template
-        let template = &model_info.template;
         let supports_tools = template.contains(".Tools") || template.contains(".tools");
         
         Ok(supports_tools)
     }
 
-    pub async fn list_local_models(&self) -> Result<Vec<Model>, Box<dyn Error>> {
+    /// Whether the current model accepts images, from its `/api/show` metadata
+    pub async fn supports_vision(&self) -> Result<bool, AIRequestError> {
+        Ok(self.show_model_info(&self.model).await?.supports_vision())
+    }
+
+    /// The current model's chat template, fetched from `/api/show` once per model and reused,
+    /// since tool support is checked on every chat request
+    async fn model_template(&self) -> Result<String, AIRequestError> {
+        if let Some(template) = self.template_cache.lock().unwrap().get(&self.model) {
+            return Ok(template.clone());
+        }
+        let template = self.show_model_info(&self.model).await?.template;
+        self.template_cache.lock().unwrap().insert(self.model.clone(), template.clone());
+        Ok(template)
+    }
+
+    /// Drop cached model info so the next request fetches it again, e.g. after re-creating a model
+    pub fn refresh_model_info(&self) {
+        self.template_cache.lock().unwrap().clear();
+    }
+
+    pub async fn list_local_models(&self) -> Result<Vec<Model>, AIRequestError> {
         let response = self
-            .client
-            .get(&format!("{}/api/tags", self.endpoint))
+            .with_api_headers(self.client.get(format!("{}/api/tags", self.endpoint)))
             .send()
             .await?
             .json::<ListModelsResponse>()
@@ -90,28 +249,195 @@ impl OllamaClient {
         Ok(response.models)
     }
 
-    pub async fn get_available_models(&self) -> Result<Vec<Model>, Box<dyn Error>> {
+    pub async fn get_available_models(&self) -> Result<Vec<Model>, AIRequestError> {
         self.list_local_models().await
     }
 
-    pub async fn show_model_info(&self, model_name: &str) -> Result<ModelInfo, Box<dyn Error>> {
+    /// Whether a model is installed locally. Names must match exactly including the tag,
+    /// with an untagged name meaning `:latest` as in the Ollama CLI.
+    pub async fn model_exists(&self, model_name: &str) -> Result<bool, AIRequestError> {
+        let wanted = if model_name.contains(':') {
+            model_name.to_string()
+        } else {
+            format!("{}:latest", model_name)
+        };
+        let models = self.list_local_models().await?;
+        Ok(models.iter().any(|model| model.name == wanted))
+    }
+
+    pub async fn show_model_info(&self, model_name: &str) -> Result<ModelInfo, AIRequestError> {
         let response = self
-            .client
-            .post(&format!("{}/api/show", self.endpoint))
+            .with_api_headers(self.client.post(format!("{}/api/show", self.endpoint)))
             .json(&json!({ "name": model_name }))
             .send()
-            .await?
-            .json::<ModelInfo>()
             .await?;
-        Ok(response)
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_model_response(response, model_name).await);
+        }
+        Ok(response.json::<ModelInfo>().await?)
     }
 
-    pub async fn pull_model(&self, model_name: &str) -> Result<(), Box<dyn Error>> {
+    /// Ollama server version, e.g. "0.12.3"
+    pub async fn server_version(&self) -> Result<String, AIRequestError> {
+        let response = self
+            .with_api_headers(self.client.get(format!("{}/api/version", self.endpoint)))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_response(response).await);
+        }
+        let body: serde_json::Value = response.json().await?;
+        body["version"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AIRequestError::Decode("Missing version in /api/version response".to_string()))
+    }
+
+    /// Best-effort preview of the prompt the model will see for these messages.
+    ///
+    /// Simple `.System`/`.Prompt`/`.Response` templates are rendered client-side. Templates
+    /// iterating over `.Messages` are rendered by the server without running the model, which
+    /// needs Ollama 0.12 or newer; older servers return an error instead.
+    pub async fn render_prompt(&self, messages: &[Message]) -> Result<String, AIRequestError> {
+        let fallback_mode = self.is_fallback_mode().await;
+        let mut request_body = self.build_chat_body(messages.to_vec(), &ToolChoice::Auto, fallback_mode).await?;
+
+        let template = self.model_template().await?;
+        let body_messages: Vec<Message> = serde_json::from_value(request_body["messages"].clone())?;
+        if let Some(rendered) = render_legacy_template(&template, &body_messages) {
+            return Ok(rendered);
+        }
+
+        // Older servers ignore the render-only flag and run the model, so check the version first
+        let version = self.server_version().await?;
+        if parse_version(&version).is_none_or(|v| v < RENDER_ONLY_MIN_VERSION) {
+            return Err(AIRequestError::Unsupported(format!(
+                "Model template is too complex to render client-side and Ollama {} cannot render it server-side",
+                version
+            )));
+        }
+
+        request_body["stream"] = json!(false);
+        request_body["_debug_render_only"] = json!(true);
+        let response = self
+            .with_api_headers(self.client.post(format!("{}/api/chat", self.endpoint)))
+            .json(&request_body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_model_response(response, &self.model).await);
+        }
+        let body: serde_json::Value = response.json().await?;
+        body["debug_info"]["rendered_template"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| AIRequestError::Decode("Missing rendered_template in render-only response".to_string()))
+    }
+
+    /// Estimated prompt tokens for these messages. Ollama has no tokenize endpoint, so the
+    /// estimate is taken from the client-side rendered prompt, or from the messages when the
+    /// template is too complex to render locally
+    pub async fn count_tokens(&self, messages: &[Message]) -> Result<u32, AIRequestError> {
+        let fallback_mode = self.is_fallback_mode().await;
+        let request_body = self.build_chat_body(messages.to_vec(), &ToolChoice::Auto, fallback_mode).await?;
+        let body_messages: Vec<Message> = serde_json::from_value(request_body["messages"].clone())?;
+
+        let template = self.model_template().await?;
+        Ok(match render_legacy_template(&template, &body_messages) {
+            Some(prompt) => estimate_tokens(&prompt),
+            None => estimate_messages_tokens(&body_messages),
+        })
+    }
+
+    pub async fn copy_model(&self, source: &str, destination: &str) -> Result<(), AIRequestError> {
+        let response = self
+            .with_api_headers(self.client.post(format!("{}/api/copy", self.endpoint)))
+            .json(&json!({ "source": source, "destination": destination }))
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AIRequestError::ModelNotFound {
+                model: source.to_string(),
+            });
+        }
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_response(response).await);
+        }
+        // An existing destination was replaced, so its cached template is stale
+        self.template_cache.lock().unwrap().remove(destination);
+        Ok(())
+    }
+
+    pub async fn delete_model(&self, model_name: &str) -> Result<(), AIRequestError> {
+        let response = self
+            .with_api_headers(self.client.delete(format!("{}/api/delete", self.endpoint)))
+            .json(&json!({ "name": model_name }))
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AIRequestError::ModelNotFound {
+                model: model_name.to_string(),
+            });
+        }
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_response(response).await);
+        }
+        self.template_cache.lock().unwrap().remove(model_name);
+        Ok(())
+    }
+
+    /// Load the model into memory ahead of time. Returns once Ollama reports it loaded
+    pub async fn preload_model(&self) -> Result<(), AIRequestError> {
+        let mut request_body = json!({ "model": self.model });
+        self.apply_keep_alive(&mut request_body);
+        self.apply_context_window(&mut request_body);
+        self.send_load_request(request_body, "load").await
+    }
+
+    /// Free the memory held by the model now instead of waiting for keep_alive to expire
+    pub async fn unload_model(&self) -> Result<(), AIRequestError> {
+        let request_body = json!({ "model": self.model, "keep_alive": 0 });
+        self.send_load_request(request_body, "unload").await
+    }
+
+    /// A generate request without a prompt only loads or unloads the model
+    async fn send_load_request(&self, request_body: serde_json::Value, expected: &str) -> Result<(), AIRequestError> {
+        let response = self
+            .with_api_headers(self.client.post(format!("{}/api/generate", self.endpoint)))
+            .json(&request_body)
+            .send()
+            .await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Err(AIRequestError::ModelNotFound {
+                model: self.model.clone(),
+            });
+        }
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_response(response).await);
+        }
+
+        let body: serde_json::Value = response.json().await?;
+        if body["done"].as_bool() != Some(true) {
+            return Err(AIRequestError::Other(format!("Model {} did not finish: {}", expected, body)));
+        }
+        match body["done_reason"].as_str() {
+            Some(reason) if reason != expected => Err(AIRequestError::Other(format!(
+                "Expected model {} but Ollama reported {}",
+                expected, reason
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn pull_model(&self, model_name: &str) -> Result<(), AIRequestError> {
         println!("Pulling model: {}", model_name);
         let mut stream = self.pull_model_stream(model_name).await?;
 
         while let Some(progress) = stream.next().await {
-            let progress = progress.map_err(|e| format!("Stream error: {}", e))?;
+            let progress = progress?;
             println!("{}", progress.status);
         }
         Ok(())
@@ -120,18 +446,19 @@ impl OllamaClient {
     pub async fn pull_model_stream(
         &self,
         model_name: &str,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<PullProgress, String>> + Send>>, Box<dyn Error>>
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<PullProgress, AIRequestError>> + Send>>, AIRequestError>
     {
+        // A pull can replace the model, template included
+        self.template_cache.lock().unwrap().remove(model_name);
         let stream = self
-            .client
-            .post(&format!("{}/api/pull", self.endpoint))
+            .with_api_headers(self.client.post(format!("{}/api/pull", self.endpoint)))
             .json(&json!({ "name": model_name, "stream": true }))
             .send()
             .await?
             .bytes_stream();
 
         let stream = stream.map(
-            |item| -> Result<Vec<Result<PullProgress, String>>, Box<dyn Error>> {
+            |item| -> Result<Vec<Result<PullProgress, AIRequestError>>, AIRequestError> {
                 let chunk = item?;
                 let lines = chunk.split(|&b| b == b'\n');
                 let mut results = Vec::new();
@@ -175,9 +502,9 @@ impl OllamaClient {
 
         let flattened_stream = stream
             .map(
-                |result: Result<Vec<Result<PullProgress, String>>, Box<dyn Error>>| match result {
+                |result: Result<Vec<Result<PullProgress, AIRequestError>>, AIRequestError>| match result {
                     Ok(items) => futures_util::stream::iter(items),
-                    Err(e) => futures_util::stream::iter(vec![Err(e.to_string())]),
+                    Err(e) => futures_util::stream::iter(vec![Err(e)]),
                 },
             )
             .flatten();
@@ -189,7 +516,7 @@ impl OllamaClient {
         &self,
         messages: &[Message],
         image_paths: Vec<String>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
         self.send_chat_request_with_images_stream_and_options(messages, image_paths, None).await
     }
 
@@ -197,7 +524,7 @@ impl OllamaClient {
         &self,
         messages: &[Message],
         image_paths: Vec<String>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         self.send_chat_request_with_images_no_stream_and_options(messages, image_paths, None).await
     }
 
@@ -206,7 +533,7 @@ impl OllamaClient {
         messages: &[Message],
         image_paths: Vec<String>,
         options: Option<OllamaOptions>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
         let mut encoded_images = Vec::new();
         for image_path in image_paths {
             let image_bytes = std::fs::read(image_path)?;
@@ -216,9 +543,10 @@ impl OllamaClient {
         let mut messages_with_images = messages.to_vec();
         if let Some(last_message) = messages_with_images.last_mut() {
             last_message.images = Some(encoded_images);
+            last_message.image_media_types = None;
         }
 
-        self.send_chat_request_stream_with_options(&messages_with_images, options).await
+        self.send_chat_request_stream_owned(messages_with_images, options).await
     }
 
     pub async fn send_chat_request_with_images_no_stream_and_options(
@@ -226,7 +554,7 @@ impl OllamaClient {
         messages: &[Message],
         image_paths: Vec<String>,
         options: Option<OllamaOptions>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         let mut encoded_images = Vec::new();
         for image_path in image_paths {
             let image_bytes = std::fs::read(image_path)?;
@@ -236,6 +564,7 @@ impl OllamaClient {
         let mut messages_with_images = messages.to_vec();
         if let Some(last_message) = messages_with_images.last_mut() {
             last_message.images = Some(encoded_images);
+            last_message.image_media_types = None;
         }
 
         self.send_chat_request_no_stream_with_options(&messages_with_images, options).await
@@ -245,7 +574,7 @@ impl OllamaClient {
         &self,
         messages: &[Message],
         images_data: Vec<Vec<u8>>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
         self.send_chat_request_with_images_data_stream_and_options(messages, images_data, None).await
     }
 
@@ -253,7 +582,7 @@ impl OllamaClient {
         &self,
         messages: &[Message],
         images_data: Vec<Vec<u8>>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         self.send_chat_request_with_images_data_no_stream_and_options(messages, images_data, None).await
     }
 
@@ -262,7 +591,7 @@ impl OllamaClient {
         messages: &[Message],
         images_data: Vec<Vec<u8>>,
         options: Option<OllamaOptions>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
         let mut encoded_images = Vec::new();
         for image_bytes in images_data {
             encoded_images.push(general_purpose::STANDARD.encode(image_bytes));
@@ -271,9 +600,10 @@ impl OllamaClient {
         let mut messages_with_images = messages.to_vec();
         if let Some(last_message) = messages_with_images.last_mut() {
             last_message.images = Some(encoded_images);
+            last_message.image_media_types = None;
         }
 
-        self.send_chat_request_stream_with_options(&messages_with_images, options).await
+        self.send_chat_request_stream_owned(messages_with_images, options).await
     }
 
     pub async fn send_chat_request_with_images_data_no_stream_and_options(
@@ -281,7 +611,7 @@ impl OllamaClient {
         messages: &[Message],
         images_data: Vec<Vec<u8>>,
         options: Option<OllamaOptions>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         let mut encoded_images = Vec::new();
         for image_bytes in images_data {
             encoded_images.push(general_purpose::STANDARD.encode(image_bytes));
@@ -290,6 +620,7 @@ impl OllamaClient {
         let mut messages_with_images = messages.to_vec();
         if let Some(last_message) = messages_with_images.last_mut() {
             last_message.images = Some(encoded_images);
+            last_message.image_media_types = None;
         }
 
         self.send_chat_request_no_stream_with_options(&messages_with_images, options).await
@@ -298,33 +629,102 @@ impl OllamaClient {
     pub async fn send_chat_request(
         &self,
         messages: &[Message],
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
         self.send_chat_request_stream_with_options(messages, None).await
     }
 
     pub async fn send_chat_request_no_stream(
         &self,
         messages: &[Message],
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         self.send_chat_request_no_stream_with_options(messages, None).await
     }
 
+    /// Complete response from a single `"stream": false` request, with tool call markup filtered
+    /// and fallback tool calls parsed the same way as the streaming path
     pub async fn send_chat_request_no_stream_with_options(
         &self,
         messages: &[Message],
         options: Option<OllamaOptions>,
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
+        let fallback_mode = self.is_fallback_mode().await;
+        let chat_response = self.chat_single(messages, options, fallback_mode).await?;
+        let raw_content = chat_response.message.content;
+        let mut tool_calls = chat_response.message.tool_calls;
+
+        if tool_calls.is_none() && fallback_mode {
+            tool_calls = FallbackToolHandler::parse_fallback_tool_calls_with(&self.fallback_config, &raw_content);
+        }
+        let content = if self.debug_mode {
+            raw_content
+        } else {
+            let mut xml_filter = self.xml_filter();
+            let mut content = xml_filter.process_chunk(&raw_content);
+            content.push_str(&xml_filter.flush().content);
+            content
+        };
+        Ok((content, tool_calls))
+    }
+
+    /// The raw `/api/chat` response of a non-streaming request, including timing fields
+    /// such as `total_duration` that the streamed chunks don't carry
+    pub async fn send_chat_request_single(
+        &self,
+        messages: &[Message],
+        options: Option<OllamaOptions>,
+    ) -> Result<ChatResponse, AIRequestError> {
+        let fallback_mode = self.is_fallback_mode().await;
+        self.chat_single(messages, options, fallback_mode).await
+    }
+
+    async fn chat_single(
+        &self,
+        messages: &[Message],
+        options: Option<OllamaOptions>,
+        fallback_mode: bool,
+    ) -> Result<ChatResponse, AIRequestError> {
+        let mut request_body = self.build_chat_body(messages.to_vec(), &ToolChoice::Auto, fallback_mode).await?;
+        request_body["stream"] = json!(false);
+        if let Some(opts) = options {
+            merge_options(&mut request_body, opts)?;
+        }
+
+        let response = self
+            .with_api_headers(self.client.post(format!("{}/api/chat", self.endpoint)))
+            .json(&request_body)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_model_response(response, &self.model).await);
+        }
+        let body = response.text().await?;
+        if let Some(hook) = &self.raw_chunk_hook {
+            hook(&body);
+        }
+        Ok(serde_json::from_str(&body)?)
+    }
+
+    /// Complete response accumulated from the streaming endpoint
+    pub async fn send_chat_request_no_stream_accumulated(
+        &self,
+        messages: &[Message],
+        options: Option<OllamaOptions>,
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         let mut full_response = String::new();
         let mut tool_calls: Option<Vec<ToolCall>> = None;
         let mut stream = self.send_chat_request_stream_with_options(messages, options).await?;
 
         while let Some(item) = stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
+            let item = match item {
+                Ok(item) => item,
+                Err(error) if !error.is_fatal() => continue,
+                Err(error) => return Err(error),
+            };
             if !item.content.is_empty() {
                 full_response.push_str(&item.content);
             }
             if let Some(tc) = item.tool_calls {
-                tool_calls = Some(tc);
+                tool_calls.get_or_insert_with(Vec::new).extend(tc);
             }
             if item.done {
                 return Ok((full_response, tool_calls));
@@ -333,27 +733,46 @@ impl OllamaClient {
         Ok((full_response, tool_calls))
     }
 
-    pub async fn send_chat_request_stream(
+    async fn build_chat_body(
         &self,
-        messages: &[Message],
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>>
-    {
-        self.send_chat_request_stream_with_options(messages, None).await
+        mut messages_to_send: Vec<Message>,
+        tool_choice: &ToolChoice,
+        fallback_mode: bool,
+    ) -> Result<serde_json::Value, AIRequestError> {
+        self.download_remote_images(&mut messages_to_send).await?;
+        Ok(self.chat_body(messages_to_send, tool_choice, fallback_mode))
     }
 
-    pub async fn send_chat_request_stream_with_options(
+    /// Chat request body as sent to `/api/chat`, including fallback tool context injection.
+    /// Ollama has no native tool_choice, so it is emulated by filtering tools and instructing the model
+    fn chat_body(
         &self,
-        messages: &[Message],
-        options: Option<OllamaOptions>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>>
-    {
-        let mut messages_to_send = messages.to_vec();
+        mut messages_to_send: Vec<Message>,
+        tool_choice: &ToolChoice,
+        fallback_mode: bool,
+    ) -> serde_json::Value {
+        let tools: Vec<&Tool> = match tool_choice {
+            ToolChoice::None => Vec::new(),
+            ToolChoice::Specific(name) => self.tools.iter().filter(|t| &t.name == name).map(|t| t.as_ref()).collect(),
+            ToolChoice::Auto | ToolChoice::Required => self.tools.iter().map(|t| t.as_ref()).collect(),
+        };
         
         // In fallback mode, inject tool context into the system message
-        let is_fallback = self.is_fallback_mode().await;
-        if is_fallback && !self.tools.is_empty() {
-            let tool_context = FallbackToolHandler::generate_tool_context(&self.tools);
-            
+        let mut tool_context = String::new();
+        if fallback_mode && !tools.is_empty() {
+            tool_context = FallbackToolHandler::generate_tool_context_with(&self.fallback_config, tools.iter().copied());
+        }
+        match tool_choice {
+            ToolChoice::Required if !tools.is_empty() => {
+                tool_context.push_str("\n\nYou must call at least one of the available tools in your response.");
+            }
+            ToolChoice::Specific(name) if !tools.is_empty() => {
+                tool_context.push_str(&format!("\n\nYou must call the {} tool in your response.", name));
+            }
+            _ => {}
+        }
+
+        if !tool_context.is_empty() {
             // Find existing system message or create one
             if let Some(system_msg) = messages_to_send.iter_mut().find(|msg| msg.role == "system") {
                 system_msg.content.push_str(&tool_context);
@@ -362,8 +781,7 @@ impl OllamaClient {
                 messages_to_send.insert(0, Message {
                     role: "system".to_string(),
                     content: format!("You are a helpful assistant.{}", tool_context),
-                    images: None,
-                    tool_calls: None,
+                    ..Default::default()
                 });
             }
         }
@@ -375,101 +793,281 @@ impl OllamaClient {
         });
 
         // Only add tools if not in fallback mode
-        if !is_fallback && !self.tools.is_empty() {
+        if !fallback_mode && !tools.is_empty() {
             let tools_json: Vec<serde_json::Value> =
-                self.tools.iter().map(|t| t.to_json()).collect();
+                tools.iter().map(|t| t.to_json()).collect();
             request_body["tools"] = serde_json::Value::Array(tools_json);
         }
 
+        apply_response_format(&mut request_body, &self.response_format);
+        self.apply_keep_alive(&mut request_body);
+        self.apply_context_window(&mut request_body);
+        request_body
+    }
+
+    /// `keep_alive` is a top-level field of the request, not one of the model `options`
+    fn apply_keep_alive(&self, request_body: &mut serde_json::Value) {
+        if let Some(keep_alive) = &self.keep_alive {
+            request_body["keep_alive"] = json!(keep_alive);
+        }
+    }
+
+    // Set before the request options, which may override it
+    fn apply_context_window(&self, request_body: &mut serde_json::Value) {
+        if let Some(tokens) = self.context_window {
+            request_body["options"]["num_ctx"] = json!(tokens);
+        }
+    }
+
+    /// Ollama only accepts base64 images, so image URLs are fetched and encoded in place
+    async fn download_remote_images(&self, messages: &mut [Message]) -> Result<(), AIRequestError> {
+        for message in messages.iter_mut() {
+            let Some(images) = message.images.as_mut() else { continue };
+            for image in images.iter_mut() {
+                self.encode_remote_image(image).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn encode_remote_image(&self, image: &mut String) -> Result<(), AIRequestError> {
+        if is_image_url(image) {
+            let response = self.client.get(image.as_str()).send().await?;
+            if !response.status().is_success() {
+                return Err(AIRequestError::from_response(response).await);
+            }
+            let bytes = response.bytes().await?;
+            *image = general_purpose::STANDARD.encode(bytes);
+        }
+        Ok(())
+    }
+
+    pub async fn send_chat_request_stream(
+        &self,
+        messages: &[Message],
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError>
+    {
+        self.send_chat_request_stream_with_options(messages, None).await
+    }
+
+    pub async fn send_chat_request_stream_with_options(
+        &self,
+        messages: &[Message],
+        options: Option<OllamaOptions>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError>
+    {
+        self.send_chat_request_stream_owned(messages.to_vec(), options).await
+    }
+
+    /// Like `send_chat_request_stream_with_options`, taking the history by value so it is extended
+    /// (tool context, downloaded images) in place rather than copied
+    pub async fn send_chat_request_stream_owned(
+        &self,
+        messages: Vec<Message>,
+        options: Option<OllamaOptions>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError>
+    {
+        // Checked once per request, since it may need a round trip to the server
+        let fallback_mode = self.is_fallback_mode().await;
+        let mut request_body = self.build_chat_body(messages, &ToolChoice::Auto, fallback_mode).await?;
+
         if let Some(opts) = options {
-            request_body["options"] = serde_json::to_value(opts)?;
+            merge_options(&mut request_body, opts)?;
         }
 
-        let stream = self
-            .client
-            .post(&format!("{}/api/chat", self.endpoint))
+        self.stream_chat(request_body, fallback_mode).await
+    }
+
+    /// Streaming chat with the cross-provider `ChatOptions`
+    pub async fn send_chat_request_with_chat_options(
+        &self,
+        messages: &[Message],
+        options: &ChatOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError>
+    {
+        self.send_chat_request_owned_with_chat_options(messages.to_vec(), options).await
+    }
+
+    /// `send_chat_request_with_chat_options` taking the history by value, see `send_chat_request_stream_owned`
+    pub async fn send_chat_request_owned_with_chat_options(
+        &self,
+        messages: Vec<Message>,
+        options: &ChatOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError>
+    {
+        let tool_choice = options.tool_choice.clone().unwrap_or_default();
+        let fallback_mode = self.is_fallback_mode().await;
+        let mut request_body = self.build_chat_body(messages, &tool_choice, fallback_mode).await?;
+        apply_chat_options(&mut request_body, options)?;
+
+        self.stream_chat(request_body, fallback_mode).await
+    }
+
+    /// The body `send_chat_request_with_chat_options` would post to `/api/chat`, including the tool
+    /// context injected in fallback mode, without sending it. Image URLs are left as they are
+    /// instead of being downloaded
+    pub async fn build_request_json(
+        &self,
+        messages: &[Message],
+        options: &ChatOptions,
+    ) -> Result<serde_json::Value, AIRequestError> {
+        let tool_choice = options.tool_choice.clone().unwrap_or_default();
+        let fallback_mode = self.is_fallback_mode().await;
+        let mut request_body = self.chat_body(messages.to_vec(), &tool_choice, fallback_mode);
+        apply_chat_options(&mut request_body, options)?;
+        Ok(request_body)
+    }
+
+    async fn stream_chat(
+        &self,
+        request_body: serde_json::Value,
+        fallback_mode: bool,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError>
+    {
+        let response = self
+            .with_api_headers(self.client.post(format!("{}/api/chat", self.endpoint)))
             .json(&request_body)
             .send()
-            .await?
-            .bytes_stream();
+            .await?;
+        // Errors such as a model that isn't pulled come as a plain JSON body, not a stream
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_model_response(response, &self.model).await);
+        }
+        let stream = response.bytes_stream();
 
-        let fallback_mode = self.is_fallback_mode().await;
         let debug_mode = self.debug_mode;
+        let capture_filtered = self.capture_filtered;
+        let raw_chunk_hook = self.raw_chunk_hook.clone();
+        let fallback_config = self.fallback_config.clone();
         
         // Create a stateful stream that handles tool calling internally
         let stream = futures_util::stream::unfold(
-            (stream, StreamingXmlFilter::new(), String::new(), false),
-            move |(mut stream, mut xml_filter, mut accumulated_raw, mut stream_done)| async move {
-                match stream.next().await {
-                    Some(chunk_result) => {
-                        match chunk_result {
-                            Ok(chunk) => {
-                                let lines = chunk.split(|&b| b == b'\n');
-                                let mut results = Vec::new();
-
-                                for line in lines {
-                                    if line.is_empty() {
-                                        continue;
-                                    }
-                                    match serde_json::from_slice::<ChatResponse>(&line) {
-                                        Ok(chat_response) => {
-                                            let mut tool_calls = chat_response.message.tool_calls.clone();
-                                            let raw_content = chat_response.message.content.clone();
+            (stream, self.xml_filter(), String::new(), 0, false, Vec::new()),
+            move |(mut stream, mut xml_filter, mut accumulated_raw, mut parsed_until, mut stream_done, mut pending_line)| {
+                let raw_chunk_hook = raw_chunk_hook.clone();
+                let fallback_config = fallback_config.clone();
+                async move {
+                    let next = match stream.next().await {
+                        // A last line without a trailing newline still gets parsed
+                        None if !pending_line.is_empty() => {
+                            pending_line.push(b'\n');
+                            Some(Ok(Default::default()))
+                        }
+                        next => next,
+                    };
+                    match next {
+                        Some(chunk_result) => {
+                            match chunk_result {
+                                Ok(chunk) => {
+                                    // Lines can be split across network chunks, only complete ones are parsed
+                                    pending_line.extend_from_slice(&chunk);
+                                    let complete: Vec<u8> = match pending_line.iter().rposition(|&b| b == b'\n') {
+                                        Some(end) => pending_line.drain(..=end).collect(),
+                                        None => Vec::new(),
+                                    };
+                                    let lines = complete.split(|&b| b == b'\n');
+                                    let mut results = Vec::new();
+
+                                    for line in lines {
+                                        if line.is_empty() {
+                                            continue;
+                                        }
+                                        if let Some(hook) = &raw_chunk_hook {
+                                            hook(&String::from_utf8_lossy(line));
+                                        }
+                                        match serde_json::from_slice::<ChatResponse>(line) {
+                                            Ok(chat_response) => {
+                                                let mut tool_calls = chat_response.message.tool_calls.clone();
+                                                let raw_content = chat_response.message.content.clone();
                                             
-                                            // Accumulate raw content for fallback tool detection
-                                            accumulated_raw.push_str(&raw_content);
+                                                // Accumulate raw content for fallback tool detection
+                                                accumulated_raw.push_str(&raw_content);
                                             
-                                            // Apply XML filtering when debug is disabled
-                                            let content = if !debug_mode {
-                                                xml_filter.process_chunk(&raw_content)
-                                            } else {
-                                                raw_content.clone()
-                                            };
+                                                // Apply XML filtering when debug is disabled, keeping <think> text as reasoning
+                                                let (content, reasoning, filtered) = if !debug_mode {
+                                                    let mut split = xml_filter.split_chunk(&raw_content);
+                                                    if chat_response.done {
+                                                        let rest = xml_filter.flush();
+                                                        split.content.push_str(&rest.content);
+                                                        split.reasoning.push_str(&rest.reasoning);
+                                                        split.filtered.push_str(&rest.filtered);
+                                                    }
+                                                    (
+                                                        split.content,
+                                                        Some(split.reasoning).filter(|r| !r.is_empty()),
+                                                        Some(split.filtered).filter(|f| capture_filtered && !f.is_empty()),
+                                                    )
+                                                } else {
+                                                    (raw_content.clone(), None, None)
+                                                };
                                             
-                                            // On stream completion, check for fallback tool calls
-                                            if chat_response.done && fallback_mode && tool_calls.is_none() {
-                                                if let Some(fallback_tools) = FallbackToolHandler::parse_fallback_tool_calls(&accumulated_raw) {
-                                                    tool_calls = Some(fallback_tools);
+                                                // Fallback tool calls fire as soon as their closing tag arrives,
+                                                // each in the item that completes it
+                                                if fallback_mode && tool_calls.is_none() {
+                                                    let (mut fallback_tools, end) = FallbackToolHandler::parse_closed_tool_calls_with(&fallback_config, &accumulated_raw[parsed_until..]);
+                                                    parsed_until += end;
+                                                    // An unclosed last block only counts once the stream ends
+                                                    if chat_response.done {
+                                                        if let Some(rest) = FallbackToolHandler::parse_fallback_tool_calls_with(&fallback_config, &accumulated_raw[parsed_until..]) {
+                                                            fallback_tools.extend(rest);
+                                                        }
+                                                        stream_done = true;
+                                                    }
+                                                    if !fallback_tools.is_empty() {
+                                                        tool_calls = Some(fallback_tools);
+                                                    }
                                                 }
-                                                stream_done = true;
-                                            }
                                             
-                                            // Extract token usage if available (usually only on done=true)
-                                            let usage = if chat_response.done {
-                                                if let (Some(prompt_tokens), Some(completion_tokens)) = 
-                                                    (chat_response.prompt_eval_count, chat_response.eval_count) {
-                                                    Some(TokenUsage {
-                                                        prompt_tokens: Some(prompt_tokens),
-                                                        completion_tokens: Some(completion_tokens),
-                                                        total_tokens: Some(prompt_tokens + completion_tokens),
-                                                    })
+                                                // Extract token usage if available (usually only on done=true)
+                                                let usage = if chat_response.done {
+                                                    if let (Some(prompt_tokens), Some(completion_tokens)) = 
+                                                        (chat_response.prompt_eval_count, chat_response.eval_count) {
+                                                        Some(TokenUsage {
+                                                            prompt_tokens: Some(prompt_tokens),
+                                                            completion_tokens: Some(completion_tokens),
+                                                            total_tokens: Some(prompt_tokens + completion_tokens),
+                                                            cost_usd: None,
+                                                            generation_id: None,
+                                                            cache_creation_tokens: None,
+                                                            cache_read_tokens: None,
+                                                        })
+                                                    } else {
+                                                        None
+                                                    }
                                                 } else {
                                                     None
-                                                }
-                                            } else {
-                                                None
-                                            };
+                                                };
+                                                let timings = chat_response.done.then(|| response_timings(&chat_response));
                                             
-                                            results.push(Ok(ChatStreamItem {
-                                                content,
-                                                tool_calls,
-                                                done: chat_response.done,
-                                                usage,
-                                            }));
-                                        }
-                                        Err(e) => {
-                                            eprintln!("\nError parsing response: {}", e);
-                                            eprintln!("Problematic line: {:?}", String::from_utf8_lossy(&line));
+                                                results.push(Ok(ChatStreamItem {
+                                                    content,
+                                                    tool_calls,
+                                                    done: chat_response.done,
+                                                    usage,
+                                                    finish_reason: chat_response.done_reason,
+                                                    reasoning,
+                                                    filtered,
+                                                    citations: None,
+                                                    timings,
+                                                }));
+                                            }
+                                            Err(e) => {
+                                                // Left to the consumer, see `AIRequestError::is_fatal`
+                                                trace_event!(warn, error = %e, line = %String::from_utf8_lossy(line), "unparseable Ollama stream line");
+                                                results.push(Err(AIRequestError::Decode(format!(
+                                                    "{} in line {:?}", e, String::from_utf8_lossy(line)
+                                                ))));
+                                            }
                                         }
                                     }
-                                }
                                 
-                                Some((Ok(results), (stream, xml_filter, accumulated_raw, stream_done)))
+                                    Some((Ok(results), (stream, xml_filter, accumulated_raw, parsed_until, stream_done, pending_line)))
+                                }
+                                Err(e) => Some((Err(AIRequestError::Network(e)), (stream, xml_filter, accumulated_raw, parsed_until, stream_done, pending_line)))
                             }
-                            Err(e) => Some((Err(Box::new(e) as Box<dyn Error>), (stream, xml_filter, accumulated_raw, stream_done)))
                         }
+                        None => None
                     }
-                    None => None
                 }
             }
         );
@@ -478,7 +1076,7 @@ impl OllamaClient {
             .map(
                 |result| match result {
                     Ok(items) => futures_util::stream::iter(items),
-                    Err(e) => futures_util::stream::iter(vec![Err(e.to_string())]),
+                    Err(e) => futures_util::stream::iter(vec![Err(e)]),
                 },
             )
             .flatten();
@@ -489,7 +1087,7 @@ impl OllamaClient {
     pub async fn generate(
         &self,
         prompt: &str,
-    ) -> Result<String, Box<dyn Error>> {
+    ) -> Result<String, AIRequestError> {
         self.generate_with_options(prompt, None).await
     }
 
@@ -497,35 +1095,95 @@ impl OllamaClient {
         &self,
         prompt: &str,
         options: Option<OllamaOptions>,
-    ) -> Result<String, Box<dyn Error>> {
+    ) -> Result<String, AIRequestError> {
+        let (response, _) = self.generate_with_usage(prompt, options).await?;
+        Ok(response)
+    }
+
+    /// Like `generate_with_options`, also returning the prompt and eval token counts of the response
+    pub async fn generate_with_usage(
+        &self,
+        prompt: &str,
+        options: Option<OllamaOptions>,
+    ) -> Result<(String, Option<TokenUsage>), AIRequestError> {
+        self.generate_with_images_and_usage(prompt, Vec::new(), options).await
+    }
+
+    /// `generate_with_images` with options, along with the eval counts as token usage
+    pub async fn generate_with_images_and_usage(
+        &self,
+        prompt: &str,
+        images: Vec<String>,
+        options: Option<OllamaOptions>,
+    ) -> Result<(String, Option<TokenUsage>), AIRequestError> {
+        let request_body = self.build_generate_body(prompt, images, options, false).await?;
+        self.generate_single(request_body).await
+    }
+
+    /// Single completion for a prompt about `images` (base64 data or image URLs), e.g. for captions
+    /// or OCR with a vision model without building a conversation
+    pub async fn generate_with_images(
+        &self,
+        prompt: &str,
+        images: Vec<String>,
+    ) -> Result<String, AIRequestError> {
+        let (response, _) = self.generate_with_images_and_usage(prompt, images, None).await?;
+        Ok(response)
+    }
+
+    async fn build_generate_body(
+        &self,
+        prompt: &str,
+        mut images: Vec<String>,
+        options: Option<OllamaOptions>,
+        stream: bool,
+    ) -> Result<serde_json::Value, AIRequestError> {
         let mut request_body = json!({
             "model": self.model,
             "prompt": prompt,
-            "stream": false,
+            "stream": stream,
         });
+        self.apply_keep_alive(&mut request_body);
+        self.apply_context_window(&mut request_body);
 
         if let Some(opts) = options {
-            request_body["options"] = serde_json::to_value(opts)?;
+            merge_options(&mut request_body, opts)?;
+        }
+        if !images.is_empty() {
+            for image in images.iter_mut() {
+                self.encode_remote_image(image).await?;
+            }
+            request_body["images"] = json!(images);
         }
+        Ok(request_body)
+    }
 
+    async fn generate_single(
+        &self,
+        request_body: serde_json::Value,
+    ) -> Result<(String, Option<TokenUsage>), AIRequestError> {
         let response = self
-            .client
-            .post(&format!("{}/api/generate", self.endpoint))
+            .with_api_headers(self.client.post(format!("{}/api/generate", self.endpoint)))
             .json(&request_body)
             .send()
             .await?;
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_model_response(response, &self.model).await);
+        }
 
         let response_json: serde_json::Value = response.json().await?;
-        Ok(response_json["response"]
+        let usage = generate_usage(&response_json);
+        let text = response_json["response"]
             .as_str()
             .unwrap_or("")
-            .to_string())
+            .to_string();
+        Ok((text, usage))
     }
 
     pub async fn generate_stream(
         &self,
         prompt: &str,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AIRequestError>> + Send>>, AIRequestError> {
         self.generate_stream_with_options(prompt, None).await
     }
 
@@ -533,27 +1191,42 @@ impl OllamaClient {
         &self,
         prompt: &str,
         options: Option<OllamaOptions>,
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, String>> + Send>>, Box<dyn Error>> {
-        let mut request_body = json!({
-            "model": self.model,
-            "prompt": prompt,
-            "stream": true,
-        });
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AIRequestError>> + Send>>, AIRequestError> {
+        let stream = self.generate_stream_with_usage(prompt, Vec::new(), options).await?;
+        Ok(Box::pin(stream.map(|item| item.map(|(text, _)| text))))
+    }
 
-        if let Some(opts) = options {
-            request_body["options"] = serde_json::to_value(opts)?;
-        }
+    /// Streaming variant of `generate_with_images`
+    pub async fn generate_stream_with_images(
+        &self,
+        prompt: &str,
+        images: Vec<String>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<String, AIRequestError>> + Send>>, AIRequestError> {
+        let stream = self.generate_stream_with_usage(prompt, images, None).await?;
+        Ok(Box::pin(stream.map(|item| item.map(|(text, _)| text))))
+    }
 
-        let stream = self
-            .client
-            .post(&format!("{}/api/generate", self.endpoint))
+    /// Streaming generate yielding each text fragment with the token usage, which the final
+    /// fragment carries
+    pub async fn generate_stream_with_usage(
+        &self,
+        prompt: &str,
+        images: Vec<String>,
+        options: Option<OllamaOptions>,
+    ) -> Result<Pin<Box<dyn Stream<Item = GenerateChunk> + Send>>, AIRequestError> {
+        let request_body = self.build_generate_body(prompt, images, options, true).await?;
+        let response = self
+            .with_api_headers(self.client.post(format!("{}/api/generate", self.endpoint)))
             .json(&request_body)
             .send()
-            .await?
-            .bytes_stream();
+            .await?;
+        if !response.status().is_success() {
+            return Err(AIRequestError::from_model_response(response, &self.model).await);
+        }
+        let stream = response.bytes_stream();
 
         let stream = stream.map(
-            |item| -> Result<Vec<Result<String, String>>, Box<dyn Error>> {
+            |item| -> Result<Vec<GenerateChunk>, AIRequestError> {
                 let chunk = item?;
                 let lines = chunk.split(|&b| b == b'\n');
                 let mut results = Vec::new();
@@ -563,14 +1236,14 @@ impl OllamaClient {
                         continue;
                     }
 
-                    match serde_json::from_slice::<serde_json::Value>(&line) {
+                    match serde_json::from_slice::<serde_json::Value>(line) {
                         Ok(json) => {
                             if let Some(response) = json["response"].as_str() {
-                                results.push(Ok(response.to_string()));
+                                results.push(Ok((response.to_string(), generate_usage(&json))));
                             }
                         }
                         Err(e) => {
-                            results.push(Err(format!("Parse error: {}", e)));
+                            results.push(Err(AIRequestError::Decode(e.to_string())));
                         }
                     }
                 }
@@ -581,9 +1254,9 @@ impl OllamaClient {
 
         let flattened_stream = stream
             .map(
-                |result: Result<Vec<Result<String, String>>, Box<dyn Error>>| match result {
+                |result: Result<Vec<GenerateChunk>, AIRequestError>| match result {
                     Ok(items) => futures_util::stream::iter(items),
-                    Err(e) => futures_util::stream::iter(vec![Err(e.to_string())]),
+                    Err(e) => futures_util::stream::iter(vec![Err(e)]),
                 },
             )
             .flatten();
@@ -592,28 +1265,26 @@ impl OllamaClient {
     }
 
     pub async fn handle_tool_calls(&self, tool_calls: Vec<ToolCall>) -> Vec<Message> {
+        let results = call_tools(&self.tools, &tool_calls).await;
+        let is_fallback = self.is_fallback_mode().await;
         let mut tool_responses = Vec::new();
-        for tool_call in tool_calls {
-            if let Some(tool) = self
-                .tools
-                .iter()
-                .find(|t| t.name == tool_call.function.name)
-            {
-                let result = (tool.function)(tool_call.function.arguments.clone());
-                
+        for (tool_call, result) in tool_calls.into_iter().zip(results) {
+            if let Some(result) = result {
                 // In fallback mode, format tool response as user message with tool context
-                let is_fallback = self.is_fallback_mode().await;
-                let (role, content) = if is_fallback {
-                    ("user".to_string(), format!("Tool response from {}: {}", tool_call.function.name, result))
+                let (role, content, tool_name, tool_call_id) = if is_fallback {
+                    ("user".to_string(), format!("Tool response from {}: {}", tool_call.function.name, result), None, None)
                 } else {
-                    ("tool".to_string(), result)
+                    // Native results name their tool, and carry the call id on servers that assign
+                    // one, so the model can match them to the calls
+                    ("tool".to_string(), result, Some(tool_call.function.name.clone()), tool_call.id.clone())
                 };
                 
                 tool_responses.push(Message {
                     role,
                     content,
-                    images: None,
-                    tool_calls: None,
+                    tool_name,
+                    tool_call_id,
+                    ..Default::default()
                 });
             }
         }
@@ -626,6 +1297,109 @@ impl OllamaClient {
             return (content.to_string(), None);
         }
 
-        FallbackToolHandler::process_fallback_response(content)
+        FallbackToolHandler::process_fallback_response_with(&self.fallback_config, content)
+    }
+}
+
+// Per-request settings on top of the client defaults already in the body
+fn apply_chat_options(request_body: &mut serde_json::Value, options: &ChatOptions) -> Result<(), AIRequestError> {
+    if let Some(opts) = OllamaOptions::from_chat_options(options) {
+        merge_options(request_body, opts)?;
+    }
+    if let Some(response_format) = &options.response_format {
+        apply_response_format(request_body, response_format);
+    }
+    Ok(())
+}
+
+// Adds the set options to those already in the body, such as the client's context window
+fn merge_options(request_body: &mut serde_json::Value, options: OllamaOptions) -> Result<(), AIRequestError> {
+    if let serde_json::Value::Object(options) = serde_json::to_value(options)? {
+        for (key, value) in options {
+            request_body["options"][key] = value;
+        }
+    }
+    Ok(())
+}
+
+fn apply_response_format(request_body: &mut serde_json::Value, response_format: &ResponseFormat) {
+    match response_format {
+        ResponseFormat::Text => {
+            if let Some(body) = request_body.as_object_mut() {
+                body.remove("format");
+            }
+        }
+        ResponseFormat::Json => request_body["format"] = json!("json"),
+        ResponseFormat::JsonSchema(schema) => request_body["format"] = schema.clone(),
+    }
+}
+
+/// Durations from the final chat response, with tokens/sec derived from `eval_count` and `eval_duration`
+fn response_timings(chat_response: &ChatResponse) -> Timings {
+    let tokens_per_second = match (chat_response.eval_count, chat_response.eval_duration) {
+        (Some(count), Some(duration)) if duration > 0 => Some(count as f64 / (duration as f64 / 1_000_000_000.0)),
+        _ => None,
+    };
+    Timings {
+        total_duration: chat_response.total_duration,
+        load_duration: chat_response.load_duration,
+        prompt_eval_duration: chat_response.prompt_eval_duration,
+        eval_duration: chat_response.eval_duration,
+        tokens_per_second,
+    }
+}
+
+/// Token usage from the eval counts of a final `/api/generate` response
+fn generate_usage(response_json: &serde_json::Value) -> Option<TokenUsage> {
+    match (response_json["prompt_eval_count"].as_u64(), response_json["eval_count"].as_u64()) {
+        (Some(prompt_tokens), Some(completion_tokens)) => {
+            Some(TokenUsage::with_tokens(prompt_tokens as u32, completion_tokens as u32))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: "user".to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn seed_sent_as_an_option() {
+        let client = OllamaClient::new("http://localhost:11434".to_string(), "llama3.2".to_string());
+        let messages = [user("Hi")];
+
+        let body = client.build_request_json(&messages, &ChatOptions::default()).await.unwrap();
+        assert!(body.get("options").is_none());
+
+        let options = ChatOptions { seed: Some(4_000_000_000), ..Default::default() };
+        let body = client.build_request_json(&messages, &options).await.unwrap();
+        assert_eq!(body["options"]["seed"], json!(4_000_000_000i64));
+        assert!(body.get("seed").is_none());
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn context_window_sent_as_num_ctx() {
+        let mut client = OllamaClient::new("http://localhost:11434".to_string(), "llama3.2".to_string());
+        client.set_context_window(16384);
+        let options = ChatOptions { temperature: Some(0.2), ..Default::default() };
+
+        let mut body = client.build_request_json(&[user("Hi")], &options).await.unwrap();
+        assert_eq!(body["options"]["num_ctx"], json!(16384));
+        assert_eq!(body["options"]["temperature"], json!(0.2f32));
+
+        // Per-request options override the client's window and keep the other options
+        merge_options(&mut body, OllamaOptions { num_ctx: Some(4096), ..Default::default() }).unwrap();
+        assert_eq!(body["options"]["num_ctx"], json!(4096));
+        assert_eq!(body["options"]["temperature"], json!(0.2f32));
+    }
+}
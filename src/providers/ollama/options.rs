@@ -1,5 +1,7 @@
 use serde::Serialize;
 
+use crate::core::ChatOptions;
+
 #[derive(Serialize, Debug, Default)]
 pub struct OllamaOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -11,7 +13,11 @@ pub struct OllamaOptions {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub repeat_penalty: Option<f32>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub seed: Option<i32>,
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub num_predict: Option<i32>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -38,4 +44,61 @@ pub struct OllamaOptions {
     pub use_mlock: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub num_thread: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+}
+
+impl OllamaOptions {
+    /// Ollama equivalents of the cross-provider options, or `None` when nothing is set
+    pub fn from_chat_options(options: &ChatOptions) -> Option<Self> {
+        if options.temperature.is_none()
+            && options.top_p.is_none()
+            && options.max_tokens.is_none()
+            && options.stop.is_none()
+            && options.seed.is_none()
+            && options.frequency_penalty.is_none()
+            && options.presence_penalty.is_none()
+        {
+            return None;
+        }
+        Some(Self {
+            temperature: options.temperature,
+            top_p: options.top_p,
+            num_predict: options.max_tokens.map(|tokens| tokens as i32),
+            stop: options.stop.clone(),
+            seed: options.seed,
+            frequency_penalty: options.frequency_penalty,
+            presence_penalty: options.presence_penalty,
+            ..Default::default()
+        })
+    }
+    /// These options with unset cross-provider settings taken from `defaults`
+    pub fn with_chat_defaults(self, defaults: &ChatOptions) -> Self {
+        Self {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            num_predict: self.num_predict.or(defaults.max_tokens.map(|tokens| tokens as i32)),
+            stop: self.stop.or_else(|| defaults.stop.clone()),
+            seed: self.seed.or(defaults.seed),
+            frequency_penalty: self.frequency_penalty.or(defaults.frequency_penalty),
+            presence_penalty: self.presence_penalty.or(defaults.presence_penalty),
+            ..self
+        }
+    }
+
+    /// The options that have a cross-provider equivalent, for sending them to cloud providers.
+    /// Model loading and hardware settings such as `num_ctx` or `num_gpu` are Ollama-only and dropped
+    pub fn to_chat_options(&self) -> ChatOptions {
+        ChatOptions {
+            temperature: self.temperature,
+            top_p: self.top_p,
+            // Ollama uses -1 and -2 for unlimited and fill the context
+            max_tokens: self.num_predict.and_then(|tokens| u32::try_from(tokens).ok()),
+            stop: self.stop.clone(),
+            seed: self.seed,
+            frequency_penalty: self.frequency_penalty,
+            presence_penalty: self.presence_penalty,
+            ..Default::default()
+        }
+    }
 }
\ No newline at end of file
@@ -5,6 +5,12 @@ use crate::core::Message;
 pub struct ChatResponse {
     pub message: Message,
     pub done: bool,
+    #[serde(default)]
+    pub done_reason: Option<String>,
+    #[serde(default)]
+    pub total_duration: Option<u64>,
+    #[serde(default)]
+    pub load_duration: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub prompt_eval_count: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
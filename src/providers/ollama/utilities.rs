@@ -1,35 +1,473 @@
+use crate::core::Message;
+
 pub struct StreamingXmlFilter {
     inside_tool_call: bool,
+    inside_think: bool,
+    // Drop the whitespace models put between </think> and the answer
+    trim_after_think: bool,
+    tool_open_tag: String,
+    tool_close_tag: String,
+    pending: String, // Chunk tail that may be the start of a tag split across chunks
+}
+
+impl Default for StreamingXmlFilter {
+    fn default() -> Self {
+        Self {
+            inside_tool_call: false,
+            inside_think: false,
+            trim_after_think: false,
+            tool_open_tag: "<tool_call>".to_string(),
+            tool_close_tag: "</tool_call>".to_string(),
+            pending: String::new(),
+        }
+    }
+}
+
+/// A chunk split into visible content and the reasoning found inside `<think>` tags
+#[derive(Debug, Default)]
+pub struct FilteredChunk {
+    pub content: String,
+    pub reasoning: String,
+    pub filtered: String, // Everything removed from content, tags included
 }
 
 impl StreamingXmlFilter {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Filter tool calls wrapped in the markup of a `FallbackConfig` instead of `<tool_call>` tags
+    pub fn with_tool_tags(open_tag: impl Into<String>, close_tag: impl Into<String>) -> Self {
         Self {
-            inside_tool_call: false,
+            tool_open_tag: open_tag.into(),
+            tool_close_tag: close_tag.into(),
+            ..Self::default()
         }
     }
 
     pub fn process_chunk(&mut self, content: &str) -> String {
-        if content.is_empty() {
-            return content.to_string();
-        }
-
-        let mut result = content.to_string();
-        
-        if content.contains("<tool_call>") {
-            self.inside_tool_call = true;
-            result = String::new();
-        } else if content.contains("</tool_call>") {
-            self.inside_tool_call = false;
-            result = String::new();
-        } else if self.inside_tool_call {
-            result = String::new();
+        self.split_chunk(content).content
+    }
+
+    /// Removes tool call blocks and moves `<think>` blocks into `reasoning`. A chunk ending in
+    /// what may be the start of a tag is held back until the next chunk or `flush`
+    pub fn split_chunk(&mut self, content: &str) -> FilteredChunk {
+        let mut result = FilteredChunk::default();
+        let input = std::mem::take(&mut self.pending) + content;
+        let mut rest = input.as_str();
+
+        while !rest.is_empty() {
+            if self.inside_tool_call || self.inside_think {
+                let close_tag = if self.inside_tool_call { self.tool_close_tag.as_str() } else { "</think>" };
+                let (inner, after) = match rest.find(close_tag) {
+                    Some(pos) => (&rest[..pos], Some(&rest[pos + close_tag.len()..])),
+                    None => (&rest[..rest.len() - partial_tag_len(rest, close_tag)], None),
+                };
+                if self.inside_think {
+                    result.reasoning.push_str(inner);
+                }
+                result.filtered.push_str(inner);
+                if after.is_some() {
+                    result.filtered.push_str(close_tag);
+                }
+                match after {
+                    Some(after) => {
+                        if self.inside_think {
+                            self.trim_after_think = true;
+                        }
+                        self.inside_tool_call = false;
+                        self.inside_think = false;
+                        rest = after;
+                    }
+                    None => {
+                        self.pending = rest[inner.len()..].to_string();
+                        break;
+                    }
+                }
+            } else {
+                let tool_call = rest.find(self.tool_open_tag.as_str());
+                let think = rest.find("<think>");
+                let (text, after) = match (tool_call, think) {
+                    (Some(t), Some(k)) if k < t => (&rest[..k], Some((false, &rest[k..]))),
+                    (Some(t), _) => (&rest[..t], Some((true, &rest[t..]))),
+                    (None, Some(k)) => (&rest[..k], Some((false, &rest[k..]))),
+                    (None, None) => {
+                        let held = partial_tag_len(rest, &self.tool_open_tag).max(partial_tag_len(rest, "<think>"));
+                        (&rest[..rest.len() - held], None)
+                    }
+                };
+                self.push_content(&mut result, text);
+                match after {
+                    Some((is_tool_call, after)) => {
+                        let open_tag = if is_tool_call { self.tool_open_tag.as_str() } else { "<think>" };
+                        result.filtered.push_str(open_tag);
+                        self.inside_tool_call = is_tool_call;
+                        self.inside_think = !is_tool_call;
+                        rest = &after[open_tag.len()..];
+                    }
+                    None => {
+                        self.pending = rest[text.len()..].to_string();
+                        break;
+                    }
+                }
+            }
         }
 
         result
     }
 
+    /// Releases the text held back by `split_chunk`. Call once the stream ends
+    pub fn flush(&mut self) -> FilteredChunk {
+        let mut result = FilteredChunk::default();
+        let rest = std::mem::take(&mut self.pending);
+        if self.inside_think {
+            result.reasoning.push_str(&rest);
+            result.filtered.push_str(&rest);
+        } else if self.inside_tool_call {
+            result.filtered.push_str(&rest);
+        } else {
+            self.push_content(&mut result, &rest);
+        }
+        result
+    }
+
+    fn push_content(&mut self, result: &mut FilteredChunk, text: &str) {
+        if self.trim_after_think {
+            let trimmed = text.trim_start();
+            result.filtered.push_str(&text[..text.len() - trimmed.len()]);
+            if trimmed.is_empty() {
+                return;
+            }
+            self.trim_after_think = false;
+            result.content.push_str(trimmed);
+        } else {
+            result.content.push_str(text);
+        }
+    }
+
     pub fn is_inside_tool_call(&self) -> bool {
         self.inside_tool_call
     }
-}
\ No newline at end of file
+
+    pub fn is_inside_think(&self) -> bool {
+        self.inside_think
+    }
+}
+
+/// Parse an Ollama version such as "0.12.3" or "0.12.3-rc1" into its numeric parts
+pub fn parse_version(version: &str) -> Option<(u32, u32, u32)> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    let mut parts = version.split('.').map(|part| part.parse::<u32>().ok());
+    Some((parts.next()??, parts.next().flatten().unwrap_or(0), parts.next().flatten().unwrap_or(0)))
+}
+
+// Length of the longest end of `text` that could grow into `tag` with the next chunk
+fn partial_tag_len(text: &str, tag: &str) -> usize {
+    (1..tag.len())
+        .rev()
+        .find(|&len| tag.is_char_boundary(len) && text.ends_with(&tag[..len]))
+        .unwrap_or(0)
+}
+
+
+/// Renders a legacy prompt template that only uses `.System`, `.Prompt` and `.Response`,
+/// the way Ollama does: once per user turn, stopping at the open response of the last turn.
+/// Returns `None` for templates using anything else (e.g. ranging over `.Messages`).
+pub fn render_legacy_template(template: &str, messages: &[Message]) -> Option<String> {
+    let nodes = parse_template(template)?;
+
+    let system = messages
+        .iter()
+        .filter(|msg| msg.role == "system")
+        .map(|msg| msg.content.as_str())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Pair each user prompt with the assistant reply that follows it
+    let mut turns: Vec<(String, String)> = Vec::new();
+    for msg in messages {
+        match msg.role.as_str() {
+            "user" => turns.push((msg.content.clone(), String::new())),
+            "assistant" => match turns.last_mut() {
+                Some((_, response)) if response.is_empty() => response.push_str(&msg.content),
+                _ => turns.push((String::new(), msg.content.clone())),
+            },
+            _ => {}
+        }
+    }
+    if turns.is_empty() {
+        turns.push((String::new(), String::new()));
+    }
+
+    let mut rendered = String::new();
+    let last = turns.len() - 1;
+    for (i, (prompt, response)) in turns.iter().enumerate() {
+        let vars = TemplateVars {
+            // Ollama only fills the system prompt into the first turn
+            system: if i == 0 { &system } else { "" },
+            prompt,
+            response,
+        };
+        let mut turn = String::new();
+        let stopped = render_nodes(&nodes, &vars, i == last, &mut turn);
+        rendered.push_str(&turn);
+        if stopped {
+            break;
+        }
+    }
+    Some(rendered)
+}
+
+enum TemplateNode {
+    Text(String),
+    Var(String),
+    If(String, Vec<TemplateNode>, Vec<TemplateNode>),
+}
+
+struct TemplateVars<'a> {
+    system: &'a str,
+    prompt: &'a str,
+    response: &'a str,
+}
+
+impl TemplateVars<'_> {
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            ".System" => Some(self.system),
+            ".Prompt" => Some(self.prompt),
+            ".Response" => Some(self.response),
+            _ => None,
+        }
+    }
+}
+
+fn parse_template(template: &str) -> Option<Vec<TemplateNode>> {
+    // Split into text and trimmed actions, honouring `{{-` and `-}}` whitespace trimming
+    let mut tokens: Vec<(bool, String)> = Vec::new();
+    let mut rest = template;
+    let mut trim_next = false;
+    while let Some(start) = rest.find("{{") {
+        let end = start + rest[start..].find("}}")?;
+        let mut text = &rest[..start];
+        let mut action = &rest[start + 2..end];
+        if trim_next {
+            text = text.trim_start();
+        }
+        if let Some(stripped) = action.strip_prefix('-') {
+            text = text.trim_end();
+            action = stripped;
+        }
+        trim_next = false;
+        if let Some(stripped) = action.strip_suffix('-') {
+            trim_next = true;
+            action = stripped;
+        }
+        tokens.push((false, text.to_string()));
+        tokens.push((true, action.trim().to_string()));
+        rest = &rest[end + 2..];
+    }
+    let tail = if trim_next { rest.trim_start() } else { rest };
+    tokens.push((false, tail.to_string()));
+
+    let mut tokens = tokens.into_iter();
+    let (nodes, terminator) = parse_nodes(&mut tokens)?;
+    terminator.is_none().then_some(nodes)
+}
+
+fn is_legacy_var(name: &str) -> bool {
+    matches!(name, ".System" | ".Prompt" | ".Response")
+}
+
+/// Parses until `else`/`end` (returned as the terminator) or the end of the template
+fn parse_nodes(tokens: &mut impl Iterator<Item = (bool, String)>) -> Option<(Vec<TemplateNode>, Option<String>)> {
+    let mut nodes = Vec::new();
+    while let Some((is_action, token)) = tokens.next() {
+        if !is_action {
+            if !token.is_empty() {
+                nodes.push(TemplateNode::Text(token));
+            }
+            continue;
+        }
+        if token == "else" || token == "end" {
+            return Some((nodes, Some(token)));
+        }
+        if let Some(condition) = token.strip_prefix("if ") {
+            let condition = condition.trim();
+            if !is_legacy_var(condition) {
+                return None;
+            }
+            let (then_nodes, terminator) = parse_nodes(tokens)?;
+            let else_nodes = match terminator.as_deref() {
+                Some("end") => Vec::new(),
+                Some("else") => match parse_nodes(tokens)? {
+                    (else_nodes, Some(end)) if end == "end" => else_nodes,
+                    _ => return None,
+                },
+                _ => return None,
+            };
+            nodes.push(TemplateNode::If(condition.to_string(), then_nodes, else_nodes));
+        } else if token.starts_with("/*") {
+            // Template comment
+        } else if is_legacy_var(&token) {
+            nodes.push(TemplateNode::Var(token));
+        } else {
+            return None;
+        }
+    }
+    Some((nodes, None))
+}
+
+/// Returns true once rendering stopped at the open response of the final turn
+fn render_nodes(nodes: &[TemplateNode], vars: &TemplateVars, last_turn: bool, out: &mut String) -> bool {
+    for node in nodes {
+        match node {
+            TemplateNode::Text(text) => out.push_str(text),
+            TemplateNode::Var(name) => {
+                if last_turn && name == ".Response" {
+                    return true;
+                }
+                out.push_str(vars.get(name).unwrap_or_default());
+            }
+            TemplateNode::If(condition, then_nodes, else_nodes) => {
+                let branch = if vars.get(condition).is_some_and(|value| !value.is_empty()) {
+                    then_nodes
+                } else {
+                    else_nodes
+                };
+                if render_nodes(branch, vars, last_turn, out) {
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Runs the chunks through a filter and joins what each part of the output received
+    fn split(chunks: &[&str]) -> (String, String, String) {
+        let mut filter = StreamingXmlFilter::new();
+        let mut parts = chunks.iter().map(|chunk| filter.split_chunk(chunk)).collect::<Vec<_>>();
+        parts.push(filter.flush());
+        (
+            parts.iter().map(|part| part.content.as_str()).collect(),
+            parts.iter().map(|part| part.reasoning.as_str()).collect(),
+            parts.iter().map(|part| part.filtered.as_str()).collect(),
+        )
+    }
+
+    #[test]
+    fn think_block_in_one_chunk() {
+        let (content, reasoning, filtered) = split(&["<think>The user said hi.</think>\n\nHello!"]);
+
+        assert_eq!(content, "Hello!");
+        assert_eq!(reasoning, "The user said hi.");
+        assert_eq!(filtered, "<think>The user said hi.</think>\n\n");
+    }
+
+    #[test]
+    fn tags_split_across_chunks() {
+        let (content, reasoning, filtered) = split(&[
+            "<thi",
+            "nk>Look it up",
+            ".</thi",
+            "nk>Checking.<tool_",
+            "call>{\"name\": \"search\"}</tool_call>",
+        ]);
+
+        assert_eq!(content, "Checking.");
+        assert_eq!(reasoning, "Look it up.");
+        assert_eq!(filtered, "<think>Look it up.</think><tool_call>{\"name\": \"search\"}</tool_call>");
+    }
+
+    #[test]
+    fn unclosed_think_block_is_flushed_as_reasoning() {
+        let (content, reasoning, _) = split(&["Hmm <think>still thinking", " <"]);
+
+        assert_eq!(content, "Hmm ");
+        assert_eq!(reasoning, "still thinking <");
+    }
+
+    fn message(role: &str, content: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    const CHATML: &str = "{{ if .System }}<|im_start|>system\n{{ .System }}<|im_end|>\n{{ end }}{{ if .Prompt }}<|im_start|>user\n{{ .Prompt }}<|im_end|>\n{{ end }}<|im_start|>assistant\n{{ .Response }}<|im_end|>\n";
+
+    #[test]
+    fn chatml_template() {
+        let messages = [message("system", "Be brief."), message("user", "Hi")];
+
+        let rendered = render_legacy_template(CHATML, &messages).unwrap();
+
+        assert_eq!(rendered, "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\n");
+    }
+
+    #[test]
+    fn multi_turn_stops_at_the_last_response() {
+        let messages = [
+            message("system", "Be brief."),
+            message("user", "Hi"),
+            message("assistant", "Hello!"),
+            message("user", "How are you?"),
+        ];
+
+        let rendered = render_legacy_template(CHATML, &messages).unwrap();
+
+        // The system prompt only goes into the first turn
+        assert_eq!(
+            rendered,
+            concat!(
+                "<|im_start|>system\nBe brief.<|im_end|>\n<|im_start|>user\nHi<|im_end|>\n<|im_start|>assistant\nHello!<|im_end|>\n",
+                "<|im_start|>user\nHow are you?<|im_end|>\n<|im_start|>assistant\n",
+            )
+        );
+    }
+
+    #[test]
+    fn trim_markers() {
+        let template = "{{- if .System }}\n<<SYS>>{{ .System }}<</SYS>>\n{{ end -}}\n[INST] {{ .Prompt }} [/INST]";
+
+        let with_system = render_legacy_template(template, &[message("system", "S"), message("user", "Q")]).unwrap();
+        let without_system = render_legacy_template(template, &[message("user", "Q")]).unwrap();
+
+        assert_eq!(with_system, "\n<<SYS>>S<</SYS>>\n[INST] Q [/INST]");
+        assert_eq!(without_system, "[INST] Q [/INST]");
+    }
+
+    #[test]
+    fn if_else_blocks() {
+        let template = "{{ if .System }}SYS:{{ .System }}{{ else }}NOSYS{{ end }}|{{ /* the prompt */ }}{{ .Prompt }}";
+
+        assert_eq!(render_legacy_template(template, &[message("system", "S"), message("user", "Q")]).unwrap(), "SYS:S|Q");
+        assert_eq!(render_legacy_template(template, &[message("user", "Q")]).unwrap(), "NOSYS|Q");
+    }
+
+    #[test]
+    fn unsupported_templates() {
+        let messages = [message("user", "Hi")];
+
+        assert!(render_legacy_template("{{- range .Messages }}{{ .Content }}{{ end }}", &messages).is_none());
+        assert!(render_legacy_template("{{ if .Tools }}{{ .Tools }}{{ end }}{{ .Prompt }}", &messages).is_none());
+        assert!(render_legacy_template("{{ if .System }}{{ .System }}", &messages).is_none());
+        assert!(render_legacy_template("{{ .Prompt }}{{ end }}", &messages).is_none());
+        assert!(render_legacy_template("{{ .Prompt ", &messages).is_none());
+    }
+
+    #[test]
+    fn versions() {
+        assert_eq!(parse_version("0.12.3-rc1"), Some((0, 12, 3)));
+        assert_eq!(parse_version("0.12.3"), Some((0, 12, 3)));
+        assert_eq!(parse_version("v0.5"), Some((0, 5, 0)));
+        assert_eq!(parse_version("0.6.4+build.1"), Some((0, 6, 4)));
+        assert_eq!(parse_version("unknown"), None);
+    }
+}
@@ -1,51 +1,200 @@
 use futures_util::{Stream, StreamExt};
-use reqwest::Client;
-use std::error::Error;
+use reqwest::{Client, RequestBuilder};
+use reqwest::header::HeaderMap;
 use std::pin::Pin;
-use std::collections::HashMap;
+use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
 use bytes::Bytes;
 
-use crate::core::{Message, ToolCall, ChatStreamItem, Tool, TokenUsage};
+use crate::core::{Message, ToolCall, ChatStreamItem, Tool, ToolChoice, ChatOptions, ResponseFormat, TokenUsage, RawChunkHook, AIRequestError, parse_header, is_image_url, call_tools};
+use crate::core::trace::trace_event;
+#[cfg(not(feature = "tiktoken"))]
+use crate::core::estimate_messages_tokens;
 use super::types::*;
 
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+
+// Headers this client sets on its own requests
+const RESERVED_HEADERS: &[&str] = &["authorization", "api-key", "content-type"];
+
 pub struct OpenAIClient {
     client: Client,
     api_key: String,
     pub model: String,
-    tools: Vec<Tool>,
+    tools: Vec<Arc<Tool>>,
     base_url: String,
+    lenient_stream: bool,
+    done_markers: Vec<String>,
+    response_format: ResponseFormat,
+    strict_tools: bool,
+    parallel_tool_calls: Option<bool>,
+    raw_chunk_hook: Option<RawChunkHook>,
+    headers: HeaderMap, // Custom headers sent with every request
+    azure_api_version: Option<String>, // Set for Azure OpenAI, which addresses deployments by URL
 }
 
 impl OpenAIClient {
     pub fn new(api_key: String, model: String) -> Self {
-        Self {
-            client: Client::new(),
-            api_key,
-            model,
-            tools: Vec::new(),
-            base_url: "https://api.openai.com/v1".to_string(),
-        }
+        Self::with_base_url(api_key, model, DEFAULT_BASE_URL.to_string())
     }
 
     pub fn with_base_url(api_key: String, model: String, base_url: String) -> Self {
+        Self::with_client(Client::new(), api_key, model, base_url)
+    }
+
+    /// Use a pre-built `reqwest::Client`, e.g. to share a connection pool or set proxies and timeouts.
+    /// `base_url` is usually `DEFAULT_BASE_URL`
+    pub fn with_client(client: Client, api_key: String, model: String, base_url: String) -> Self {
         Self {
-            client: Client::new(),
+            client,
             api_key,
             model,
             tools: Vec::new(),
             base_url,
+            lenient_stream: false,
+            done_markers: vec!["[DONE]".to_string()],
+            response_format: ResponseFormat::Text,
+            strict_tools: true,
+            parallel_tool_calls: None,
+            raw_chunk_hook: None,
+            headers: HeaderMap::new(),
+            azure_api_version: None,
         }
     }
 
+    /// Azure OpenAI deployment at `endpoint`, e.g. `https://my-resource.openai.azure.com`. The
+    /// deployment name takes the place of the model and goes into the request URL, together with
+    /// `api_version` (e.g. `2024-10-21`); the key is sent in the `api-key` header
+    pub fn azure(endpoint: String, deployment: String, api_key: String, api_version: String) -> Self {
+        Self::azure_with_client(Client::new(), endpoint, deployment, api_key, api_version)
+    }
+
+    /// Like `azure`, sending requests through a pre-built `reqwest::Client`
+    pub fn azure_with_client(
+        client: Client,
+        endpoint: String,
+        deployment: String,
+        api_key: String,
+        api_version: String,
+    ) -> Self {
+        let endpoint = endpoint.trim_end_matches('/').to_string();
+        let mut openai = Self::with_client(client, api_key, deployment, endpoint);
+        openai.azure_api_version = Some(api_version);
+        openai
+    }
+
+    /// Whether this client talks to an Azure OpenAI deployment
+    pub fn is_azure(&self) -> bool {
+        self.azure_api_version.is_some()
+    }
+
     pub fn set_base_url(&mut self, base_url: String) {
         self.base_url = base_url;
     }
 
-    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), Box<dyn Error>> {
-        self.tools.push(tool);
+    /// Whether the base URL points at OpenRouter, whose model listing carries pricing and capabilities
+    pub fn is_openrouter(&self) -> bool {
+        self.base_url.contains("openrouter.ai")
+    }
+
+    /// Tolerate nonstandard SSE from OpenAI-compatible servers: events may be separated by CRLF
+    /// and `data:` may omit the space
+    pub fn set_lenient_stream(&mut self, lenient: bool) {
+        self.lenient_stream = lenient;
+    }
+
+    /// Payloads that mark the end of the stream, `[DONE]` by default
+    pub fn set_done_markers(&mut self, markers: Vec<String>) {
+        self.done_markers = markers;
+    }
+
+    /// Whether tool schemas get `additionalProperties: false`, on by default since OpenAI structured
+    /// outputs need it. Turn it off for backends that reject the field (vLLM, Groq, Mistral, some local runtimes)
+    pub fn set_strict_tools(&mut self, strict: bool) {
+        self.strict_tools = strict;
+    }
+
+    /// `Some(false)` makes the model call at most one tool per turn, for tools that depend on each
+    /// other's results. `None` leaves the field out and keeps the provider default (parallel calls)
+    pub fn set_parallel_tool_calls(&mut self, parallel: Option<bool>) {
+        self.parallel_tool_calls = parallel;
+    }
+
+    /// Request JSON output through `response_format`
+    pub fn set_response_format(&mut self, response_format: ResponseFormat) {
+        self.response_format = response_format;
+    }
+
+    /// Send a custom header with every request, e.g. for gateway authentication. Headers the
+    /// client sets itself (authorization, api-key, content-type) are refused rather than silently replaced
+    pub fn set_header(&mut self, name: &str, value: &str) -> Result<(), AIRequestError> {
+        let (name, value) = parse_header(name, value, RESERVED_HEADERS)?;
+        self.headers.insert(name, value);
+        Ok(())
+    }
+
+    /// Pass every raw line of chat response streams to `hook` before parsing, `None` to stop
+    pub fn set_raw_chunk_hook(&mut self, hook: Option<RawChunkHook>) {
+        self.raw_chunk_hook = hook;
+    }
+
+    // Azure puts the deployment and API version into the URL, other backends append `path` to the base URL
+    fn url(&self, path: &str) -> String {
+        match &self.azure_api_version {
+            Some(version) if path == "models" => format!("{}/openai/models?api-version={}", self.base_url, version),
+            Some(version) => format!(
+                "{}/openai/deployments/{}/{}?api-version={}",
+                self.base_url, self.model, path, version
+            ),
+            None => format!("{}/{}", self.base_url, path),
+        }
+    }
+
+    fn with_api_headers(&self, request: RequestBuilder) -> RequestBuilder {
+        let request = request.headers(self.headers.clone());
+        if self.is_azure() {
+            request.header("api-key", &self.api_key)
+        } else {
+            request.header("Authorization", format!("Bearer {}", self.api_key))
+        }
+    }
+
+    pub(crate) fn raw_chunk_hook(&self) -> Option<RawChunkHook> {
+        self.raw_chunk_hook.clone()
+    }
+
+    pub(crate) fn extend_tools(&mut self, tools: Vec<Arc<Tool>>) {
+        self.tools.extend(tools);
+    }
+
+    pub(crate) fn response_format(&self) -> &ResponseFormat {
+        &self.response_format
+    }
+
+    pub(crate) fn take_tools(&mut self) -> Vec<Arc<Tool>> {
+        std::mem::take(&mut self.tools)
+    }
+
+    pub async fn add_tool(&mut self, tool: Tool) -> Result<(), AIRequestError> {
+        self.tools.push(Arc::new(tool));
         Ok(())
     }
 
+    /// Remove a registered tool by name, `false` if there was none
+    pub fn remove_tool(&mut self, name: &str) -> bool {
+        let count = self.tools.len();
+        self.tools.retain(|tool| tool.name != name);
+        self.tools.len() != count
+    }
+
+    pub fn clear_tools(&mut self) {
+        self.tools.clear();
+    }
+
+    pub fn list_tools(&self) -> Vec<&str> {
+        self.tools.iter().map(|tool| tool.name.as_str()).collect()
+    }
+
     pub async fn is_fallback_mode(&self) -> bool {
         false // OpenAI has native tool support
     }
@@ -58,27 +207,51 @@ impl OpenAIClient {
         false
     }
 
-    pub async fn supports_tool_calls(&self) -> Result<bool, Box<dyn Error>> {
+    pub async fn supports_tool_calls(&self) -> Result<bool, AIRequestError> {
         Ok(true) // OpenAI models support native tool calling
     }
 
-    pub async fn get_available_models(&self) -> Result<Vec<OpenAIModel>, Box<dyn Error>> {
+    pub async fn get_available_models(&self) -> Result<Vec<OpenAIModel>, AIRequestError> {
         let response = self
-            .client
-            .get(&format!("{}/models", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
+            .with_api_headers(self.client.get(self.url("models")))
             .send()
             .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("OpenAI API error: {}", error_text).into());
+            return Err(AIRequestError::from_response(response).await);
         }
 
         let models_response: OpenAIModelsResponse = response.json().await?;
         Ok(models_response.data)
     }
 
+    /// Prompt tokens for these messages, counted with the model's tokenizer when the `tiktoken`
+    /// feature is enabled and estimated otherwise. Tool definitions and images aren't counted
+    pub async fn count_tokens(&self, messages: &[Message]) -> Result<u32, AIRequestError> {
+        #[cfg(feature = "tiktoken")]
+        {
+            // OpenRouter style names carry a vendor prefix, and unknown models get the newest encoding
+            let model = self.model.rsplit('/').next().unwrap_or(&self.model);
+            let bpe = match tiktoken_rs::tokenizer::get_tokenizer(model) {
+                Some(tiktoken_rs::tokenizer::Tokenizer::Cl100kBase) => tiktoken_rs::cl100k_base_singleton(),
+                _ => tiktoken_rs::o200k_base_singleton(),
+            };
+            let count = |text: &str| bpe.encode_with_special_tokens(text).len() as u32;
+
+            // Every message is wrapped in 3 tokens of markup, and the reply is primed with 3 more
+            let mut total = 3;
+            for message in messages {
+                total += 3 + count(&message.role) + count(&message.content);
+                for call in message.tool_calls.iter().flatten() {
+                    total += count(&call.function.name) + count(&call.function.arguments.to_string());
+                }
+            }
+            Ok(total)
+        }
+        #[cfg(not(feature = "tiktoken"))]
+        Ok(estimate_messages_tokens(messages))
+    }
+
     fn convert_to_openai_message(&self, message: &Message) -> OpenAIMessage {
         // Check if this is a tool result message
         if message.role == "tool" {
@@ -100,12 +273,15 @@ impl OpenAIClient {
                 content: Some(serde_json::Value::String(content)),
                 tool_calls: None,
                 tool_call_id,
+                name: None, // Not part of tool results, which are matched by tool_call_id
+                reasoning: None,
+                reasoning_content: None,
             };
         }
 
         // Convert tool calls if present
-        let tool_calls = if let Some(tc) = &message.tool_calls {
-            Some(tc.iter().map(|call| {
+        let tool_calls = message.tool_calls.as_ref().map(|tc| {
+            tc.iter().map(|call| {
                 OpenAIToolCall {
                     id: Some(call.id.clone().unwrap_or_else(|| format!("call_{}", "generated_id"))),
                     call_type: Some("function".to_string()),
@@ -114,10 +290,8 @@ impl OpenAIClient {
                         arguments: Some(serde_json::to_string(&call.function.arguments).unwrap_or_default()),
                     },
                 }
-            }).collect())
-        } else {
-            None
-        };
+            }).collect()
+        });
 
         // Handle vision messages with images for OpenAI's structured content format
         let content = if let Some(ref images) = message.images {
@@ -133,12 +307,17 @@ impl OpenAIClient {
                     }));
                 }
                 
-                // Add image content in OpenAI's base64 format
-                for image in images {
+                // Add image content, remote URLs as is and base64 data as a data URL
+                for (index, image) in images.iter().enumerate() {
+                    let url = if is_image_url(image) {
+                        image.clone()
+                    } else {
+                        format!("data:{};base64,{}", message.image_media_type(index), image)
+                    };
                     content_items.push(serde_json::json!({
                         "type": "image_url", 
                         "image_url": {
-                            "url": format!("data:image/jpeg;base64,{}", image)
+                            "url": url
                         }
                     }));
                 }
@@ -156,6 +335,9 @@ impl OpenAIClient {
             content,
             tool_calls,
             tool_call_id: None,
+            name: message.name.clone(),
+            reasoning: None,
+            reasoning_content: None,
         }
     }
 
@@ -163,9 +345,11 @@ impl OpenAIClient {
         self.tools
             .iter()
             .map(|tool| {
-                // Ensure the parameters have additionalProperties: false for OpenAI compatibility
+                // Strict mode closes the parameters with additionalProperties: false, as OpenAI expects
                 let mut parameters = tool.parameters.clone();
-                if let Some(obj) = parameters.as_object_mut() {
+                if self.strict_tools
+                    && let Some(obj) = parameters.as_object_mut()
+                {
                     obj.insert("additionalProperties".to_string(), serde_json::Value::Bool(false));
                 }
                 
@@ -181,61 +365,131 @@ impl OpenAIClient {
             .collect()
     }
 
+    fn convert_tool_choice_to_openai(&self, tool_choice: &ToolChoice) -> Option<serde_json::Value> {
+        // tool_choice is only valid alongside tools
+        if self.tools.is_empty() {
+            return None;
+        }
+
+        match tool_choice {
+            ToolChoice::Auto => None,
+            ToolChoice::None => Some(serde_json::json!("none")),
+            ToolChoice::Required => Some(serde_json::json!("required")),
+            ToolChoice::Specific(name) => Some(serde_json::json!({
+                "type": "function",
+                "function": { "name": name }
+            })),
+        }
+    }
+
+    fn convert_response_format_to_openai(response_format: &ResponseFormat) -> Option<serde_json::Value> {
+        match response_format {
+            // Text is the default, so leave the field out for compatible servers that lack it
+            ResponseFormat::Text => None,
+            ResponseFormat::Json => Some(serde_json::json!({ "type": "json_object" })),
+            ResponseFormat::JsonSchema(schema) => Some(serde_json::json!({
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "response",
+                    "schema": schema,
+                    "strict": true
+                }
+            })),
+        }
+    }
+
     pub async fn send_chat_request(
         &self,
         messages: &[Message],
-    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, String>> + Send>>, Box<dyn Error>> {
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        self.send_chat_request_with_options(messages, &ChatOptions::default()).await
+    }
+
+    /// The body `send_chat_request_with_options` would post to `/chat/completions`, without sending it
+    pub fn build_request_json(&self, messages: &[Message], options: &ChatOptions) -> Result<serde_json::Value, AIRequestError> {
+        Ok(serde_json::to_value(self.build_request(messages, options))?)
+    }
+
+    fn build_request(&self, messages: &[Message], options: &ChatOptions) -> OpenAIRequest {
         let openai_messages: Vec<OpenAIMessage> = messages
             .iter()
             .map(|msg| self.convert_to_openai_message(msg))
             .collect();
 
-        let request = OpenAIRequest {
+        let max_tokens = options.max_tokens.unwrap_or(4096);
+        OpenAIRequest {
             model: self.model.clone(),
             messages: openai_messages,
-            temperature: None,
+            temperature: options.temperature,
             // Use max_completion_tokens for o1 and gpt-5 models, max_tokens for others
-            max_tokens: if self.model.contains("o1") || self.model.contains("gpt-5") { None } else { Some(4096) },
-            max_completion_tokens: if self.model.contains("o1") || self.model.contains("gpt-5") { Some(4096) } else { None },
+            max_tokens: if self.model.contains("o1") || self.model.contains("gpt-5") { None } else { Some(max_tokens) },
+            max_completion_tokens: if self.model.contains("o1") || self.model.contains("gpt-5") { Some(max_tokens) } else { None },
+            top_p: options.top_p,
+            stop: options.stop.clone(),
+            seed: options.seed,
+            frequency_penalty: options.frequency_penalty,
+            presence_penalty: options.presence_penalty,
             tools: if self.tools.is_empty() {
                 None
             } else {
                 Some(self.convert_tools_to_openai())
             },
+            tool_choice: options
+                .tool_choice
+                .as_ref()
+                .and_then(|choice| self.convert_tool_choice_to_openai(choice)),
+            // Only allowed alongside tools
+            parallel_tool_calls: self.parallel_tool_calls.filter(|_| !self.tools.is_empty()),
+            response_format: Self::convert_response_format_to_openai(
+                options.response_format.as_ref().unwrap_or(&self.response_format),
+            ),
             stream: Some(true),
             stream_options: Some(OpenAIStreamOptions { include_usage: true }),
-        };
+        }
+    }
 
+    pub async fn send_chat_request_with_options(
+        &self,
+        messages: &[Message],
+        options: &ChatOptions,
+    ) -> Result<Pin<Box<dyn Stream<Item = Result<ChatStreamItem, AIRequestError>> + Send>>, AIRequestError> {
+        let request = self.build_request(messages, options);
         let response = self
-            .client
-            .post(&format!("{}/chat/completions", self.base_url))
-            .header("Authorization", format!("Bearer {}", self.api_key))
+            .with_api_headers(self.client.post(self.url("chat/completions")))
             .header("content-type", "application/json")
             .json(&request)
             .send()
             .await?;
 
         if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("OpenAI API error: {}", error_text).into());
+            return Err(AIRequestError::from_model_response(response, &self.model).await);
         }
 
         let stream = response.bytes_stream();
         
         // Create a stateful stream processor
-        Ok(Box::pin(OpenAIStreamProcessor::new(Box::pin(stream))))
+        let settings = StreamSettings {
+            lenient: self.lenient_stream,
+            done_markers: self.done_markers.clone(),
+            raw_chunk_hook: self.raw_chunk_hook.clone(),
+        };
+        Ok(Box::pin(OpenAIStreamProcessor::new(Box::pin(stream), settings)))
     }
 
     pub async fn send_chat_request_no_stream(
         &self,
         messages: &[Message],
-    ) -> Result<(String, Option<Vec<ToolCall>>), Box<dyn Error>> {
+    ) -> Result<(String, Option<Vec<ToolCall>>), AIRequestError> {
         let mut full_response = String::new();
         let mut tool_calls: Option<Vec<ToolCall>> = None;
         let mut stream = self.send_chat_request(messages).await?;
 
         while let Some(item) = stream.next().await {
-            let item = item.map_err(|e| format!("Stream error: {}", e))?;
+            let item = match item {
+                Ok(item) => item,
+                Err(error) if !error.is_fatal() => continue,
+                Err(error) => return Err(error),
+            };
             if !item.content.is_empty() {
                 full_response.push_str(&item.content);
             }
@@ -250,14 +504,10 @@ impl OpenAIClient {
     }
 
     pub async fn handle_tool_calls(&self, tool_calls: Vec<ToolCall>) -> Vec<Message> {
+        let results = call_tools(&self.tools, &tool_calls).await;
         let mut tool_responses = Vec::new();
-        for tool_call in tool_calls {
-            if let Some(tool) = self
-                .tools
-                .iter()
-                .find(|t| t.name == tool_call.function.name)
-            {
-                let result = (tool.function)(tool_call.function.arguments.clone());
+        for (tool_call, result) in tool_calls.into_iter().zip(results) {
+            if let Some(result) = result {
                 
                 // Use the tool call ID if available, otherwise use "unknown"
                 let tool_id = tool_call.id.unwrap_or_else(|| "unknown".to_string());
@@ -267,8 +517,7 @@ impl OpenAIClient {
                 tool_responses.push(Message {
                     role: "tool".to_string(),
                     content: format!("TOOL_RESULT:{}:{}", tool_id, result),
-                    images: None,
-                    tool_calls: None,
+                    ..Default::default()
                 });
             }
         }
@@ -281,9 +530,59 @@ impl OpenAIClient {
     }
 }
 
+fn convert_usage(chunk: &OpenAIStreamChunk) -> Option<TokenUsage> {
+    let usage = chunk.usage.as_ref()?;
+    Some(TokenUsage {
+        prompt_tokens: Some(usage.prompt_tokens),
+        completion_tokens: Some(usage.completion_tokens),
+        total_tokens: Some(usage.total_tokens),
+        // Only OpenRouter reports the cost itself
+        cost_usd: usage.cost,
+        generation_id: Some(chunk.id.clone()),
+        cache_creation_tokens: None,
+        cache_read_tokens: None,
+    })
+}
+
+// Parse an error object sent in place of a chunk, so it isn't reported as a malformed chunk
+fn parse_stream_error(payload: &str) -> Option<AIRequestError> {
+    if !payload.contains("\"error\"") {
+        return None;
+    }
+    let error = serde_json::from_str::<OpenAIStreamError>(payload).ok()?.error;
+    let kind = error
+        .code
+        .as_ref()
+        .and_then(|code| code.as_str())
+        .or(error.error_type.as_deref())
+        .unwrap_or_default()
+        .to_string();
+    let message = if kind.is_empty() { error.message } else { format!("{}: {}", kind, error.message) };
+
+    let status = error.code.as_ref().and_then(|code| code.as_u64()).map(|code| code as u16);
+    match (kind.as_str(), status) {
+        ("invalid_api_key" | "authentication_error" | "permission_error", _) | (_, Some(401 | 403)) => {
+            Some(AIRequestError::Auth { message })
+        }
+        ("rate_limit_exceeded" | "rate_limit_error", _) | (_, Some(429)) => {
+            Some(AIRequestError::RateLimited { retry_after: None })
+        }
+        ("invalid_request_error", None) => Some(AIRequestError::Api { status: 400, message }),
+        (_, status) => Some(AIRequestError::Api { status: status.unwrap_or(500), message }),
+    }
+}
+
+// How strictly the stream processor treats the SSE it receives
+struct StreamSettings {
+    lenient: bool,
+    done_markers: Vec<String>,
+    raw_chunk_hook: Option<RawChunkHook>,
+}
+
 // Custom stream processor for OpenAI streaming responses
 struct OpenAIStreamProcessor {
     stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>,
+    settings: StreamSettings,
     accumulated_content: String,
     accumulated_tool_calls: HashMap<usize, ToolCall>,
     // Track tool arguments being accumulated: tool_index -> accumulated_json_string
@@ -292,30 +591,160 @@ struct OpenAIStreamProcessor {
     buffer: String,
     done: bool,
     usage: Option<TokenUsage>,
+    finish_reason: Option<String>,
+    // Items produced by one network chunk beyond the one returned for it, e.g. decode errors
+    pending_results: VecDeque<Result<ChatStreamItem, AIRequestError>>,
 }
 
 impl OpenAIStreamProcessor {
-    fn new(stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>) -> Self {
+    fn new(stream: Pin<Box<dyn Stream<Item = Result<Bytes, reqwest::Error>> + Send>>, settings: StreamSettings) -> Self {
         Self {
             stream,
+            settings,
             accumulated_content: String::new(),
             accumulated_tool_calls: HashMap::new(),
             accumulating_tool_args: HashMap::new(),
             buffer: String::new(),
             done: false,
             usage: None,
+            finish_reason: None,
+            pending_results: VecDeque::new(),
+        }
+    }
+
+    // Extract the payload of an SSE data line
+    fn data_payload<'a>(&self, line: &'a str) -> Option<&'a str> {
+        if self.settings.lenient {
+            line.strip_prefix("data:").map(|payload| payload.trim())
+        } else {
+            line.strip_prefix("data: ")
+        }
+    }
+
+    fn is_done_marker(&self, payload: &str) -> bool {
+        self.settings.done_markers.iter().any(|marker| marker == payload.trim())
+    }
+
+    // Apply a parsed chunk: usage, finish reason and the content, reasoning and tool call deltas.
+    // Returns whether it carried tool call deltas
+    fn apply_chunk(&mut self, chunk: OpenAIStreamChunk, content: &mut String, reasoning: &mut String) -> bool {
+        if let Some(usage) = convert_usage(&chunk) {
+            self.usage = Some(usage);
+        }
+
+        // The last chunk usually carries finish_reason, e.g. "length" on truncation
+        if let Some(choice) = chunk.choices.first()
+            && choice.finish_reason.is_some()
+        {
+            self.finish_reason = choice.finish_reason.clone();
+        }
+
+        let Some(delta) = chunk.choices.first().and_then(|choice| choice.delta.as_ref()) else {
+            return false;
+        };
+        if let Some(delta_content) = &delta.content
+            && let Some(text) = delta_content.as_str()
+        {
+            content.push_str(text);
+            self.accumulated_content.push_str(text);
+        }
+        if let Some(delta_reasoning) = delta.reasoning.as_ref().or(delta.reasoning_content.as_ref()) {
+            reasoning.push_str(delta_reasoning);
+        }
+
+        let Some(tool_calls) = &delta.tool_calls else {
+            return false;
+        };
+        for (i, tool_call) in tool_calls.iter().enumerate() {
+            let entry = self.accumulated_tool_calls.entry(i).or_insert_with(|| ToolCall {
+                id: None,
+                function: crate::core::Function {
+                    name: String::new(),
+                    arguments: serde_json::Value::Null,
+                },
+            });
+            if let Some(name) = &tool_call.function.name
+                && !name.is_empty()
+            {
+                entry.function.name = name.clone();
+            }
+            if let Some(id) = &tool_call.id
+                && !id.is_empty()
+            {
+                entry.id = Some(id.clone());
+            }
+
+            // Arguments arrive as JSON string fragments, parsed once the stream ends
+            if let Some(args_str) = &tool_call.function.arguments
+                && !args_str.is_empty()
+            {
+                self.accumulating_tool_args.entry(i).or_default().push_str(args_str);
+            }
+        }
+        true
+    }
+
+    // Parse whatever is left in the buffer once the response ends, e.g. a final event without its
+    // closing blank line. Each line is handled exactly once. Returns the trailing content and reasoning
+    fn flush_buffer(&mut self) -> Result<(String, String), AIRequestError> {
+        let buffer = std::mem::take(&mut self.buffer);
+        let mut content = String::new();
+        let mut reasoning = String::new();
+        for line in buffer.lines() {
+            if let Some(hook) = &self.settings.raw_chunk_hook {
+                hook(line);
+            }
+            let Some(json_str) = self.data_payload(line) else { continue };
+            if json_str.is_empty() || self.is_done_marker(json_str) {
+                continue;
+            }
+            if let Some(error) = parse_stream_error(json_str) {
+                return Err(error);
+            }
+            match serde_json::from_str::<OpenAIStreamChunk>(json_str) {
+                Ok(chunk) => {
+                    self.apply_chunk(chunk, &mut content, &mut reasoning);
+                }
+                Err(e) => {
+                    // A cut off final line is reported, the rest of the response is still returned
+                    trace_event!(warn, error = %e, line = json_str, "unparseable OpenAI stream line");
+                    self.pending_results.push_back(Err(AIRequestError::Decode(format!("{} in line {:?}", e, json_str))));
+                }
+            }
         }
+        Ok((content, reasoning))
     }
 
+    // Tool calls with their accumulated argument strings parsed into JSON
+    fn final_tool_calls(&self) -> Option<Vec<ToolCall>> {
+        if self.accumulated_tool_calls.is_empty() {
+            return None;
+        }
+        let mut tool_calls = Vec::new();
+        for (i, tool_call) in &self.accumulated_tool_calls {
+            let mut tool_call = tool_call.clone();
+            if let Some(args_str) = self.accumulating_tool_args.get(i)
+                && !args_str.is_empty()
+                && let Ok(args) = serde_json::from_str::<serde_json::Value>(args_str)
+            {
+                tool_call.function.arguments = args;
+            }
+            tool_calls.push(tool_call);
+        }
+        Some(tool_calls)
+    }
 }
 
 impl Stream for OpenAIStreamProcessor {
-    type Item = Result<ChatStreamItem, String>;
+    type Item = Result<ChatStreamItem, AIRequestError>;
 
     fn poll_next(
         mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
+        if let Some(result) = self.pending_results.pop_front() {
+            return std::task::Poll::Ready(Some(result));
+        }
         if self.done {
             return std::task::Poll::Ready(None);
         }
@@ -328,10 +757,17 @@ impl Stream for OpenAIStreamProcessor {
                             let chunk_str = String::from_utf8_lossy(&chunk);
                             
                             // Add new chunk to buffer
-                            self.buffer.push_str(&chunk_str);
+                            if self.settings.lenient {
+                                // Some servers separate events with CRLF
+                                let normalized = chunk_str.replace("\r\n", "\n");
+                                self.buffer.push_str(&normalized);
+                            } else {
+                                self.buffer.push_str(&chunk_str);
+                            }
                             
                             // Collect all content from complete SSE events in buffer
                             let mut accumulated_content = String::new();
+                            let mut accumulated_reasoning = String::new();
                             let mut has_any_tool_calls = false;
                             
                             // Process complete SSE events from buffer
@@ -341,105 +777,60 @@ impl Stream for OpenAIStreamProcessor {
                                 
                                 // Parse each line in the event
                                 for line in event.lines() {
-                                    if line.starts_with("data: ") {
-                                        let json_str = &line[6..]; // Remove "data: " prefix
+                                    if let Some(hook) = &self.settings.raw_chunk_hook {
+                                        hook(line);
+                                    }
+                                    if let Some(json_str) = self.data_payload(line) {
                                     
-                                    if json_str == "[DONE]" {
+                                    if self.is_done_marker(json_str) {
                                         self.done = true;
-                                        let final_tool_calls = if !self.accumulated_tool_calls.is_empty() {
-                                            let mut tool_calls = Vec::new();
-                                            for (i, mut tool_call) in self.accumulated_tool_calls.clone() {
-                                                // Parse the accumulated argument string
-                                                if let Some(args_str) = self.accumulating_tool_args.get(&i) {
-                                                    if !args_str.is_empty() {
-                                                        if let Ok(args) = serde_json::from_str::<serde_json::Value>(args_str) {
-                                                            tool_call.function.arguments = args;
-                                                        }
-                                                    }
-                                                }
-                                                tool_calls.push(tool_call);
-                                            }
-                                            Some(tool_calls)
-                                        } else {
-                                            None
-                                        };
-                                        
-                                        return std::task::Poll::Ready(Some(Ok(ChatStreamItem {
-                                            content: String::new(),
-                                            tool_calls: final_tool_calls,
+                                        // Events before the marker in the same network chunk still count,
+                                        // after any errors they produced
+                                        let done_item = Ok(ChatStreamItem {
+                                            content: accumulated_content,
+                                            tool_calls: self.final_tool_calls(),
                                             done: true,
                                             usage: self.usage.clone(),
-                                        })));
+                                            finish_reason: self.finish_reason.clone(),
+                                            reasoning: Some(accumulated_reasoning).filter(|r| !r.is_empty()),
+                                            filtered: None,
+                                            citations: None,
+                                            timings: None,
+                                        });
+                                        self.pending_results.push_back(done_item);
+                                        return std::task::Poll::Ready(self.pending_results.pop_front());
                                     }
                                     
+                                    if let Some(error) = parse_stream_error(json_str) {
+                                        self.done = true;
+                                        // Text that arrived before the error in this network chunk is still delivered
+                                        if !accumulated_content.is_empty() || !accumulated_reasoning.is_empty() {
+                                            self.pending_results.push_back(Ok(ChatStreamItem {
+                                                content: accumulated_content,
+                                                tool_calls: None,
+                                                done: false,
+                                                usage: None,
+                                                finish_reason: None,
+                                                reasoning: Some(accumulated_reasoning).filter(|r| !r.is_empty()),
+                                                filtered: None,
+                                                citations: None,
+                                                timings: None,
+                                            }));
+                                        }
+                                        self.pending_results.push_back(Err(error));
+                                        return std::task::Poll::Ready(self.pending_results.pop_front());
+                                    }
+
                                     match serde_json::from_str::<OpenAIStreamChunk>(json_str) {
                                         Ok(chunk) => {
-                                            // Extract usage information if available
-                                            if let Some(usage) = &chunk.usage {
-                                                self.usage = Some(TokenUsage {
-                                                    prompt_tokens: Some(usage.prompt_tokens),
-                                                    completion_tokens: Some(usage.completion_tokens),
-                                                    total_tokens: Some(usage.total_tokens),
-                                                });
-                                            }
-                                            
-                                            if let Some(choice) = chunk.choices.first() {
-                                                if let Some(delta) = &choice.delta {
-                                                    // Handle content delta
-                                                    if let Some(delta_content) = &delta.content {
-                                                        if let Some(text) = delta_content.as_str() {
-                                                            accumulated_content.push_str(text);
-                                                            self.accumulated_content.push_str(text);
-                                                        }
-                                                    }
-                                                    
-                                                    // Handle tool call deltas
-                                                    if let Some(tool_calls) = &delta.tool_calls {
-                                                        has_any_tool_calls = true;
-                                                        for (i, tool_call) in tool_calls.iter().enumerate() {
-                                                            // Ensure tool call entry exists
-                                                            if !self.accumulated_tool_calls.contains_key(&i) {
-                                                                self.accumulated_tool_calls.insert(i, ToolCall {
-                                                                    id: tool_call.id.clone(),
-                                                                    function: crate::core::Function {
-                                                                        name: tool_call.function.name.clone().unwrap_or_default(),
-                                                                        arguments: serde_json::Value::Null,
-                                                                    },
-                                                                });
-                                                            }
-                                                            
-                                                            // Accumulate function arguments as string chunks
-                                                            if let Some(ref args_str) = tool_call.function.arguments {
-                                                                if !args_str.is_empty() {
-                                                                    let accumulated_args = self.accumulating_tool_args.entry(i).or_insert_with(String::new);
-                                                                    accumulated_args.push_str(args_str);
-                                                                }
-                                                            }
-                                                            
-                                                            // Update name if provided
-                                                            if let Some(ref name) = tool_call.function.name {
-                                                                if !name.is_empty() {
-                                                                    if let Some(entry) = self.accumulated_tool_calls.get_mut(&i) {
-                                                                        entry.function.name = name.clone();
-                                                                    }
-                                                                }
-                                                            }
-                                                            
-                                                            // Update ID if provided
-                                                            if let Some(ref id) = tool_call.id {
-                                                                if !id.is_empty() {
-                                                                    if let Some(entry) = self.accumulated_tool_calls.get_mut(&i) {
-                                                                        entry.id = Some(id.clone());
-                                                                    }
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
+                                            if self.apply_chunk(chunk, &mut accumulated_content, &mut accumulated_reasoning) {
+                                                has_any_tool_calls = true;
                                             }
                                         }
                                         Err(e) => {
-                                            return std::task::Poll::Ready(Some(Err(format!("JSON parse error: {}", e))));
+                                            // Not fatal, later events are still processed
+                                            trace_event!(warn, error = %e, line = json_str, "unparseable OpenAI stream line");
+                                            self.pending_results.push_back(Err(AIRequestError::Decode(format!("{} in line {:?}", e, json_str))));
                                         }
                                     }
                                     } // End of line processing
@@ -447,103 +838,52 @@ impl Stream for OpenAIStreamProcessor {
                             } // End of while let Some(event_end) loop
                             
                             // Return accumulated content from all processed events
-                            if !accumulated_content.is_empty() || has_any_tool_calls {
+                            if !accumulated_content.is_empty() || !accumulated_reasoning.is_empty() || has_any_tool_calls {
                                 return std::task::Poll::Ready(Some(Ok(ChatStreamItem {
                                     content: accumulated_content,
                                     tool_calls: None, // Don't return partial tool calls
                                     done: false,
                                     usage: None,
+                                    finish_reason: None,
+                                    reasoning: Some(accumulated_reasoning).filter(|r| !r.is_empty()),
+                                    filtered: None,
+                                    citations: None,
+                                    timings: None,
                                 })));
                             }
+                            if let Some(result) = self.pending_results.pop_front() {
+                                return std::task::Poll::Ready(Some(result));
+                            }
                         }
                         Err(e) => {
-                            return std::task::Poll::Ready(Some(Err(format!("Stream error: {}", e))));
+                            return std::task::Poll::Ready(Some(Err(AIRequestError::Network(e))));
                         }
                     }
                 }
-                std::task::Poll::Ready(None) => {                    
-                    // Process any remaining data in the buffer before ending
-                    if !self.buffer.is_empty() {
-                        let buffer_clone = self.buffer.clone();
-                        for line in buffer_clone.lines() {
-                            if line.starts_with("data: ") {
-                                let json_str = &line[6..];
-                                
-                                if json_str == "[DONE]" {
-                                    // Stream done signal found in buffer
-                                } else if !json_str.is_empty() {
-                                    // Process this final chunk
-                                    match serde_json::from_str::<OpenAIStreamChunk>(json_str) {
-                                        Ok(chunk) => {
-                                            if let Some(choice) = chunk.choices.first() {
-                                                if let Some(delta) = &choice.delta {
-                                                    if let Some(tool_calls) = &delta.tool_calls {
-                                                        for (i, tool_call) in tool_calls.iter().enumerate() {
-                                                            if let Some(ref args_str) = tool_call.function.arguments {
-                                                                if !args_str.is_empty() {
-                                                                    let accumulated_args = self.accumulating_tool_args.entry(i).or_insert_with(String::new);
-                                                                    accumulated_args.push_str(args_str);
-                                                                }
-                                                            }
-                                                        }
-                                                    }
-                                                }
-                                            }
-                                        }
-                                        Err(_) => {
-                                            // Failed to parse final buffer JSON, ignore
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    // Process any remaining data in buffer before ending
-                    let buffer_content = self.buffer.clone();
-                    if !buffer_content.is_empty() {
-                        for line in buffer_content.lines() {
-                            if line.starts_with("data: ") {
-                                let json_str = &line[6..];
-                                if json_str != "[DONE]" && !json_str.is_empty() {
-                                    if let Ok(chunk) = serde_json::from_str::<OpenAIStreamChunk>(json_str) {
-                                        if let Some(usage) = &chunk.usage {
-                                            self.usage = Some(TokenUsage {
-                                                prompt_tokens: Some(usage.prompt_tokens),
-                                                completion_tokens: Some(usage.completion_tokens),
-                                                total_tokens: Some(usage.total_tokens),
-                                            });
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    
+                std::task::Poll::Ready(None) => {
+                    // The last event may lack its closing blank line
                     self.done = true;
-                    let final_tool_calls = if !self.accumulated_tool_calls.is_empty() {
-                        let mut tool_calls = Vec::new();
-                        for (i, mut tool_call) in self.accumulated_tool_calls.clone() {
-                            // Parse the accumulated argument string when stream ends
-                            if let Some(args_str) = self.accumulating_tool_args.get(&i) {
-                                if !args_str.is_empty() {
-                                    if let Ok(args) = serde_json::from_str::<serde_json::Value>(args_str) {
-                                        tool_call.function.arguments = args;
-                                    }
-                                }
-                            }
-                            tool_calls.push(tool_call);
+                    let (content, reasoning) = match self.flush_buffer() {
+                        Ok(trailing) => trailing,
+                        Err(error) => {
+                            self.pending_results.push_back(Err(error));
+                            return std::task::Poll::Ready(self.pending_results.pop_front());
                         }
-                        Some(tool_calls)
-                    } else {
-                        None
                     };
-                    
-                    return std::task::Poll::Ready(Some(Ok(ChatStreamItem {
-                        content: String::new(),
-                        tool_calls: final_tool_calls,
+
+                    let done_item = Ok(ChatStreamItem {
+                        content,
+                        tool_calls: self.final_tool_calls(),
                         done: true,
                         usage: self.usage.clone(),
-                    })));
+                        finish_reason: self.finish_reason.clone(),
+                        reasoning: Some(reasoning).filter(|r| !r.is_empty()),
+                        filtered: None,
+                        citations: None,
+                        timings: None,
+                    });
+                    self.pending_results.push_back(done_item);
+                    return std::task::Poll::Ready(self.pending_results.pop_front());
                 }
                 std::task::Poll::Pending => {
                     return std::task::Poll::Pending;
@@ -551,4 +891,168 @@ impl Stream for OpenAIStreamProcessor {
             }
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn processor(chunks: Vec<Bytes>) -> OpenAIStreamProcessor {
+        let chunks = chunks.into_iter().map(Ok::<_, reqwest::Error>);
+        let settings = StreamSettings {
+            lenient: false,
+            done_markers: vec!["[DONE]".to_string()],
+            raw_chunk_hook: None,
+        };
+        OpenAIStreamProcessor::new(Box::pin(futures_util::stream::iter(chunks)), settings)
+    }
+
+    // Feed raw SSE text through the processor, one network chunk per string
+    async fn process(chunks: &[&str]) -> Vec<Result<ChatStreamItem, AIRequestError>> {
+        processor(chunks.iter().map(|chunk| Bytes::from(chunk.to_string())).collect()).collect().await
+    }
+
+    // A `data:` line carrying one chunk with this delta, without the closing blank line
+    fn data(delta: serde_json::Value, finish_reason: Option<&str>) -> String {
+        let chunk = json!({
+            "id": "chatcmpl-1",
+            "object": "chat.completion.chunk",
+            "created": 0,
+            "model": "gpt-4o",
+            "choices": [{"index": 0, "delta": delta, "finish_reason": finish_reason}],
+        });
+        format!("data: {}\n", chunk)
+    }
+
+    #[tokio::test]
+    async fn final_event_without_blank_line_is_parsed_once() {
+        let tool_start = json!({"tool_calls": [{"id": "call_1", "type": "function", "function": {"name": "get_weather", "arguments": "{\"city\": "}}]});
+        let tool_end = json!({"tool_calls": [{"function": {"arguments": "\"Oslo\"}"}}]});
+        let first = data(json!({"role": "assistant", "content": "Checking"}), None) + "\n" + &data(tool_start, None) + "\n";
+        // The response ends without the blank line after the last event and without [DONE]
+        let last = data(tool_end, None) + "\n" + &data(json!({}), Some("tool_calls"));
+
+        let items = process(&[&first, &last]).await;
+        let items: Vec<ChatStreamItem> = items.into_iter().map(Result::unwrap).collect();
+
+        let content: String = items.iter().map(|item| item.content.as_str()).collect();
+        assert_eq!(content, "Checking");
+        let last = items.last().unwrap();
+        assert!(last.done);
+        assert_eq!(last.finish_reason.as_deref(), Some("tool_calls"));
+        let tool_calls = last.tool_calls.as_ref().unwrap();
+        assert_eq!(tool_calls.len(), 1);
+        assert_eq!(tool_calls[0].id.as_deref(), Some("call_1"));
+        assert_eq!(tool_calls[0].function.arguments, json!({"city": "Oslo"}));
+    }
+
+    #[tokio::test]
+    async fn error_object_mid_stream_ends_with_that_error() {
+        let body = data(json!({"role": "assistant", "content": "Hel"}), None)
+            + "\n"
+            + "data: {\"error\":{\"message\":\"Rate limit reached\",\"type\":\"requests\",\"code\":\"rate_limit_exceeded\"}}\n\n"
+            + &data(json!({"content": "lo"}), None)
+            + "\n";
+
+        let items = process(&[&body]).await;
+
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0].as_ref().unwrap().content, "Hel");
+        assert!(matches!(items[1], Err(AIRequestError::RateLimited { .. })));
+    }
+
+    #[tokio::test]
+    async fn error_object_is_not_reported_as_malformed() {
+        let body = "data: {\"error\":{\"message\":\"The server had an error\",\"type\":\"server_error\",\"code\":null}}\n\n";
+
+        let items = process(&[body]).await;
+
+        assert_eq!(items.len(), 1);
+        match &items[0] {
+            Err(AIRequestError::Api { status, message }) => {
+                assert_eq!(*status, 500);
+                assert_eq!(message, "server_error: The server had an error");
+            }
+            other => panic!("expected an API error, got {:?}", other),
+        }
+    }
+
+    fn user(content: &str) -> Message {
+        Message {
+            role: "user".to_string(),
+            content: content.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn penalties_only_sent_when_set() {
+        let client = OpenAIClient::new("key".to_string(), "gpt-4o".to_string());
+        let messages = [user("Hi")];
+
+        let body = client.build_request_json(&messages, &ChatOptions::default()).unwrap();
+        assert!(body.get("frequency_penalty").is_none());
+        assert!(body.get("presence_penalty").is_none());
+
+        let options = ChatOptions {
+            frequency_penalty: Some(0.5),
+            presence_penalty: Some(-0.25),
+            ..Default::default()
+        };
+        let body = client.build_request_json(&messages, &options).unwrap();
+        assert_eq!(body["frequency_penalty"], json!(0.5));
+        assert_eq!(body["presence_penalty"], json!(-0.25));
+    }
+
+    #[tokio::test]
+    async fn strict_tools_close_the_parameters() {
+        let mut client = OpenAIClient::new("key".to_string(), "gpt-4o".to_string());
+        let parameters = json!({"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]});
+        client.add_tool(Tool::new("get_weather", "Weather for a city", parameters, |_| Ok("sunny".to_string()))).await.unwrap();
+        let messages = [user("Weather in Oslo?")];
+
+        let body = client.build_request_json(&messages, &ChatOptions::default()).unwrap();
+        assert_eq!(body["tools"][0]["function"]["parameters"]["additionalProperties"], json!(false));
+
+        client.set_strict_tools(false);
+        let body = client.build_request_json(&messages, &ChatOptions::default()).unwrap();
+        let parameters = &body["tools"][0]["function"]["parameters"];
+        assert!(parameters.get("additionalProperties").is_none());
+        assert_eq!(parameters["required"], json!(["city"]));
+    }
+
+    #[tokio::test]
+    async fn reasoning_is_collected_apart_from_content() {
+        let body = [
+            data(json!({"role": "assistant", "reasoning_content": "The user wants "}), None),
+            data(json!({"reasoning_content": "a greeting."}), None),
+            data(json!({"content": "Hello"}), None),
+            data(json!({"content": " there!"}), None),
+            data(json!({}), Some("stop")),
+            "data: [DONE]\n".to_string(),
+        ]
+        .join("\n")
+            + "\n";
+        let stream = processor(body.as_bytes().chunks(7).map(Bytes::copy_from_slice).collect());
+
+        let result = crate::core::ChatResult::collect(stream).await.unwrap();
+
+        assert_eq!(result.content, "Hello there!");
+        assert_eq!(result.reasoning.as_deref(), Some("The user wants a greeting."));
+        assert_eq!(result.finish_reason.as_deref(), Some("stop"));
+    }
+
+    #[test]
+    fn seed_sent_when_set() {
+        let client = OpenAIClient::new("key".to_string(), "gpt-4o".to_string());
+        let messages = [user("Hi")];
+
+        let body = client.build_request_json(&messages, &ChatOptions::default()).unwrap();
+        assert!(body.get("seed").is_none());
+
+        let options = ChatOptions { seed: Some(4_000_000_000), ..Default::default() };
+        let body = client.build_request_json(&messages, &options).unwrap();
+        assert_eq!(body["seed"], json!(4_000_000_000i64));
+    }
+}
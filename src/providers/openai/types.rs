@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use crate::core::ModelPrice;
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct OpenAIMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -10,6 +12,13 @@ pub struct OpenAIMessage {
     pub tool_calls: Option<Vec<OpenAIToolCall>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    // Reasoning deltas, named reasoning_content by some compatible servers
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_content: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -51,8 +60,24 @@ pub struct OpenAIRequest {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_completion_tokens: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub frequency_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub presence_penalty: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tools: Option<Vec<OpenAITool>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_choice: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parallel_tool_calls: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_format: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub stream: Option<bool>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub stream_options: Option<OpenAIStreamOptions>,
@@ -104,6 +129,8 @@ pub struct OpenAIUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
     pub total_tokens: u32,
+    #[serde(default)]
+    pub cost: Option<f64>, // OpenRouter extension, in US dollars
 }
 
 #[derive(Deserialize, Debug)]
@@ -117,6 +144,21 @@ pub struct OpenAIStreamChunk {
     pub usage: Option<OpenAIUsage>,
 }
 
+// Error object some servers send inside the SSE body after a 200
+#[derive(Deserialize, Debug)]
+pub struct OpenAIStreamError {
+    pub error: OpenAIErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OpenAIErrorDetail {
+    pub message: String,
+    #[serde(rename = "type", default)]
+    pub error_type: Option<String>,
+    #[serde(default)]
+    pub code: Option<serde_json::Value>, // A string like "rate_limit_exceeded", or an HTTP status on some proxies
+}
+
 #[derive(Deserialize, Debug)]
 pub struct OpenAIModelsResponse {
     pub object: String,
@@ -126,7 +168,41 @@ pub struct OpenAIModelsResponse {
 #[derive(Deserialize, Debug)]
 pub struct OpenAIModel {
     pub id: String,
+    #[serde(default)]
     pub object: String,
+    // Azure's model listing calls it `created_at`
+    #[serde(default, alias = "created_at")]
     pub created: u64,
+    #[serde(default)]
     pub owned_by: String,
+    // OpenRouter extensions
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub context_length: Option<u32>,
+    #[serde(default)]
+    pub architecture: Option<OpenRouterArchitecture>,
+    #[serde(default)]
+    pub supported_parameters: Option<Vec<String>>,
+    // Shapes differ between OpenAI-compatible servers, so only read for OpenRouter
+    #[serde(default)]
+    pub pricing: Option<serde_json::Value>,
+}
+
+impl OpenAIModel {
+    /// OpenRouter's per-token USD prices, sent as decimal strings. Routers with a variable price report -1
+    pub fn openrouter_price(&self) -> Option<ModelPrice> {
+        let pricing = self.pricing.as_ref()?;
+        let price = |key: &str| pricing[key].as_str()?.parse::<f64>().ok().filter(|price| *price >= 0.0);
+        Some(ModelPrice {
+            input_per_token: price("prompt")?,
+            output_per_token: price("completion")?,
+        })
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct OpenRouterArchitecture {
+    #[serde(default)]
+    pub input_modalities: Vec<String>,
 }
\ No newline at end of file
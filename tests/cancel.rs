@@ -0,0 +1,50 @@
+mod common;
+
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use naori_ai::{CancellationToken, Message, NaoriAI};
+use serde_json::json;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+#[tokio::test]
+async fn cancelling_ends_the_stream_and_closes_the_connection() {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let (closed_tx, closed_rx) = oneshot::channel();
+
+    // Sends the first chunk of a response, then waits for the client to hang up
+    tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        common::read_request(&mut socket).await.unwrap();
+        let line = format!("{}\n", json!({"model": "llama3.2", "created_at": "2025-01-01T00:00:00Z", "message": {"role": "assistant", "content": "Once upon"}, "done": false}));
+        let head = "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let chunk = format!("{:x}\r\n{}\r\n", line.len(), line);
+        socket.write_all(head.as_bytes()).await.unwrap();
+        socket.write_all(chunk.as_bytes()).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        while socket.read(&mut buf).await.is_ok_and(|read| read > 0) {}
+        let _ = closed_tx.send(());
+    });
+
+    let client = NaoriAI::ollama(url, "llama3.2".to_string());
+    let messages = [Message {
+        role: "user".to_string(),
+        content: "Tell me a story".to_string(),
+        ..Default::default()
+    }];
+    let token = CancellationToken::new();
+    let mut stream = client.send_chat_request_cancellable(&messages, token.clone()).await.unwrap();
+
+    let first = stream.next().await.unwrap().unwrap();
+    assert_eq!(first.content, "Once upon");
+
+    token.cancel();
+    assert!(stream.next().await.is_none());
+    drop(stream);
+
+    tokio::time::timeout(Duration::from_secs(5), closed_rx).await.expect("connection still open").unwrap();
+}
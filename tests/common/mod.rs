@@ -0,0 +1,150 @@
+//! A minimal HTTP server standing in for a provider API, recording the requests it receives
+#![allow(dead_code)] // Each test binary uses a different part
+
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub headers: Vec<(String, String)>, // Names lowercased
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_lowercase();
+        self.headers.iter().find(|(key, _)| *key == name).map(|(_, value)| value.as_str())
+    }
+
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_str(&self.body).unwrap()
+    }
+}
+
+/// Canned response for requests with this method and path
+pub struct Route {
+    method: String,
+    path: String,
+    status: u16,
+    body: String,
+}
+
+pub fn route(method: &str, path: &str, status: u16, body: impl Into<String>) -> Route {
+    Route {
+        method: method.to_string(),
+        path: path.to_string(),
+        status,
+        body: body.into(),
+    }
+}
+
+pub struct MockServer {
+    pub url: String,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    /// Serves `routes` on a local port until the test ends; anything else gets a 404
+    pub async fn start(routes: Vec<Route>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let routes = Arc::new(routes);
+
+        let recorded = requests.clone();
+        tokio::spawn(async move {
+            while let Ok((socket, _)) = listener.accept().await {
+                tokio::spawn(respond(socket, recorded.clone(), routes.clone()));
+            }
+        });
+        Self { url, requests }
+    }
+
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// The one recorded request with this method and path
+    pub fn request(&self, method: &str, path: &str) -> Request {
+        let matching: Vec<Request> = self
+            .requests()
+            .into_iter()
+            .filter(|request| request.method == method && request.path == path)
+            .collect();
+        assert_eq!(matching.len(), 1, "expected one {} {} request", method, path);
+        matching.into_iter().next().unwrap()
+    }
+}
+
+// Serves one request per connection, closing it afterwards
+async fn respond(mut socket: TcpStream, recorded: Arc<Mutex<Vec<Request>>>, routes: Arc<Vec<Route>>) {
+    let Some(request) = read_request(&mut socket).await else {
+        return;
+    };
+    let (status, body) = routes
+        .iter()
+        .find(|route| route.method == request.method && route.path == request.path)
+        .map_or((404, r#"{"error":"not found"}"#), |route| (route.status, route.body.as_str()));
+    recorded.lock().unwrap().push(request);
+
+    let response = format!(
+        "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = socket.write_all(response.as_bytes()).await;
+    let _ = socket.shutdown().await;
+}
+
+/// Reads one request off a connection, for tests that answer it themselves
+pub async fn read_request(socket: &mut TcpStream) -> Option<Request> {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    let header_end = loop {
+        if let Some(pos) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break pos;
+        }
+        let read = socket.read(&mut buf).await.ok()?;
+        if read == 0 {
+            return None;
+        }
+        data.extend_from_slice(&buf[..read]);
+    };
+
+    let head = String::from_utf8_lossy(&data[..header_end]).to_string();
+    let mut lines = head.lines();
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?;
+    let path = target.split('?').next().unwrap_or(target).to_string();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_lowercase(), value.trim().to_string()))
+        .collect();
+
+    let content_length = headers
+        .iter()
+        .find(|(name, _)| name == "content-length")
+        .and_then(|(_, value)| value.parse::<usize>().ok())
+        .unwrap_or(0);
+    let mut body = data[header_end + 4..].to_vec();
+    while body.len() < content_length {
+        let read = socket.read(&mut buf).await.ok()?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&buf[..read]);
+    }
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body: String::from_utf8_lossy(&body).to_string(),
+    })
+}
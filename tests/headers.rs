@@ -0,0 +1,87 @@
+mod common;
+
+use naori_ai::{Message, NaoriAI};
+use serde_json::json;
+
+use common::{MockServer, route};
+
+fn hello() -> Vec<Message> {
+    vec![Message {
+        role: "user".to_string(),
+        content: "Hello".to_string(),
+        ..Default::default()
+    }]
+}
+
+fn with_headers(mut client: NaoriAI) -> NaoriAI {
+    client.set_headers([("X-Title", "naori tests"), ("HTTP-Referer", "https://example.com")]).unwrap();
+    client
+}
+
+#[tokio::test]
+async fn ollama_requests_carry_custom_headers() {
+    let chunk = json!({"model": "llama3.2", "created_at": "2025-01-01T00:00:00Z", "message": {"role": "assistant", "content": "Hi"}, "done": true, "done_reason": "stop"});
+    let server = MockServer::start(vec![route("POST", "/api/chat", 200, format!("{}\n", chunk))]).await;
+    let client = with_headers(NaoriAI::ollama(server.url.clone(), "llama3.2".to_string()));
+
+    let result = client.chat(&hello()).await.unwrap();
+
+    assert_eq!(result.content, "Hi");
+    let request = server.request("POST", "/api/chat");
+    assert_eq!(request.header("x-title"), Some("naori tests"));
+    assert_eq!(request.header("http-referer"), Some("https://example.com"));
+}
+
+#[tokio::test]
+async fn openai_requests_carry_custom_headers() {
+    let chunk = json!({
+        "id": "chatcmpl-1",
+        "object": "chat.completion.chunk",
+        "created": 0,
+        "model": "gpt-4o",
+        "choices": [{"index": 0, "delta": {"role": "assistant", "content": "Hi"}, "finish_reason": "stop"}],
+    });
+    let body = format!("data: {}\n\ndata: [DONE]\n\n", chunk);
+    let server = MockServer::start(vec![route("POST", "/v1/chat/completions", 200, body)]).await;
+    let client = with_headers(NaoriAI::openai_custom("sk-test".to_string(), "gpt-4o".to_string(), format!("{}/v1", server.url)));
+
+    let result = client.chat(&hello()).await.unwrap();
+
+    assert_eq!(result.content, "Hi");
+    let request = server.request("POST", "/v1/chat/completions");
+    assert_eq!(request.header("x-title"), Some("naori tests"));
+    assert_eq!(request.header("http-referer"), Some("https://example.com"));
+    assert_eq!(request.header("authorization"), Some("Bearer sk-test"));
+}
+
+#[tokio::test]
+async fn anthropic_requests_carry_custom_headers() {
+    let events = [
+        json!({"type": "message_start", "message": {"id": "msg_1", "type": "message", "role": "assistant", "model": "claude-sonnet-4-5", "content": [], "usage": {"input_tokens": 5, "output_tokens": 0}}}),
+        json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+        json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hi"}}),
+        json!({"type": "content_block_stop", "index": 0}),
+        json!({"type": "message_delta", "delta": {"stop_reason": "end_turn"}, "usage": {"output_tokens": 1}}),
+        json!({"type": "message_stop"}),
+    ];
+    let body: String = events.iter().map(|event| format!("event: {}\ndata: {}\n\n", event["type"].as_str().unwrap(), event)).collect();
+    let server = MockServer::start(vec![route("POST", "/v1/messages", 200, body)]).await;
+    let client = with_headers(NaoriAI::anthropic_custom("sk-ant-test".to_string(), "claude-sonnet-4-5".to_string(), format!("{}/v1", server.url)));
+
+    let result = client.chat(&hello()).await.unwrap();
+
+    assert_eq!(result.content, "Hi");
+    let request = server.request("POST", "/v1/messages");
+    assert_eq!(request.header("x-title"), Some("naori tests"));
+    assert_eq!(request.header("http-referer"), Some("https://example.com"));
+    assert_eq!(request.header("x-api-key"), Some("sk-ant-test"));
+}
+
+#[test]
+fn reserved_headers_are_refused() {
+    let mut client = NaoriAI::openai("sk-test".to_string(), "gpt-4o".to_string());
+
+    assert!(client.set_header("Authorization", "Bearer other").is_err());
+    assert!(client.set_header("Content-Type", "text/plain").is_err());
+    assert!(client.set_header("X-Bad\nName", "value").is_err());
+}
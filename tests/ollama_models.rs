@@ -0,0 +1,76 @@
+mod common;
+
+use naori_ai::AIRequestError;
+use naori_ai::providers::ollama::client::OllamaClient;
+use serde_json::json;
+
+use common::{MockServer, route};
+
+#[tokio::test]
+async fn delete_model_sends_delete_with_the_name() {
+    let server = MockServer::start(vec![route("DELETE", "/api/delete", 200, "")]).await;
+    let client = OllamaClient::new(server.url.clone(), "llama3.2".to_string());
+
+    client.delete_model("llama3.2:1b").await.unwrap();
+
+    let request = server.request("DELETE", "/api/delete");
+    assert_eq!(request.json(), json!({"name": "llama3.2:1b"}));
+}
+
+#[tokio::test]
+async fn delete_missing_model() {
+    let server = MockServer::start(vec![route("DELETE", "/api/delete", 404, r#"{"error":"model 'gone' not found"}"#)]).await;
+    let client = OllamaClient::new(server.url.clone(), "llama3.2".to_string());
+
+    let error = client.delete_model("gone").await.unwrap_err();
+
+    assert!(matches!(error, AIRequestError::ModelNotFound { model } if model == "gone"));
+}
+
+#[tokio::test]
+async fn model_exists_matches_the_exact_tag() {
+    let tags = json!({"models": [
+        {"name": "llama3.2:latest", "modified_at": "2025-01-01T00:00:00Z", "size": 2019393189u64, "digest": "a80c4f17acd5"},
+        {"name": "qwen3:8b", "modified_at": "2025-01-01T00:00:00Z", "size": 5225388164u64, "digest": "500a1f067a9f"},
+    ]});
+    let server = MockServer::start(vec![route("GET", "/api/tags", 200, tags.to_string())]).await;
+    let client = OllamaClient::new(server.url.clone(), "llama3.2".to_string());
+
+    assert!(client.model_exists("llama3.2").await.unwrap());
+    assert!(client.model_exists("llama3.2:latest").await.unwrap());
+    assert!(client.model_exists("qwen3:8b").await.unwrap());
+    assert!(!client.model_exists("qwen3").await.unwrap());
+    assert!(!client.model_exists("llama3.2:1b").await.unwrap());
+}
+
+#[tokio::test]
+async fn copy_model_sends_source_and_destination() {
+    let server = MockServer::start(vec![route("POST", "/api/copy", 200, "")]).await;
+    let client = OllamaClient::new(server.url.clone(), "llama3.2".to_string());
+
+    client.copy_model("llama3.2", "llama3.2-backup").await.unwrap();
+
+    let request = server.request("POST", "/api/copy");
+    assert_eq!(request.json(), json!({"source": "llama3.2", "destination": "llama3.2-backup"}));
+}
+
+#[tokio::test]
+async fn copy_model_refreshes_the_destination_template() {
+    let show = json!({"license": "", "modelfile": "", "parameters": "", "template": "{{ .Prompt }}"});
+    let server = MockServer::start(vec![
+        route("POST", "/api/show", 200, show.to_string()),
+        route("POST", "/api/copy", 200, ""),
+    ])
+    .await;
+    let client = OllamaClient::new(server.url.clone(), "assistant".to_string());
+    let show_requests = || server.requests().iter().filter(|request| request.path == "/api/show").count();
+
+    client.supports_tool_calls().await.unwrap();
+    client.supports_tool_calls().await.unwrap();
+    assert_eq!(show_requests(), 1);
+
+    // Copying onto the client's model replaces it, so its template is fetched again
+    client.copy_model("qwen3", "assistant").await.unwrap();
+    client.supports_tool_calls().await.unwrap();
+    assert_eq!(show_requests(), 2);
+}
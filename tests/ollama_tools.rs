@@ -0,0 +1,78 @@
+mod common;
+
+use naori_ai::providers::ollama::client::OllamaClient;
+use naori_ai::{Message, Tool};
+use serde_json::json;
+
+use common::{MockServer, route};
+
+#[tokio::test]
+async fn tool_results_name_their_calls() {
+    let show = json!({"license": "", "modelfile": "", "parameters": "", "template": "{{ if .Tools }}{{ .Tools }}{{ end }}{{ .Prompt }}"});
+    let chat = json!({
+        "model": "qwen3",
+        "created_at": "2025-01-01T00:00:00Z",
+        "message": {
+            "role": "assistant",
+            "content": "",
+            "tool_calls": [
+                {"id": "call_a", "function": {"name": "get_weather", "arguments": {"city": "Oslo"}}},
+                {"function": {"name": "get_time", "arguments": {}}},
+            ],
+        },
+        "done": true,
+        "done_reason": "stop",
+    });
+    let server = MockServer::start(vec![
+        route("POST", "/api/show", 200, show.to_string()),
+        route("POST", "/api/chat", 200, chat.to_string()),
+    ])
+    .await;
+
+    let mut client = OllamaClient::new(server.url.clone(), "qwen3".to_string());
+    let parameters = json!({"type": "object", "properties": {"city": {"type": "string"}}});
+    client
+        .add_tool(Tool::new("get_weather", "Weather for a city", parameters, |args| Ok(format!("Sunny in {}", args["city"].as_str().unwrap()))))
+        .await
+        .unwrap();
+    client
+        .add_tool(Tool::new("get_time", "Current time", json!({"type": "object", "properties": {}}), |_| Ok("12:00".to_string())))
+        .await
+        .unwrap();
+
+    let mut messages = vec![Message {
+        role: "user".to_string(),
+        content: "Weather and time?".to_string(),
+        ..Default::default()
+    }];
+    let (content, tool_calls) = client.send_chat_request_no_stream(&messages).await.unwrap();
+    let tool_calls = tool_calls.unwrap();
+    assert_eq!(tool_calls.len(), 2);
+
+    messages.push(Message {
+        role: "assistant".to_string(),
+        content,
+        tool_calls: Some(tool_calls.clone()),
+        ..Default::default()
+    });
+    let results = client.handle_tool_calls(tool_calls).await;
+    assert_eq!(results.len(), 2);
+    assert!(results.iter().all(|result| result.role == "tool"));
+    assert_eq!(results[0].tool_name.as_deref(), Some("get_weather"));
+    assert_eq!(results[0].tool_call_id.as_deref(), Some("call_a"));
+    assert_eq!(results[0].content, "Sunny in Oslo");
+    assert_eq!(results[1].tool_name.as_deref(), Some("get_time"));
+    assert_eq!(results[1].tool_call_id, None);
+    assert_eq!(results[1].content, "12:00");
+
+    messages.extend(results);
+    client.send_chat_request_no_stream(&messages).await.unwrap();
+
+    // The follow-up sends the results back as tool messages, tagged the way Ollama matches them
+    let follow_up = server.requests().into_iter().filter(|request| request.path == "/api/chat").nth(1).unwrap().json();
+    let sent = &follow_up["messages"];
+    assert_eq!(sent[1]["tool_calls"][0]["function"]["name"], "get_weather");
+    assert_eq!(sent[2], json!({"role": "tool", "content": "Sunny in Oslo", "tool_name": "get_weather", "tool_call_id": "call_a"}));
+    assert_eq!(sent[3], json!({"role": "tool", "content": "12:00", "tool_name": "get_time"}));
+    assert!(follow_up["tools"].is_array());
+}
@@ -0,0 +1,65 @@
+use naori_ai::{Tool, ToolFunction};
+use naori_ai_macros::tool;
+use serde_json::{Value, json};
+
+/// Weather forecast for a city
+/// @param city City name
+/// @param days Number of days, defaults to one
+#[tool]
+fn forecast(city: String, days: Option<i64>) -> String {
+    format!("{} for {} day(s)", city, days.unwrap_or(1))
+}
+
+/// Add up numbers
+/// @param numbers The numbers to add
+#[tool]
+async fn sum(numbers: Vec<i64>) -> Result<i64, String> {
+    if numbers.is_empty() {
+        return Err("nothing to add".to_string());
+    }
+    Ok(numbers.iter().sum())
+}
+
+async fn call(tool: &Tool, args: Value) -> Result<String, String> {
+    match &tool.function {
+        ToolFunction::Sync(function) => function(args),
+        ToolFunction::Async(function) => function(args).await,
+    }
+}
+
+#[tokio::test]
+async fn missing_required_argument_is_reported() {
+    let tool = forecast_tool();
+
+    assert_eq!(call(&tool, json!({"days": 3})).await, Err("missing required argument 'city'".to_string()));
+    assert_eq!(call(&tool, json!({})).await, Err("missing required argument 'city'".to_string()));
+    assert_eq!(call(&sum_tool(), json!({})).await, Err("missing required argument 'numbers'".to_string()));
+}
+
+#[tokio::test]
+async fn arguments_are_read_from_the_call() {
+    let tool = forecast_tool();
+
+    assert_eq!(call(&tool, json!({"city": "Oslo"})).await, Ok("Oslo for 1 day(s)".to_string()));
+    assert_eq!(call(&tool, json!({"city": "Oslo", "days": null})).await, Ok("Oslo for 1 day(s)".to_string()));
+    assert_eq!(call(&tool, json!({"city": "Oslo", "days": 3})).await, Ok("Oslo for 3 day(s)".to_string()));
+    assert_eq!(
+        call(&tool, json!({"city": 5})).await.unwrap_err(),
+        "invalid argument 'city': expected string (invalid type: integer `5`, expected a string)"
+    );
+
+    let sum = sum_tool();
+    assert_eq!(call(&sum, json!({"numbers": [1, 2, 3]})).await, Ok("6".to_string()));
+    assert_eq!(call(&sum, json!({"numbers": []})).await, Err("nothing to add".to_string()));
+}
+
+#[test]
+fn schema_marks_required_parameters() {
+    let tool = forecast_tool();
+
+    assert_eq!(tool.name, "forecast");
+    assert_eq!(tool.description, "Weather forecast for a city");
+    assert_eq!(tool.parameters["required"], json!(["city"]));
+    assert_eq!(tool.parameters["properties"]["days"], json!({"type": "integer", "description": "Number of days, defaults to one"}));
+    assert_eq!(sum_tool().parameters["properties"]["numbers"]["items"], json!({"type": "integer"}));
+}